@@ -1,30 +1,41 @@
 //! Runs embedding on messages when flush is triggered.
-//! Thin wrapper around `embedding::Engine`.
+//! Thin wrapper around `embedding::CachedEmbedder`, so a message retrieval
+//! already embedded isn't embedded again. Entries are embedded
+//! one at a time, and blank messages (a reply stopped before its first
+//! token) get no record: the engine rejects them, and they would take the
+//! rest of the flush down with them.
 
-use crate::message::CacheEntry;
-use embedding::Engine;
+use super::message::{CacheEntry, ChatMessage};
+use crate::embedding::{self, CachedEmbedder};
+use crate::records::MemoryRecord;
 
-pub fn embed_batch(engine: &Engine, batch: Vec<CacheEntry>) -> Vec<memory::MemoryRecord> {
+pub fn embed_batch(
+    embedder: &CachedEmbedder,
+    batch: &[CacheEntry],
+) -> embedding::Result<Vec<MemoryRecord>> {
     let mut records = Vec::with_capacity(batch.len() * 2);
 
     for entry in batch {
-        let input_vec = engine.embed(&entry.input.content);
-        let output_vec = engine.embed(&entry.output.content);
+        let messages: Vec<&ChatMessage> = [&entry.input, &entry.output]
+            .into_iter()
+            .filter(|message| !message.content.trim().is_empty())
+            .collect();
+        if messages.is_empty() {
+            continue;
+        }
+        let texts: Vec<String> = messages.iter().map(|message| message.content.clone()).collect();
+        let vectors = embedder.embed_passages(&texts)?;
 
-        let now_secs = (entry.output.timestamp / 1000) as u64;
+        let now_secs = entry.output.timestamp / 1000;
 
-        records.push(memory::MemoryRecord {
-            vector: input_vec,
-            timestamp: now_secs,
-            payload: serde_json::to_vec(&entry.input).unwrap(),
-        });
-
-        records.push(memory::MemoryRecord {
-            vector: output_vec,
-            timestamp: now_secs,
-            payload: serde_json::to_vec(&entry.output).unwrap(),
-        });
+        for (message, embedded) in messages.into_iter().zip(vectors) {
+            records.push(MemoryRecord {
+                vector: embedded.vector,
+                timestamp: now_secs,
+                payload: serde_json::to_vec(message).unwrap(),
+            });
+        }
     }
 
-    records
-}
\ No newline at end of file
+    Ok(records)
+}
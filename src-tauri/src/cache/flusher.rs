@@ -1,41 +1,303 @@
 //! Handles triggering + passing embedded records to memory.
-//! Spawns a background thread that:
-//!   1. waits 1 s after receiving a batch,
+//! Spawns a tokio task that, per batch:
+//!   1. waits 1 s, merging whatever else was flushed meanwhile,
 //!   2. embeds via `embedder`,
-//!   3. appends to the global `memory::Store`.
+//!   3. appends to the long-term `records::MemoryStore`,
+//!   4. acknowledges the batch in the write-ahead log, if there is one.
+//!
+//! Entries arrive already redacted for the memory sink by `Cache::push`.
+//!
+//! Batches travel over a bounded channel; when it is full `Cache` keeps the
+//! entries buffered instead of queueing more. Embedding and disk work run on
+//! the blocking pool through a `MemoryWriter`, each step retried with
+//! exponential backoff. A batch that still fails is reported back to `Cache`
+//! (see `Cache::take_errors`) and the task carries on, so one bad disk write
+//! no longer ends memory persistence for the session.
 
-use crate::embedder::embed_batch;
-use crate::message::CacheEntry;
-use std::sync::mpsc::{channel, Sender};
-use std::thread;
+use super::embedder::embed_batch;
+use super::message::CacheEntry;
+use super::wal::Wal;
+use crate::preprocessing::{Redactor, Sink};
+use crate::records::{MemoryRecord, MemoryStore};
+use crate::registry::ActiveModels;
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::task::JoinHandle;
 
-#[derive(Clone)]
-pub struct FlusherHandle(Sender<Vec<CacheEntry>>);
+/// Batches that may wait for the flusher before `Cache` has to hold on to them.
+pub const CHANNEL_CAPACITY: usize = 8;
+
+/// Tries per step (loading, embedding, writing) before a batch is given up.
+pub const MAX_ATTEMPTS: u32 = 4;
+
+const FIRST_BACKOFF: Duration = Duration::from_millis(250);
+const SETTLE_DELAY: Duration = Duration::from_secs(1);
+const STORE_PATH: &str = "memory.sqlite3";
+
+/// Errors kept for `Cache::take_errors`; the oldest go first.
+const MAX_REPORTED: usize = 32;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum FlushError {
+    #[error("Loading the embedding model failed: {0}")]
+    Engine(String),
+    #[error("Embedding a batch of {entries} entries failed: {message}")]
+    Embedding { entries: usize, message: String },
+    #[error("Writing to the memory store failed: {0}")]
+    Store(String),
+    #[error("The flusher has shut down; {0} entries were not persisted")]
+    Closed(usize),
+    #[error("Updating the write-ahead log failed: {0}")]
+    Log(String),
+}
+
+#[derive(Debug, Default)]
+struct Report {
+    errors: VecDeque<FlushError>,
+    persisted: u64,
+}
+
+impl Report {
+    fn error(&mut self, error: FlushError) {
+        if self.errors.len() == MAX_REPORTED {
+            self.errors.pop_front();
+        }
+        self.errors.push_back(error);
+    }
+}
+
+pub struct FlusherHandle {
+    tx: Option<mpsc::Sender<Vec<CacheEntry>>>,
+    task: Option<JoinHandle<()>>,
+    closing: Arc<AtomicBool>,
+    report: Arc<Mutex<Report>>,
+}
 
 impl FlusherHandle {
-    fn send(&self, batch: Vec<CacheEntry>) {
-        let _ = self.0.send(batch);
+    /// Queues `batch` without waiting; hands it back when the channel is
+    /// full or closed.
+    pub fn try_send(&self, batch: Vec<CacheEntry>) -> Result<(), TrySendError<Vec<CacheEntry>>> {
+        match &self.tx {
+            Some(tx) => tx.try_send(batch),
+            None => Err(TrySendError::Closed(batch)),
+        }
+    }
+
+    pub fn report(&self, error: FlushError) {
+        self.report.lock().unwrap().error(error);
+    }
+
+    /// Errors since the last call, oldest first.
+    pub fn take_errors(&self) -> Vec<FlushError> {
+        self.report.lock().unwrap().errors.drain(..).collect()
+    }
+
+    /// Entries written to the store so far.
+    pub fn persisted(&self) -> u64 {
+        self.report.lock().unwrap().persisted
+    }
+
+    /// Stops accepting batches. The returned `Closer` finishes the job; it
+    /// is split off so no lock is held across the wait.
+    pub fn close(&mut self) -> Closer {
+        self.closing.store(true, Ordering::Release);
+        Closer {
+            tx: self.tx.take(),
+            task: self.task.take(),
+        }
     }
 }
 
-pub fn spawn() -> (thread::JoinHandle<()>, FlusherHandle) {
-    let (tx, rx) = channel::<Vec<CacheEntry>>();
-    let handle = thread::spawn(move || {
-        let engine = embedding::Engine::new(); // cheap clone
-        let mut store = memory::Store::open("memory.vec").expect("open store");
+pub struct Closer {
+    tx: Option<mpsc::Sender<Vec<CacheEntry>>>,
+    task: Option<JoinHandle<()>>,
+}
 
-        while let Ok(batch) = rx.recv() {
-            // 1. wait 1 s
-            thread::sleep(std::time::Duration::from_secs(1));
-            // 2. embed
-            let records = embed_batch(&engine, batch);
-            // 3. append
-            for rec in records {
-                store.append(&rec).expect("append");
+impl Closer {
+    /// Queues `last`, waiting for room if need be, then waits until the task
+    /// has written everything queued and exited.
+    pub async fn drain(self, last: Vec<CacheEntry>) {
+        if let Some(tx) = self.tx {
+            if !last.is_empty() {
+                let _ = tx.send(last).await;
             }
-            store.flush().expect("flush");
         }
-    });
+        if let Some(task) = self.task {
+            let _ = task.await;
+        }
+    }
+}
 
-    (handle, FlusherHandle(tx))
-}
\ No newline at end of file
+/// Must be called from within a tokio runtime. Batches go to `writer`;
+/// written ones are acknowledged in `wal`, failed ones stay there for the
+/// next start. `redactor` scrubs the errors the task logs.
+pub fn spawn(redactor: Redactor, wal: Option<Arc<Mutex<Wal>>>, writer: Box<dyn MemoryWriter>) -> FlusherHandle {
+    let (tx, rx) = mpsc::channel(CHANNEL_CAPACITY);
+    let closing = Arc::new(AtomicBool::new(false));
+    let report = Arc::new(Mutex::new(Report::default()));
+    let task = tokio::spawn(run(rx, redactor, wal, writer, closing.clone(), report.clone()));
+    FlusherHandle {
+        tx: Some(tx),
+        task: Some(task),
+        closing,
+        report,
+    }
+}
+
+async fn run(
+    mut rx: mpsc::Receiver<Vec<CacheEntry>>,
+    redactor: Redactor,
+    wal: Option<Arc<Mutex<Wal>>>,
+    writer: Box<dyn MemoryWriter>,
+    closing: Arc<AtomicBool>,
+    report: Arc<Mutex<Report>>,
+) {
+    let writer = Arc::new(Mutex::new(writer));
+    while let Some(mut batch) = rx.recv().await {
+        // 1. wait 1 s, unless the app is on its way out
+        if !closing.load(Ordering::Acquire) {
+            tokio::time::sleep(SETTLE_DELAY).await;
+        }
+        while let Ok(more) = rx.try_recv() {
+            batch.extend(more);
+        }
+        let seqs: Vec<u64> = batch.iter().map(|entry| entry.seq).filter(|&seq| seq > 0).collect();
+        // 2 + 3 + 4. embed, append and acknowledge, off the async threads
+        let writer = writer.clone();
+        let wal = wal.clone();
+        let (outcome, acknowledged) = tokio::task::spawn_blocking(move || {
+            let outcome = write(&mut **writer.lock().unwrap(), &batch);
+            // Only what reached the store may leave the log.
+            let acknowledged = match (&outcome, wal) {
+                (Ok(_), Some(wal)) => wal.lock().unwrap().acknowledge(&seqs).map_err(|e| FlushError::Log(e.to_string())),
+                _ => Ok(()),
+            };
+            (outcome, acknowledged)
+        })
+        .await
+        .unwrap_or_else(|e| (Err(FlushError::Store(e.to_string())), Ok(())));
+
+        let mut report = report.lock().unwrap();
+        match outcome {
+            Ok(entries) => report.persisted += entries as u64,
+            Err(error) => {
+                // Errors can quote the input they choked on.
+                eprintln!("Dropping cache batch: {}", redactor.scrub(Sink::Logs, &error.to_string()));
+                report.error(error);
+            }
+        }
+        if let Err(error) = acknowledged {
+            // The batch is stored; it will be stored again on replay.
+            report.error(error);
+        }
+    }
+}
+
+/// Turns batches into long-term memory. Both steps run on the blocking
+/// pool and are retried by the flusher, so a step that fails is simply
+/// called again.
+pub trait MemoryWriter: Send + 'static {
+    /// Records to store for `batch`, typically one per message.
+    fn embed(&mut self, batch: &[CacheEntry]) -> Result<Vec<MemoryRecord>, FlushError>;
+
+    /// Appends `records` in order, counting each one in `written` as it
+    /// lands, so a retry resumes after the last.
+    fn append(&mut self, records: &[MemoryRecord], written: &mut usize) -> Result<(), FlushError>;
+
+    /// Pause before the first retry of a step; it doubles after each one.
+    fn backoff(&self) -> Duration {
+        FIRST_BACKOFF
+    }
+}
+
+/// Number of entries written.
+fn write(writer: &mut dyn MemoryWriter, batch: &[CacheEntry]) -> Result<usize, FlushError> {
+    let backoff = writer.backoff();
+    let records = retry(backoff, || writer.embed(batch))?;
+
+    // Resume where a failed attempt stopped, so nothing is written twice.
+    let mut written = 0;
+    retry(backoff, || writer.append(&records[written..], &mut written))?;
+    Ok(batch.len())
+}
+
+/// Embeds with the active embedding model, through the shared embedding
+/// cache, into a `records::MemoryStore`. The engine is looked up for every
+/// batch, so a model swap applies from
+/// the next flush on; the store is opened on first use and reopened after a
+/// failure.
+pub struct StoreWriter {
+    models: Arc<ActiveModels>,
+    path: PathBuf,
+    store: Option<MemoryStore>,
+}
+
+impl Default for StoreWriter {
+    /// A selection of its own, which only ever holds the preferred model,
+    /// and a store in the working directory.
+    fn default() -> Self {
+        Self::new(Arc::default(), STORE_PATH)
+    }
+}
+
+impl StoreWriter {
+    /// Embeds with whatever `models` has active into the store at `path`.
+    pub fn new(models: Arc<ActiveModels>, path: impl Into<PathBuf>) -> Self {
+        Self { models, path: path.into(), store: None }
+    }
+
+    fn store(&mut self) -> Result<&mut MemoryStore, FlushError> {
+        if self.store.is_none() {
+            let store = MemoryStore::open(&self.path).map_err(|e| FlushError::Store(e.to_string()))?;
+            self.store = Some(store);
+        }
+        Ok(self.store.as_mut().unwrap())
+    }
+}
+
+impl MemoryWriter for StoreWriter {
+    fn embed(&mut self, batch: &[CacheEntry]) -> Result<Vec<MemoryRecord>, FlushError> {
+        let embedder = self.models.embedder().map_err(|e| FlushError::Engine(e.to_string()))?;
+        embed_batch(&embedder, batch).map_err(|e| FlushError::Embedding {
+            entries: batch.len(),
+            message: e.to_string(),
+        })
+    }
+
+    fn append(&mut self, records: &[MemoryRecord], written: &mut usize) -> Result<(), FlushError> {
+        let result = self.store().and_then(|store| {
+            for record in records {
+                store.append(record).map_err(|e| FlushError::Store(e.to_string()))?;
+                *written += 1;
+            }
+            Ok(())
+        });
+        if result.is_err() {
+            self.store = None;
+        }
+        result
+    }
+}
+
+/// Runs `op` up to `MAX_ATTEMPTS` times, pausing `backoff` after the first
+/// failure and doubling it each time. Only ever called on the blocking
+/// pool, so sleeping here is fine.
+fn retry<T>(mut backoff: Duration, mut op: impl FnMut() -> Result<T, FlushError>) -> Result<T, FlushError> {
+    let mut attempt = 1;
+    loop {
+        match op() {
+            Ok(value) => return Ok(value),
+            Err(e) if attempt == MAX_ATTEMPTS => return Err(e),
+            Err(_) => {
+                std::thread::sleep(backoff);
+                backoff *= 2;
+                attempt += 1;
+            }
+        }
+    }
+}
@@ -1,8 +1,15 @@
 //! High-level cache logic: push, flush check, reset.
 
-use crate::message::{CacheEntry, ChatMessage};
-use crate::flusher::FlusherHandle;
+use super::message::{CacheEntry, ChatMessage};
+use super::flusher::{FlushError, FlusherHandle, MemoryWriter, StoreWriter};
+use crate::preprocessing::{Redactor, Sink};
+use super::score::Scorer;
+use super::wal::Wal;
 use std::collections::VecDeque;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::error::TrySendError;
 use std::time::{Duration, Instant};
 
 /// Public handle to the cache.
@@ -17,11 +24,66 @@ struct CacheInner {
     ttl: Duration,             // max time before flush
     last_flush: Instant,
     flusher: FlusherHandle,
+    /// Applied with the memory settings before an entry is logged or buffered.
+    redactor: Redactor,
+    wal: Option<Arc<Mutex<Wal>>>,
+    scorer: Scorer,
+    /// Scored and kept, waiting for room in the flusher's channel.
+    selected: Vec<CacheEntry>,
 }
 
 impl Cache {
     pub fn new(capacity: usize, ttl_secs: u64) -> Self {
-        let (flusher, handle) = crate::flusher::spawn();
+        Self::with_redactor(capacity, ttl_secs, Redactor::default())
+    }
+
+    /// Like `new`, redacting pushed messages with `redactor`'s memory settings.
+    /// Both spawn the flusher task, so they must run inside a tokio runtime.
+    pub fn with_redactor(capacity: usize, ttl_secs: u64, redactor: Redactor) -> Self {
+        Self::with_writer(capacity, ttl_secs, redactor, StoreWriter::default())
+    }
+
+    /// Like `with_redactor`, with flushed batches going to `writer` instead
+    /// of the default embedding model and memory store.
+    pub fn with_writer(capacity: usize, ttl_secs: u64, redactor: Redactor, writer: impl MemoryWriter) -> Self {
+        Self::build(capacity, ttl_secs, redactor, None, Box::new(writer))
+    }
+
+    /// Like `with_writer`, but every push is written to the write-ahead log
+    /// at `wal_path` before it is buffered. Entries a previous run left there
+    /// are handed to the flusher straight away. Secrets are redacted before
+    /// anything reaches the log. The scorer's novelty window is kept next to
+    /// the log, with a `.terms` extension.
+    pub fn open(
+        wal_path: impl AsRef<Path>,
+        capacity: usize,
+        ttl_secs: u64,
+        redactor: Redactor,
+        writer: impl MemoryWriter,
+    ) -> io::Result<Self> {
+        let (wal, pending) = Wal::open(&wal_path)?;
+        let scorer = Scorer::open(wal_path.as_ref().with_extension("terms"))?;
+        let wal = Some(Arc::new(Mutex::new(wal)));
+        let cache = Self::build(capacity, ttl_secs, redactor, wal, Box::new(writer));
+        {
+            let mut inner = cache.inner.lock().unwrap();
+            inner.scorer = scorer;
+            // Logs written before pushes were redacted may still hold secrets.
+            let pending: Vec<_> = pending.into_iter().map(|entry| redact(&inner.redactor, entry)).collect();
+            inner.buffer.extend(pending);
+            inner.do_flush();
+        }
+        Ok(cache)
+    }
+
+    fn build(
+        capacity: usize,
+        ttl_secs: u64,
+        redactor: Redactor,
+        wal: Option<Arc<Mutex<Wal>>>,
+        writer: Box<dyn MemoryWriter>,
+    ) -> Self {
+        let handle = super::flusher::spawn(redactor.clone(), wal.clone(), writer);
         Self {
             inner: std::sync::Arc::new(std::sync::Mutex::new(CacheInner {
                 buffer: VecDeque::new(),
@@ -29,36 +91,111 @@ impl Cache {
                 ttl: Duration::from_secs(ttl_secs),
                 last_flush: Instant::now(),
                 flusher: handle,
+                redactor,
+                wal,
+                scorer: Scorer::default(),
+                selected: Vec::new(),
             })),
         }
     }
 
-    /// Push a new (input, output) pair into the buffer.
+    /// Replaces the importance policy deciding what reaches memory; see
+    /// `Scorer::keep_all` to embed every exchange.
+    pub fn with_scorer(self, scorer: Scorer) -> Self {
+        self.inner.lock().unwrap().scorer = scorer;
+        self
+    }
+
+    /// Push a new (input, output) pair into the buffer, logging it first
+    /// when the cache has a write-ahead log. Both messages are redacted for
+    /// memory up front, so neither the log nor the vectors carry secrets.
     pub fn push(&self, input: ChatMessage, output: ChatMessage) {
         let mut inner = self.inner.lock().unwrap();
-        inner.buffer.push_back(CacheEntry { input, output });
+        let mut entry = redact(&inner.redactor, CacheEntry { seq: 0, input, output });
+        if let Some(wal) = &inner.wal {
+            if let Err(e) = wal.lock().unwrap().append(&mut entry) {
+                // Still buffered, just not crash-safe.
+                inner.flusher.report(FlushError::Log(e.to_string()));
+            }
+        }
+        inner.buffer.push_back(entry);
         inner.maybe_flush();
     }
 
-    /// Manual flush (e.g. shutdown).
+    /// Manual flush, without waiting for the batch to be written.
     pub fn flush(&self) {
         let mut inner = self.inner.lock().unwrap();
         inner.do_flush();
     }
+
+    /// Hands over everything still buffered and waits until the flusher has
+    /// written it all and stopped. Later pushes are reported, not persisted;
+    /// with a write-ahead log they are replayed on the next start.
+    pub async fn shutdown(&self) {
+        let (batch, closer) = {
+            let mut inner = self.inner.lock().unwrap();
+            let batch = inner.take_batch();
+            (batch, inner.flusher.close())
+        };
+        closer.drain(batch).await;
+    }
+
+    /// Batches the flusher gave up on since the last call, oldest first.
+    pub fn take_errors(&self) -> Vec<FlushError> {
+        self.inner.lock().unwrap().flusher.take_errors()
+    }
+
+    /// Entries written to memory so far.
+    pub fn persisted(&self) -> u64 {
+        self.inner.lock().unwrap().flusher.persisted()
+    }
 }
 
 impl CacheInner {
     fn maybe_flush(&mut self) {
-        if self.buffer.len() >= self.capacity || self.last_flush.elapsed() >= self.ttl {
+        if self.buffer.len() >= self.capacity
+            || self.last_flush.elapsed() >= self.ttl
+            || !self.selected.is_empty()
+        {
             self.do_flush();
         }
     }
 
     fn do_flush(&mut self) {
-        let batch: Vec<_> = self.buffer.drain(..).collect();
-        if !batch.is_empty() {
-            self.flusher.send(batch);
-            self.last_flush = Instant::now();
+        let batch = self.take_batch();
+        if batch.is_empty() {
+            return;
+        }
+        match self.flusher.try_send(batch) {
+            Ok(()) => self.last_flush = Instant::now(),
+            // Backpressure: the flusher is behind, so keep the entries
+            // and offer them again on the next push.
+            Err(TrySendError::Full(batch)) => self.selected = batch,
+            Err(TrySendError::Closed(batch)) => self.flusher.report(FlushError::Closed(batch.len())),
         }
     }
-}
\ No newline at end of file
+
+    /// Empties the buffer into the entries worth embedding, after any still
+    /// waiting from a full channel. Dropped entries leave the log right away,
+    /// or they would come back on every start.
+    fn take_batch(&mut self) -> Vec<CacheEntry> {
+        let buffered: Vec<_> = self.buffer.drain(..).collect();
+        let (kept, dropped) = self.scorer.select(buffered);
+        let mut batch = std::mem::take(&mut self.selected);
+        batch.extend(kept);
+
+        if let Some(wal) = &self.wal {
+            let seqs: Vec<u64> = dropped.iter().map(|entry| entry.seq).filter(|&seq| seq > 0).collect();
+            if let Err(e) = wal.lock().unwrap().acknowledge(&seqs) {
+                self.flusher.report(FlushError::Log(e.to_string()));
+            }
+        }
+        batch
+    }
+}
+
+fn redact(redactor: &Redactor, mut entry: CacheEntry) -> CacheEntry {
+    entry.input.content = redactor.scrub(Sink::Memory, &entry.input.content);
+    entry.output.content = redactor.scrub(Sink::Memory, &entry.output.content);
+    entry
+}
@@ -1,8 +1,9 @@
 //! Chat message structs, with optional metadata.
 
+use serde::{Deserialize, Serialize};
 use std::time::{SystemTime, UNIX_EPOCH};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChatMessage {
     pub role: String,       // "user" | "assistant"
     pub content: String,
@@ -24,8 +25,11 @@ impl ChatMessage {
 }
 
 /// Internal representation of an (input, output) pair.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CacheEntry {
+    /// Position in the write-ahead log; 0 when the entry was never logged.
+    #[serde(default)]
+    pub seq: u64,
     pub input: ChatMessage,
     pub output: ChatMessage,
 }
\ No newline at end of file
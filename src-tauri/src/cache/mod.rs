@@ -1,12 +1,21 @@
 //! In-memory staging area for chat messages.
 //! Buffers (input, output) pairs, embeds them 1 s after display,
-//! then flushes to memory in configurable batches.
+//! then flushes to memory in configurable batches. Only exchanges the
+//! `score::Scorer` rates important are embedded; the rest are
+//! downsampled. `Cache::shutdown` drains whatever is still pending before
+//! the app exits, and `Cache::open` keeps a write-ahead log so a crash
+//! doesn't lose it either.
 
 pub mod embedder;
 pub mod flusher;
 pub mod manager;
 pub mod message;
-pub mod scorer;
+pub mod score;
+pub mod wal;
+
+pub use flusher::{FlushError, MemoryWriter, StoreWriter};
+pub use manager::Cache;
+pub use score::Scorer;
 
 #[cfg(test)]
 mod tests;
\ No newline at end of file
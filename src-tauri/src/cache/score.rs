@@ -1,6 +1,295 @@
-//! Optional: relevance or priority scoring (for smart flush).
-//! Currently unused — stub for future LRU / importance signals.
+//! Importance scoring for the smart flush.
+//! Decides which exchanges are worth embedding into long-term memory, so
+//! greetings and thanks don't crowd out what the learner actually asked.
+//!
+//! An entry scores on four signals, between 0 and 1:
+//!   - length: longer exchanges tend to carry more,
+//!   - novelty: content words memory hasn't seen in recent entries,
+//!   - corrections ("actually…", "that's wrong") and code,
+//!   - an explicit "remember this", which always scores 1.
+//!
+//! Novelty is lexical and only looks at what this cache sent to memory,
+//! which is cheap enough to run under the cache lock on every push. The
+//! window of recent terms is saved to disk (see `Scorer::open`), so after a
+//! restart what memory already holds isn't novel all over again. Word lists
+//! are English; other languages lean on length and novelty alone.
 
-pub fn score(_entry: &crate::message::CacheEntry) -> f32 {
-    1.0
-}
\ No newline at end of file
+use super::message::CacheEntry;
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Entries scoring below this are downsampled.
+pub const DEFAULT_THRESHOLD: f32 = 0.3;
+
+/// One in this many low-scoring entries is still kept, so memory keeps a
+/// thin trace of the small talk too.
+pub const DEFAULT_SAMPLE_EVERY: usize = 5;
+
+/// Kept entries whose words count as already seen.
+const RECENT_ENTRIES: usize = 256;
+
+const LENGTH_WEIGHT: f32 = 0.25;
+const NOVELTY_WEIGHT: f32 = 0.45;
+const CORRECTION_WEIGHT: f32 = 0.35;
+const CODE_WEIGHT: f32 = 0.35;
+
+/// Words (input and output together) at which length stops adding.
+const FULL_LENGTH_WORDS: usize = 80;
+/// New content words at which novelty stops adding.
+const FULL_NOVELTY_TERMS: usize = 6;
+
+const REMEMBER_MARKERS: &[&str] = &[
+    "remember this",
+    "remember that",
+    "please remember",
+    "remember:",
+    "don't forget",
+    "do not forget",
+    "keep in mind",
+    "for future reference",
+    "from now on",
+];
+
+const CORRECTION_MARKERS: &[&str] = &[
+    "that's wrong",
+    "that is wrong",
+    "that's not right",
+    "that is not right",
+    "that's incorrect",
+    "that is incorrect",
+    "you're wrong",
+    "you are wrong",
+    "not quite",
+    "i meant",
+    "correction:",
+];
+
+/// Only count at the start of the message: "no" and "actually" are common
+/// mid-sentence.
+const CORRECTION_OPENERS: &[&str] = &["no,", "no.", "nope", "actually", "wrong"];
+
+const CODE_OPENERS: &[&str] = &[
+    "fn ", "pub fn ", "def ", "class ", "import ", "#include", "function ", "impl ",
+];
+
+/// Function words and small talk; they never make an entry novel.
+const STOPWORDS: &[&str] = &[
+    "the", "and", "for", "are", "but", "not", "you", "your", "yours", "all", "any", "can", "had",
+    "her", "was", "one", "our", "out", "has", "him", "his", "how", "its", "may", "now", "she",
+    "too", "who", "why", "what", "when", "where", "which", "will", "with", "would", "could",
+    "should", "this", "that", "these", "those", "there", "their", "them", "they", "then", "than",
+    "from", "into", "about", "have", "been", "being", "were", "does", "did", "doing", "done",
+    "just", "also", "some", "more", "most", "very", "much", "many", "such", "only", "own",
+    "same", "other", "here", "after", "before", "again", "each", "few", "both", "over", "under",
+    "while", "because", "let", "get", "got", "yes", "yeah", "okay", "sure", "thanks", "thank",
+    "please", "welcome", "hello", "hey", "great", "good", "nice", "cool", "fine", "like", "know",
+    "need", "want", "help", "anything", "something", "else", "glad", "happy", "today", "day",
+    "bye", "goodbye", "see", "later", "well", "really", "lot", "feel", "free", "ask", "question",
+    "questions", "going", "make", "think",
+];
+
+/// What an entry is scored on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Signals {
+    pub words: usize,
+    /// Content words not among those of recently kept entries.
+    pub novel_terms: usize,
+    pub correction: bool,
+    pub code: bool,
+    pub remember: bool,
+}
+
+pub fn score(signals: &Signals) -> f32 {
+    if signals.remember {
+        return 1.0;
+    }
+    let length = (signals.words as f32 / FULL_LENGTH_WORDS as f32).min(1.0);
+    let novelty = (signals.novel_terms as f32 / FULL_NOVELTY_TERMS as f32).min(1.0);
+    let mut total = LENGTH_WEIGHT * length + NOVELTY_WEIGHT * novelty;
+    if signals.correction {
+        total += CORRECTION_WEIGHT;
+    }
+    if signals.code {
+        total += CODE_WEIGHT;
+    }
+    total.min(1.0)
+}
+
+/// Scores entries and picks the ones worth embedding. Stateful: it
+/// remembers what it let through, for novelty, and how many low scorers it
+/// has passed over, for downsampling.
+#[derive(Debug, Clone)]
+pub struct Scorer {
+    threshold: f32,
+    /// 0 drops every low scorer.
+    sample_every: usize,
+    below: usize,
+    recent: VecDeque<Vec<String>>,
+    seen: HashMap<String, usize>,
+    /// Where `recent` is saved after each selection that kept something.
+    path: Option<PathBuf>,
+}
+
+impl Default for Scorer {
+    fn default() -> Self {
+        Self::new(DEFAULT_THRESHOLD, DEFAULT_SAMPLE_EVERY)
+    }
+}
+
+impl Scorer {
+    pub fn new(threshold: f32, sample_every: usize) -> Self {
+        Self {
+            threshold,
+            sample_every,
+            below: 0,
+            recent: VecDeque::new(),
+            seen: HashMap::new(),
+            path: None,
+        }
+    }
+
+    /// Default thresholds, with the novelty window read from `path` and
+    /// written back there whenever it changes. A missing file starts an
+    /// empty window. The file holds one line of terms per kept entry,
+    /// oldest first.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut scorer = Self::default();
+        match fs::read_to_string(&path) {
+            Ok(text) => {
+                for line in text.lines() {
+                    scorer.remember(line.split_whitespace().map(str::to_string).collect());
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        scorer.path = Some(path);
+        Ok(scorer)
+    }
+
+    /// Lets every entry through, as before the smart flush.
+    pub fn keep_all() -> Self {
+        Self::new(0.0, 1)
+    }
+
+    pub fn signals(&self, entry: &CacheEntry) -> Signals {
+        let input = normalize(&entry.input.content);
+        let output = &entry.output.content;
+        let start = input.trim_start();
+        Signals {
+            words: input.split_whitespace().count() + output.split_whitespace().count(),
+            novel_terms: entry_terms(entry).iter().filter(|t| !self.seen.contains_key(*t)).count(),
+            correction: CORRECTION_OPENERS.iter().any(|m| start.starts_with(m))
+                || CORRECTION_MARKERS.iter().any(|m| input.contains(m)),
+            code: has_code(&entry.input.content) || has_code(output),
+            remember: REMEMBER_MARKERS.iter().any(|m| input.contains(m)),
+        }
+    }
+
+    pub fn score(&self, entry: &CacheEntry) -> f32 {
+        score(&self.signals(entry))
+    }
+
+    /// Splits `batch` into the entries to embed and the ones to drop, both in
+    /// their original order. Entries are scored one by one, so words an
+    /// earlier entry of the batch brought in are no longer novel.
+    pub fn select(&mut self, batch: Vec<CacheEntry>) -> (Vec<CacheEntry>, Vec<CacheEntry>) {
+        let mut kept = Vec::new();
+        let mut dropped = Vec::new();
+        for entry in batch {
+            if self.score(&entry) >= self.threshold || self.sample() {
+                self.remember(entry_terms(&entry));
+                kept.push(entry);
+            } else {
+                dropped.push(entry);
+            }
+        }
+        if !kept.is_empty() {
+            self.save();
+        }
+        (kept, dropped)
+    }
+
+    /// Counts a low scorer; true for every `sample_every`th.
+    fn sample(&mut self) -> bool {
+        if self.sample_every == 0 {
+            return false;
+        }
+        self.below += 1;
+        if self.below == self.sample_every {
+            self.below = 0;
+            return true;
+        }
+        false
+    }
+
+    fn remember(&mut self, terms: Vec<String>) {
+        for term in &terms {
+            *self.seen.entry(term.clone()).or_default() += 1;
+        }
+        self.recent.push_back(terms);
+        if self.recent.len() > RECENT_ENTRIES {
+            for term in self.recent.pop_front().unwrap_or_default() {
+                if let Some(count) = self.seen.get_mut(&term) {
+                    *count -= 1;
+                    if *count == 0 {
+                        self.seen.remove(&term);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Best-effort: losing the window only makes the first entries after
+    /// the next start look novel.
+    fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let text: String = self.recent.iter().map(|terms| terms.join(" ") + "\n").collect();
+        let tmp = path.with_extension("terms.tmp");
+        let _ = fs::write(&tmp, text).and_then(|()| fs::rename(&tmp, path));
+    }
+}
+
+/// Lowercase, with typographic apostrophes straightened.
+fn normalize(text: &str) -> String {
+    text.to_lowercase().replace('\u{2019}', "'")
+}
+
+/// Distinct content words of both messages, sorted.
+fn entry_terms(entry: &CacheEntry) -> Vec<String> {
+    let mut terms = content_words(&entry.input.content);
+    terms.extend(content_words(&entry.output.content));
+    terms.sort_unstable();
+    terms.dedup();
+    terms
+}
+
+fn content_words(text: &str) -> Vec<String> {
+    normalize(text)
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() >= 3 && !STOPWORDS.contains(word))
+        .map(str::to_string)
+        .collect()
+}
+
+/// A fenced block, or at least two lines that read like source.
+fn has_code(text: &str) -> bool {
+    if text.contains("```") {
+        return true;
+    }
+    let code_lines = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| {
+            line.ends_with([';', '{'])
+                || *line == "}"
+                || CODE_OPENERS.iter().any(|opener| line.starts_with(opener))
+        })
+        .count();
+    code_lines >= 2
+}
@@ -2,11 +2,93 @@
 
 #[cfg(test)]
 mod tests {
-    use super::*;
+    use crate::cache::flusher::{FlushError, MemoryWriter, MAX_ATTEMPTS};
+    use crate::cache::message::{CacheEntry, ChatMessage};
+    use crate::cache::score::{self, Scorer};
+    use crate::cache::wal::Wal;
+    use crate::cache::Cache;
+    use crate::preprocessing::Redactor;
+    use crate::records::MemoryRecord;
+    use std::sync::{Arc, Mutex};
     use std::thread;
+    use std::time::{Duration, Instant};
 
-    #[test]
-    fn cache_hits_capacity_flush() {
+    const TEST_BACKOFF: Duration = Duration::from_millis(10);
+
+    /// Stands in for the embedding model and memory store: each step fails
+    /// as often as it is told to, and every call is recorded.
+    #[derive(Clone, Default)]
+    struct FakeWriter(Arc<Mutex<Calls>>);
+
+    #[derive(Default)]
+    struct Calls {
+        embed_failures: u32,
+        /// Each failing append stores one record first.
+        append_failures: u32,
+        embeds: Vec<Instant>,
+        stored: Vec<String>,
+    }
+
+    impl FakeWriter {
+        fn failing(embed_failures: u32, append_failures: u32) -> Self {
+            let writer = Self::default();
+            {
+                let mut calls = writer.0.lock().unwrap();
+                calls.embed_failures = embed_failures;
+                calls.append_failures = append_failures;
+            }
+            writer
+        }
+
+        fn embeds(&self) -> Vec<Instant> {
+            self.0.lock().unwrap().embeds.clone()
+        }
+
+        fn stored(&self) -> Vec<String> {
+            self.0.lock().unwrap().stored.clone()
+        }
+    }
+
+    impl MemoryWriter for FakeWriter {
+        fn embed(&mut self, batch: &[CacheEntry]) -> Result<Vec<MemoryRecord>, FlushError> {
+            let mut calls = self.0.lock().unwrap();
+            calls.embeds.push(Instant::now());
+            if calls.embed_failures > 0 {
+                calls.embed_failures -= 1;
+                return Err(FlushError::Embedding { entries: batch.len(), message: "model busy".into() });
+            }
+            Ok(batch
+                .iter()
+                .flat_map(|entry| [&entry.input, &entry.output])
+                .map(|message| MemoryRecord {
+                    vector: Vec::new(),
+                    timestamp: 0,
+                    payload: message.content.clone().into_bytes(),
+                })
+                .collect())
+        }
+
+        fn append(&mut self, records: &[MemoryRecord], written: &mut usize) -> Result<(), FlushError> {
+            let mut calls = self.0.lock().unwrap();
+            for (i, record) in records.iter().enumerate() {
+                if i == 1 && calls.append_failures > 0 {
+                    calls.append_failures -= 1;
+                    return Err(FlushError::Store("disk full".into()));
+                }
+                calls.stored.push(String::from_utf8_lossy(&record.payload).into_owned());
+                *written += 1;
+            }
+            Ok(())
+        }
+
+        fn backoff(&self) -> Duration {
+            TEST_BACKOFF
+        }
+    }
+
+    // The flusher is a tokio task, so every cache needs a runtime around it.
+    #[tokio::test]
+    async fn cache_hits_capacity_flush() {
         let cache = Cache::new(2, 60); // capacity 2, TTL 60 s
         cache.push(
             ChatMessage::new("user", "hello"),
@@ -24,4 +106,249 @@ mod tests {
         thread::sleep(std::time::Duration::from_millis(1100));
         // TODO: assert file contents via mmap
     }
+
+    #[tokio::test]
+    async fn shutdown_drains_pending_entries() {
+        // Small talk included: this is about draining, not scoring.
+        let writer = FakeWriter::default();
+        let cache = Cache::with_writer(10, 60, Redactor::default(), writer.clone()).with_scorer(Scorer::keep_all());
+        cache.push(
+            ChatMessage::new("user", "hello"),
+            ChatMessage::new("assistant", "hi"),
+        );
+        cache.push(
+            ChatMessage::new("user", "my key is sk-proj-Ab3dEf6hIj9kLm2nOp5qRs8t"),
+            ChatMessage::new("assistant", "don't paste keys"),
+        );
+        cache.shutdown().await;
+        assert_eq!(cache.take_errors(), []);
+        assert_eq!(cache.persisted(), 2);
+        assert_eq!(writer.stored(), ["hello", "hi", "my key is [API_KEY_1]", "don't paste keys"]);
+
+        // Nothing is accepted once shut down, and shutting down twice is fine.
+        cache.push(
+            ChatMessage::new("user", "late"),
+            ChatMessage::new("assistant", "too late"),
+        );
+        cache.flush();
+        assert_eq!(cache.take_errors(), [FlushError::Closed(1)]);
+        cache.shutdown().await;
+    }
+
+    fn entry(input: &str, output: &str) -> CacheEntry {
+        CacheEntry {
+            seq: 0,
+            input: ChatMessage::new("user", input),
+            output: ChatMessage::new("assistant", output),
+        }
+    }
+
+    #[test]
+    fn wal_replay_stops_at_torn_record() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cache.wal");
+        let (mut wal, pending) = Wal::open(&path).unwrap();
+        assert!(pending.is_empty());
+        for (input, output) in [("one", "1"), ("two", "2"), ("three", "3")] {
+            wal.append(&mut entry(input, output)).unwrap();
+        }
+        drop(wal);
+
+        // A crash halfway through the last append.
+        let len = std::fs::metadata(&path).unwrap().len();
+        std::fs::OpenOptions::new().write(true).open(&path).unwrap().set_len(len - 3).unwrap();
+        let (mut wal, pending) = Wal::open(&path).unwrap();
+        let inputs: Vec<_> = pending.iter().map(|e| e.input.content.as_str()).collect();
+        assert_eq!(inputs, ["one", "two"]);
+        assert_eq!(wal.len(), 2);
+
+        // The torn bytes are gone, so new records are readable after replay,
+        // and numbering carries on from the last one that survived.
+        let mut four = entry("four", "4");
+        wal.append(&mut four).unwrap();
+        assert_eq!(four.seq, 3);
+        drop(wal);
+        let (_, pending) = Wal::open(&path).unwrap();
+        assert_eq!(pending.len(), 3);
+
+        // A flipped bit fails the checksum; nothing after it is trusted.
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[10] ^= 0x01;
+        std::fs::write(&path, bytes).unwrap();
+        let (wal, pending) = Wal::open(&path).unwrap();
+        assert!(pending.is_empty() && wal.is_empty());
+    }
+
+    #[test]
+    fn wal_drops_acknowledged_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cache.wal");
+        let (mut wal, _) = Wal::open(&path).unwrap();
+        let mut entries: Vec<_> = (0..3).map(|i| entry(&format!("q{}", i), &format!("a{}", i))).collect();
+        for e in &mut entries {
+            wal.append(e).unwrap();
+        }
+
+        wal.acknowledge(&[entries[0].seq, entries[2].seq]).unwrap();
+        let (_, pending) = Wal::open(&path).unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].seq, entries[1].seq);
+        assert_eq!(pending[0].output.content, "a1");
+
+        wal.acknowledge(&[entries[1].seq]).unwrap();
+        assert!(wal.is_empty());
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 0);
+    }
+
+    #[tokio::test]
+    async fn open_replays_wal_into_flusher() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cache.wal");
+        let (mut wal, _) = Wal::open(&path).unwrap();
+        wal.append(&mut entry("Remember this: my cat is called Miso", "Noted!")).unwrap();
+        drop(wal);
+
+        let writer = FakeWriter::default();
+        let cache = Cache::open(&path, 10, 60, Redactor::default(), writer.clone()).unwrap();
+        cache.push(
+            ChatMessage::new("user", "hello"),
+            ChatMessage::new("assistant", "hi"),
+        );
+        cache.shutdown().await;
+
+        // The small talk is scored out; the replayed entry is persisted.
+        // Both leave the log.
+        assert_eq!(cache.take_errors(), []);
+        assert_eq!(cache.persisted(), 1);
+        assert_eq!(writer.stored(), ["Remember this: my cat is called Miso", "Noted!"]);
+        let (_, pending) = Wal::open(&path).unwrap();
+        assert!(pending.is_empty());
+    }
+
+    #[tokio::test]
+    async fn flusher_retries_with_backoff() {
+        let writer = FakeWriter::failing(2, 1);
+        let cache = Cache::with_writer(10, 60, Redactor::default(), writer.clone()).with_scorer(Scorer::keep_all());
+        cache.push(
+            ChatMessage::new("user", "q"),
+            ChatMessage::new("assistant", "a"),
+        );
+        cache.shutdown().await;
+
+        assert_eq!(cache.take_errors(), []);
+        assert_eq!(cache.persisted(), 1);
+        // The failed append resumed after the record it did store.
+        assert_eq!(writer.stored(), ["q", "a"]);
+        let embeds = writer.embeds();
+        assert_eq!(embeds.len(), 3);
+        assert!(embeds[1] - embeds[0] >= TEST_BACKOFF);
+        assert!(embeds[2] - embeds[1] >= TEST_BACKOFF * 2);
+    }
+
+    #[tokio::test]
+    async fn flusher_reports_batches_it_gives_up_on() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cache.wal");
+        let writer = FakeWriter::failing(u32::MAX, 0);
+        let cache = Cache::open(&path, 10, 60, Redactor::default(), writer.clone())
+            .unwrap()
+            .with_scorer(Scorer::keep_all());
+        cache.push(
+            ChatMessage::new("user", "q"),
+            ChatMessage::new("assistant", "a"),
+        );
+        cache.shutdown().await;
+
+        assert_eq!(writer.embeds().len(), MAX_ATTEMPTS as usize);
+        assert_eq!(
+            cache.take_errors(),
+            [FlushError::Embedding { entries: 1, message: "model busy".into() }]
+        );
+        assert_eq!(cache.persisted(), 0);
+        // Kept for the next start.
+        let (_, pending) = Wal::open(&path).unwrap();
+        assert_eq!(pending.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn wal_never_sees_secrets() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cache.wal");
+        let cache = Cache::open(&path, 10, 60, Redactor::default(), FakeWriter::default()).unwrap();
+        let key = "sk-proj-Ab3dEf6hIj9kLm2nOp5qRs8t";
+        cache.push(
+            ChatMessage::new("user", format!("my key is {}", key)),
+            ChatMessage::new("assistant", format!("Revoke {} now", key)),
+        );
+
+        let bytes = std::fs::read(&path).unwrap();
+        assert!(!bytes.is_empty());
+        assert!(!bytes.windows(key.len()).any(|window| window == key.as_bytes()));
+        let (_, pending) = Wal::open(&path).unwrap();
+        assert_eq!(pending[0].input.content, "my key is [API_KEY_1]");
+    }
+
+    #[test]
+    fn scorer_prefers_substance_over_small_talk() {
+        let scorer = Scorer::default();
+        let chit_chat = entry("thanks!", "You're welcome, glad I could help.");
+        assert!(scorer.score(&chit_chat) < score::DEFAULT_THRESHOLD);
+
+        let remember = entry("Please remember that I'm preparing for the DELF B2", "Good luck!");
+        assert_eq!(scorer.score(&remember), 1.0);
+
+        let correction = entry("No, that's wrong: it's 'je suis allé'", "You're right, sorry.");
+        assert!(scorer.signals(&correction).correction);
+        assert!(scorer.score(&correction) >= score::DEFAULT_THRESHOLD);
+
+        let code = entry(
+            "Why doesn't this compile?\n```rust\nlet x: u8 = 256;\n```",
+            "256 doesn't fit in a u8.",
+        );
+        assert!(scorer.signals(&code).code);
+        assert!(scorer.score(&code) >= score::DEFAULT_THRESHOLD);
+        // "Let me explain; it's easy" is one sentence, not a program.
+        assert!(!scorer.signals(&entry("ok", "Let me explain; it's easy.")).code);
+    }
+
+    #[test]
+    fn scorer_downsamples_repeats_and_small_talk() {
+        let mut scorer = Scorer::new(score::DEFAULT_THRESHOLD, 2);
+        let fact = || entry(
+            "What is the capital of Australia?",
+            "Canberra, not Sydney: it was built as a compromise between Sydney and Melbourne.",
+        );
+        let (kept, dropped) = scorer.select(vec![fact(), fact()]);
+        assert_eq!((kept.len(), dropped.len()), (1, 1));
+
+        // Once seen, the fact is no more novel than small talk. Every second
+        // low scorer still gets through, counting the copy dropped above.
+        let (kept, dropped) = scorer.select(vec![entry("hi", "hello!"), fact(), entry("bye", "see you")]);
+        let kept: Vec<_> = kept.iter().map(|e| e.input.content.as_str()).collect();
+        assert_eq!(kept, ["hi", "bye"]);
+        assert_eq!(dropped.len(), 1);
+
+        let mut everything = Scorer::keep_all();
+        let (kept, _) = everything.select(vec![entry("hi", "hello!"), fact(), fact()]);
+        assert_eq!(kept.len(), 3);
+    }
+
+    #[test]
+    fn scorer_novelty_survives_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cache.terms");
+        let fact = || entry(
+            "What is the capital of Australia?",
+            "Canberra, not Sydney: it was built as a compromise between Sydney and Melbourne.",
+        );
+        let mut scorer = Scorer::open(&path).unwrap();
+        assert!(scorer.signals(&fact()).novel_terms > 0);
+        let (kept, _) = scorer.select(vec![fact()]);
+        assert_eq!(kept.len(), 1);
+        drop(scorer);
+
+        let reopened = Scorer::open(&path).unwrap();
+        assert_eq!(reopened.signals(&fact()).novel_terms, 0);
+        assert!(reopened.score(&fact()) < score::DEFAULT_THRESHOLD);
+    }
 }
\ No newline at end of file
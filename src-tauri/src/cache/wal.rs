@@ -0,0 +1,152 @@
+//! Write-ahead log for entries the flusher hasn't persisted yet.
+//!
+//! Append-only; every record is `[len: u32][crc32: u32][bincode entry]`,
+//! little-endian, and is synced before `push` buffers the entry. A crash can
+//! only tear the last record, so replay keeps everything up to the first
+//! short or corrupt record and cuts the file there.
+//!
+//! Each entry gets a sequence number. Once the flusher has written a batch it
+//! acknowledges those numbers: the log is truncated when nothing else is
+//! left, and rewritten without them otherwise.
+
+use super::message::CacheEntry;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+const HEADER_LEN: usize = 8;
+
+pub struct Wal {
+    path: PathBuf,
+    file: File,
+    next_seq: u64,
+    /// Records in the file, and the bytes they take.
+    records: usize,
+    len: u64,
+}
+
+impl Wal {
+    /// Opens or creates the log at `path`, returning the entries still in it
+    /// in the order they were pushed.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<(Self, Vec<CacheEntry>)> {
+        let path = path.as_ref().to_path_buf();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        let (entries, valid) = decode(&bytes);
+
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        if valid < bytes.len() {
+            // Torn tail from a crash mid-append.
+            file.set_len(valid as u64)?;
+            file.sync_data()?;
+        }
+        let next_seq = entries.iter().map(|e| e.seq).max().unwrap_or(0) + 1;
+        let wal = Self {
+            path,
+            file,
+            next_seq,
+            records: entries.len(),
+            len: valid as u64,
+        };
+        Ok((wal, entries))
+    }
+
+    /// Numbers `entry` and makes it durable. Sequence numbers are never
+    /// reused, even when the write fails.
+    pub fn append(&mut self, entry: &mut CacheEntry) -> io::Result<()> {
+        entry.seq = self.next_seq;
+        self.next_seq += 1;
+        let frame = encode(entry)?;
+        if let Err(e) = self.file.write_all(&frame).and_then(|()| self.file.sync_data()) {
+            // A partial frame would hide every record appended after it.
+            let _ = self.file.set_len(self.len);
+            return Err(e);
+        }
+        self.records += 1;
+        self.len += frame.len() as u64;
+        Ok(())
+    }
+
+    /// Drops the entries numbered `seqs` once they are safely in memory.
+    pub fn acknowledge(&mut self, seqs: &[u64]) -> io::Result<()> {
+        if seqs.is_empty() {
+            return Ok(());
+        }
+        let (entries, _) = decode(&fs::read(&self.path)?);
+        let kept: Vec<_> = entries.into_iter().filter(|e| !seqs.contains(&e.seq)).collect();
+        if kept.is_empty() {
+            return self.truncate();
+        }
+        if kept.len() == self.records {
+            return Ok(());
+        }
+
+        let mut bytes = Vec::new();
+        for entry in &kept {
+            bytes.extend(encode(entry)?);
+        }
+        let tmp = self.path.with_extension("wal.tmp");
+        let mut out = File::create(&tmp)?;
+        out.write_all(&bytes)?;
+        out.sync_all()?;
+        fs::rename(&tmp, &self.path)?;
+        self.file = OpenOptions::new().append(true).open(&self.path)?;
+        self.records = kept.len();
+        self.len = bytes.len() as u64;
+        Ok(())
+    }
+
+    pub fn truncate(&mut self) -> io::Result<()> {
+        self.file.set_len(0)?;
+        self.file.sync_data()?;
+        self.records = 0;
+        self.len = 0;
+        Ok(())
+    }
+
+    /// Records not acknowledged yet.
+    pub fn len(&self) -> usize {
+        self.records
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records == 0
+    }
+}
+
+fn encode(entry: &CacheEntry) -> io::Result<Vec<u8>> {
+    let payload = bincode::serialize(entry).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let mut frame = Vec::with_capacity(HEADER_LEN + payload.len());
+    frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    frame.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+    frame.extend_from_slice(&payload);
+    Ok(frame)
+}
+
+/// The entries before the first bad record, and how many bytes they span.
+fn decode(bytes: &[u8]) -> (Vec<CacheEntry>, usize) {
+    let mut entries = Vec::new();
+    let mut offset = 0;
+    while let Some(header) = bytes.get(offset..offset + HEADER_LEN) {
+        let len = u32::from_le_bytes(header[..4].try_into().unwrap()) as usize;
+        let crc = u32::from_le_bytes(header[4..].try_into().unwrap());
+        let Some(payload) = bytes.get(offset + HEADER_LEN..offset + HEADER_LEN + len) else {
+            break;
+        };
+        if crc32fast::hash(payload) != crc {
+            break;
+        }
+        let Ok(entry) = bincode::deserialize(payload) else {
+            break;
+        };
+        entries.push(entry);
+        offset += HEADER_LEN + len;
+    }
+    (entries, offset)
+}
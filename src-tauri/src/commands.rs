@@ -1,16 +1,118 @@
 use crate::preprocessing::{
-    router::{Mode, Proficiency, Personality},
-    Preprocessor, FormattedInput,
+    router::{Mode, Proficiency, Personality, Language},
+    Preprocessor, FormattedInput, Redactor,
 };
-use crate::postprocessing::PostProcessor;
-use crate::llama::{LLMEngine, Language};
-use tauri::command;
-use std::sync::Mutex;
+use crate::postprocessing::{shaping::parse_quiz_question, PostProcessor, QuizItem};
+use crate::engine::output::{strategy_for, PromptStrategy, QuizStrategy};
+use crate::personalities::PersonaRegistry;
+use crate::embedding::{CacheStats, EmbeddingCache, EmbeddingEngine};
+use crate::registry::{ActiveModels, ModelInfo, ModelKind, ModelListing, ModelRegistry};
+use crate::learner::{DomainEstimate, LearnerModel};
+use crate::records::{RecordStore, ScoredRecord};
+use crate::cache::{message::ChatMessage as CachedMessage, Cache};
+use crate::llama::{
+    CancellationToken, ChatMessage, LLMEngine, LLMError, MessageRole, SessionStore,
+};
+use serde::Serialize;
+use tauri::{command, AppHandle, Emitter};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Event the chat view listens on for partial assistant output.
+pub const TOKEN_EVENT: &str = "llm://token";
+
+/// Hard ceiling on a single answer, so a runaway generation can't pin the CPU.
+const GENERATION_TIMEOUT: Duration = Duration::from_secs(120);
 
-// Global state now only keeps the latest formatted payload
-#[derive(Default)]
+/// Earlier inputs offered to the model as context, at most, and how close
+/// (cosine) one has to be to the current input to count.
+const RELATED_INPUTS: usize = 3;
+const RELATED_MIN_SCORE: f32 = 0.75;
+
+// Global state keeps the latest formatted payload, the running conversation
+// and the stop flag of whatever generation is currently running.
 pub struct AppState {
     pub latest: Option<FormattedInput>,
+    pub conversation_id: String,
+    pub history: Vec<ChatMessage>,
+    pub cancel: CancellationToken,
+    /// Set while a `send_output` turn runs; see `Turn`.
+    pub generating: bool,
+    /// Quiz question awaiting the learner's answer, with its answer key.
+    pub quiz: Option<QuizItem>,
+}
+
+impl Default for AppState {
+    fn default() -> Self {
+        Self {
+            latest: None,
+            conversation_id: Uuid::new_v4().to_string(),
+            history: Vec::new(),
+            cancel: CancellationToken::new(),
+            generating: false,
+            quiz: None,
+        }
+    }
+}
+
+impl AppState {
+    /// What the model sees for `user_turn`: `system`, the conversation so
+    /// far, then the turn itself. `system` never enters the history, so a
+    /// new reply language or mode takes effect on the very next turn.
+    pub fn prompt(&self, system: String, user_turn: &ChatMessage) -> Vec<ChatMessage> {
+        let mut messages = vec![ChatMessage {
+            role: MessageRole::System,
+            content: system,
+        }];
+        messages.extend(self.history.iter().cloned());
+        messages.push(user_turn.clone());
+        messages
+    }
+
+    /// Appends a finished exchange. `reply` is the raw model output, the
+    /// tokens the conversation's KV cache already holds.
+    pub fn remember(&mut self, user_turn: ChatMessage, reply: String) {
+        self.history.push(user_turn);
+        self.history.push(ChatMessage {
+            role: MessageRole::Assistant,
+            content: reply,
+        });
+    }
+}
+
+/// A `send_output` turn in progress. Only one runs at a time, so a second
+/// call can't swap out the stop flag `cancel_generation` has to reach, or
+/// interleave its history with the first; dropping the turn, however it
+/// ends, lets the next one start.
+struct Turn<'a>(&'a Mutex<AppState>);
+
+impl<'a> Turn<'a> {
+    /// The turn and its fresh stop flag, or an error while another turn runs.
+    fn begin(state: &'a Mutex<AppState>) -> Result<(Self, CancellationToken), String> {
+        let mut guard = state.lock().unwrap();
+        if guard.generating {
+            return Err("A reply is still being generated".to_string());
+        }
+        guard.generating = true;
+        guard.cancel = CancellationToken::new();
+        Ok((Self(state), guard.cancel.clone()))
+    }
+}
+
+impl Drop for Turn<'_> {
+    fn drop(&mut self) {
+        self.0.lock().unwrap().generating = false;
+    }
+}
+
+/// Payload of every `llm://token` event. `done` is set on the final event,
+/// which carries the post-processed reply so the frontend can swap it in.
+#[derive(Clone, Serialize)]
+pub struct TokenDelta {
+    pub stream_id: String,
+    pub delta: String,
+    pub done: bool,
 }
 
 /* ---------- 1.  SETTERS ---------- */
@@ -48,40 +150,352 @@ pub async fn send_output(
     proficiency: u8,
     personality: u8,
     language: Language,
-    llm: tauri::State<'_, Mutex<LLMEngine>>,
+    app: AppHandle,
+    models: tauri::State<'_, Arc<ActiveModels>>,
     state: tauri::State<'_, Mutex<AppState>>,
+    sessions: tauri::State<'_, SessionStore>,
+    learner: tauri::State<'_, LearnerModel>,
+    redactor: tauri::State<'_, Redactor>,
+    records: tauri::State<'_, RecordStore>,
+    cache: tauri::State<'_, Cache>,
 ) -> Result<String, String> {
+    // 0. One turn at a time; its stop flag is live from here on, and
+    //    `cancel_generation` flips it.
+    let (_turn, cancel) = Turn::begin(&state)?;
+
     // 1. Convert enums
     let mode_enum      = Mode::select_mode(mode).await?;
     let prof_enum      = Proficiency::select_proficiency(proficiency).await?;
     let pers_enum      = Personality::select_personality(personality).await?;
 
-    // 2. Run the **single** preprocessing step
-    let llm_guard = llm.lock().unwrap();
-    let formatted = Preprocessor::process(
+    // 2. Run the **single** preprocessing step, on whichever chat model is
+    //    selected right now; a swap mid-turn only affects the next one.
+    let llm = models.chat().map_err(|e| e.to_string())?;
+    let mut formatted = Preprocessor::process(
         input,
         mode_enum,
         prof_enum,
         pers_enum,
         language,
-        &llm_guard,
+        &learner,
+        &redactor,
+        &*llm,
     )
     .await
     .map_err(|e| e.to_string())?;
 
-    // 3. Cache for later retrieval
-    state.lock().unwrap().latest = Some(formatted.clone());
+    // 3. Embed the (redacted) input and store the record with its vector, then
+    //    look up what the learner asked before on the same kind of topic;
+    //    none of that is reason to fail the turn. Cache for later retrieval,
+    //    and queue the turn behind the history so far.
+    //    The reply-language and mode instructions lead every prompt but never
+    //    enter the history, so switching either mid-conversation takes effect.
+    //    In quiz mode, a pending question means this turn answers it.
+    let cleaned = formatted.sqlite_vec_record.cleaned_input.clone();
+    if let Ok(mut embedded) = models.embedder().and_then(|embedder| embedder.embed_passages(&[cleaned])) {
+        formatted.sqlite_vec_record.vector = embedded.remove(0).vector;
+    }
+    let _ = records.insert(formatted.to_sqlite_vec_record());
+    let related = records.similar(&formatted, RELATED_INPUTS).unwrap_or_default();
+    let user_turn = ChatMessage {
+        role: MessageRole::User,
+        content: formatted.context.raw_input.clone(),
+    };
+    let (conversation_id, messages, asking) = {
+        let mut guard = state.lock().unwrap();
+        guard.latest = Some(formatted.clone());
+        let strategy: Arc<dyn PromptStrategy> = match (mode_enum, &guard.quiz) {
+            (Mode::Quiz, Some(item)) => Arc::new(QuizStrategy::Grade {
+                question: item.question.clone(),
+                expected: item.answer.clone(),
+            }),
+            _ => strategy_for(&formatted, language),
+        };
+        let asking = mode_enum == Mode::Quiz && guard.quiz.is_none();
+        let mut system = language.reply_instruction();
+        if let Some(instructions) = strategy.instructions() {
+            system = format!("{}\n\n{}", system, instructions);
+        }
+        if let Some(related) = related_inputs(&related) {
+            system = format!("{}\n\n{}", system, related);
+        }
+        (guard.conversation_id.clone(), guard.prompt(system, &user_turn), asking)
+    };
+
+    // 4. Generate, forwarding every delta so the UI can render partial text.
+    //    The conversation's KV cache is restored first, so only the new turn
+    //    is evaluated. A fresh quiz question isn't streamed: the raw reply
+    //    carries its answer key. Decoding blocks, so it runs on the blocking
+    //    pool; the runtime stays free to serve `cancel_generation` meanwhile.
+    let persona_config = PersonaRegistry::shared()
+        .and_then(|registry| registry.get_persona(&format!("{:?}", pers_enum)))
+        .map(|persona| persona.generation_config())
+        .unwrap_or_default();
+    let config = persona_config
+        .with_cancel(cancel)
+        .with_timeout(GENERATION_TIMEOUT);
+
+    let stream_id = formatted.get_id().to_string();
+    let result = {
+        let (llm, sessions, app, stream_id) =
+            (Arc::clone(&llm), SessionStore::clone(&sessions), app.clone(), stream_id.clone());
+        tauri::async_runtime::spawn_blocking(move || {
+            llm.chat_stream_in_session(&sessions, &conversation_id, &messages, Some(config), |delta| {
+                if asking {
+                    return;
+                }
+                let _ = app.emit(TOKEN_EVENT, TokenDelta {
+                    stream_id: stream_id.clone(),
+                    delta: delta.to_string(),
+                    done: false,
+                });
+            })
+        })
+        .await
+        .map_err(|e| e.to_string())?
+    };
+    let reply = match result {
+        Ok(reply) => reply,
+        // Stopped early: whatever was produced so far is still the answer.
+        Err(LLMError::Cancelled { partial, .. }) => partial,
+        Err(e) => return Err(e.to_string()),
+    };
+    let answered = !reply.trim().is_empty();
 
-    // 4. Post-process with persona flavor
-    let post = PostProcessor::new(llm_guard.clone());
+    // 5. Keep the answer key of a new quiz question for grading next turn,
+    //    then post-process with the mode's shaping and persona flavor.
+    let quiz = if asking { parse_quiz_question(&reply) } else { None };
+    state.lock().unwrap().quiz = quiz;
+    let post = PostProcessor::new(&*llm).asking_quiz(asking);
     let output = post
         .process(
-            formatted.context.raw_input.clone(),
+            reply.clone(),
             pers_enum,
             mode_enum,
-            prof_enum,
+            formatted.proficiency,
         )
         .await;
 
+    let _ = app.emit(TOKEN_EVENT, TokenDelta {
+        stream_id,
+        delta: output.clone(),
+        done: true,
+    });
+
+    // 6. Remember the exchange; trimming to the context window happens at prompt time.
+    //    History keeps the raw reply, the tokens the conversation's KV cache
+    //    already holds, so the next prompt extends the cache instead of
+    //    diverging from it. The learner model learns from the exchange too;
+    //    failing to persist that is no reason to lose the answer. Long-term
+    //    memory gets it once the cache flushes, redacted; a reply stopped
+    //    before its first token has nothing worth embedding.
+    let _ = learner.observe(&formatted, &output);
+    if answered {
+        cache.push(
+            CachedMessage::new("user", formatted.context.raw_input.clone()),
+            CachedMessage::new("assistant", output.clone()),
+        );
+    }
+    state.lock().unwrap().remember(user_turn, reply);
+
     Ok(output)
-}
\ No newline at end of file
+}
+
+/// Earlier inputs close enough to the current one that the model can build
+/// on them, or `None` if there are none.
+fn related_inputs(similar: &[ScoredRecord]) -> Option<String> {
+    let lines: Vec<String> = similar
+        .iter()
+        .filter(|scored| scored.score >= RELATED_MIN_SCORE)
+        .map(|scored| format!("- {}", scored.record.raw_input))
+        .collect();
+    if lines.is_empty() {
+        return None;
+    }
+    Some(format!("The learner has asked related questions before:\n{}", lines.join("\n")))
+}
+
+/* ---------- 3.  CONTROL ---------- */
+
+#[command]
+pub async fn cancel_generation(
+    state: tauri::State<'_, Mutex<AppState>>,
+) -> Result<String, String> {
+    state.lock().unwrap().cancel.cancel();
+    Ok("Generation cancelled".to_string())
+}
+
+#[command]
+pub async fn reset_conversation(
+    state: tauri::State<'_, Mutex<AppState>>,
+    sessions: tauri::State<'_, SessionStore>,
+) -> Result<String, String> {
+    let mut guard = state.lock().unwrap();
+    guard.cancel.cancel();
+    guard.history.clear();
+    guard.latest = None;
+    guard.quiz = None;
+    // The old snapshot can never be resumed, so don't leave it on disk.
+    let _ = sessions.remove(&guard.conversation_id);
+    guard.conversation_id = Uuid::new_v4().to_string();
+    Ok("Conversation reset".to_string())
+}
+
+/* ---------- 4.  MODELS ---------- */
+
+#[command]
+pub async fn list_models(
+    registry: tauri::State<'_, Mutex<ModelRegistry>>,
+) -> Result<ModelListing, String> {
+    let mut guard = registry.lock().unwrap();
+    guard.rescan().map_err(|e| e.to_string())?;
+    Ok(guard.listing())
+}
+
+#[command]
+pub async fn select_model(
+    id: String,
+    registry: tauri::State<'_, Mutex<ModelRegistry>>,
+    models: tauri::State<'_, Arc<ActiveModels>>,
+) -> Result<ModelInfo, String> {
+    let model = registry
+        .lock()
+        .unwrap()
+        .get(&id)
+        .cloned()
+        .ok_or_else(|| format!("Unknown model: {}", id))?;
+
+    // Load first, swap second: the old engine keeps serving until the new one
+    // is ready, and a failed load leaves everything as it was. Chat and the
+    // cache flusher both pick the new engine up on their next use.
+    match model.kind {
+        ModelKind::Chat => {
+            let engine = LLMEngine::new(&model.path).map_err(|e| e.to_string())?;
+            models.set_chat(engine);
+        }
+        ModelKind::Embedding => {
+            let engine = EmbeddingEngine::new(&model.path).map_err(|e| e.to_string())?;
+            models.set_embedding(engine);
+        }
+    }
+    registry.lock().unwrap().select(&id).map_err(|e| e.to_string())
+}
+
+#[command]
+pub async fn embedding_cache_stats(
+    cache: tauri::State<'_, Arc<EmbeddingCache>>,
+) -> Result<CacheStats, String> {
+    Ok(cache.stats())
+}
+
+/// How long-term memory is keeping up with the conversation cache.
+#[derive(Clone, Serialize)]
+pub struct MemoryStatus {
+    /// Entries written to memory since launch.
+    pub persisted: u64,
+    /// Batches given up on since the last call, oldest first. Their entries
+    /// stay in the write-ahead log and are retried on the next start.
+    pub errors: Vec<String>,
+}
+
+#[command]
+pub async fn memory_status(
+    cache: tauri::State<'_, Cache>,
+) -> Result<MemoryStatus, String> {
+    Ok(MemoryStatus {
+        persisted: cache.persisted(),
+        errors: cache.take_errors().iter().map(ToString::to_string).collect(),
+    })
+}
+
+/* ---------- 5.  LEARNER ---------- */
+
+/// One domain of `learner_profile`.
+#[derive(Clone, Serialize)]
+pub struct DomainProficiency {
+    pub domain: String,
+    pub estimate: DomainEstimate,
+    /// Rounded `estimate.level`.
+    pub proficiency: Proficiency,
+    /// Set when the estimate clearly disagrees with the selected level.
+    pub suggested: Option<Proficiency>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct LearnerProfile {
+    pub auto_adjust: bool,
+    pub domains: Vec<DomainProficiency>,
+}
+
+/// Per-domain estimates, with suggestions relative to the `proficiency`
+/// currently selected in the UI.
+#[command]
+pub async fn learner_profile(
+    proficiency: u8,
+    learner: tauri::State<'_, LearnerModel>,
+) -> Result<LearnerProfile, String> {
+    let selected = Proficiency::select_proficiency(proficiency).await?;
+    let domains = learner
+        .estimates()
+        .into_iter()
+        .map(|(domain, estimate)| DomainProficiency {
+            suggested: learner.suggest(&domain, selected),
+            proficiency: estimate.proficiency(),
+            domain,
+            estimate,
+        })
+        .collect();
+    Ok(LearnerProfile {
+        auto_adjust: learner.auto_adjust(),
+        domains,
+    })
+}
+
+#[command]
+pub async fn set_proficiency_auto_adjust(
+    enabled: bool,
+    learner: tauri::State<'_, LearnerModel>,
+) -> Result<String, String> {
+    learner.set_auto_adjust(enabled).map_err(|e| e.to_string())?;
+    Ok("Proficiency auto-adjust stored".to_string())
+}
+
+#[command]
+pub async fn reset_learner_profile(
+    learner: tauri::State<'_, LearnerModel>,
+) -> Result<String, String> {
+    learner.reset().map_err(|e| e.to_string())?;
+    Ok("Learner profile reset".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llama::{LlmBackend, MockBackend};
+
+    fn user(content: &str) -> ChatMessage {
+        ChatMessage {
+            role: MessageRole::User,
+            content: content.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_turns_build_on_the_history() {
+        let llm = MockBackend::scripted(["Values have one owner.", "A borrow lends one out."]);
+        let mut state = AppState::default();
+        for (system, question) in [("Reply in English.", "What is ownership?"), ("Reply in German.", "And borrowing?")] {
+            let messages = state.prompt(system.to_string(), &user(question));
+            let reply = llm.chat_stream(&messages, None, &mut |_| {}).unwrap();
+            state.remember(user(question), reply);
+        }
+
+        let prompts = llm.prompts();
+        assert!(prompts[1].contains("What is ownership?"), "{}", prompts[1]);
+        assert!(prompts[1].contains("Values have one owner."), "{}", prompts[1]);
+        // Only the current system message is sent; the old one is gone.
+        assert!(prompts[1].contains("Reply in German.") && !prompts[1].contains("Reply in English."));
+        assert_eq!(state.history.len(), 4);
+        assert_eq!(state.history[3].content, "A borrow lends one out.");
+    }
+}
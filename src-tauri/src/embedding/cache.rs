@@ -0,0 +1,471 @@
+//! Content-addressed memo of embeddings.
+//!
+//! Keys are `blake3(model id, normalised text)`, so a text is embedded once
+//! per model no matter how often it is searched. Hot entries live in an LRU;
+//! every entry is also written to `<dir>/<key>.f32` so the memo survives
+//! restarts. The directory records which model filled it and is wiped as
+//! soon as a different model is bound. It is capped in bytes: once over
+//! the cap, entries are evicted down to three quarters of it, those the LRU
+//! no longer holds first, oldest file first, then the LRU's coldest.
+
+use super::{EmbeddingEngine, EmbeddingResult, Result};
+use lru::LruCache;
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::io;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+pub const DEFAULT_CAPACITY: usize = 4096;
+/// 64 MiB: some 40 000 bge-small vectors.
+pub const DEFAULT_DISK_LIMIT: u64 = 64 * 1024 * 1024;
+
+const MODEL_FILE: &str = "MODEL";
+const EXTENSION: &str = "f32";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct CacheStats {
+    /// Served from the in-memory LRU.
+    pub hits: u64,
+    /// Served from disk and promoted into the LRU.
+    pub disk_hits: u64,
+    /// Had to be embedded.
+    pub misses: u64,
+    /// Entries currently held in memory.
+    pub entries: usize,
+    /// Bytes the entries take on disk.
+    pub disk_bytes: u64,
+}
+
+impl CacheStats {
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.disk_hits + self.misses;
+        if total == 0 {
+            return 0.0;
+        }
+        (self.hits + self.disk_hits) as f64 / total as f64
+    }
+}
+
+struct State {
+    model_id: Option<String>,
+    lru: LruCache<blake3::Hash, Vec<f32>>,
+    disk_bytes: u64,
+}
+
+pub struct EmbeddingCache {
+    dir: Option<PathBuf>,
+    disk_limit: u64,
+    state: Mutex<State>,
+    hits: AtomicU64,
+    disk_hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl EmbeddingCache {
+    /// LRU of `capacity` entries backed by `dir`, created if needed, and
+    /// capped at `DEFAULT_DISK_LIMIT`.
+    pub fn open<P: AsRef<Path>>(dir: P, capacity: usize) -> io::Result<Self> {
+        std::fs::create_dir_all(dir.as_ref())?;
+        let cache = Self::build(Some(dir.as_ref().to_path_buf()), capacity);
+        let disk_bytes = entry_files(dir.as_ref())?.iter().map(|file| file.len).sum();
+        cache.state.lock().unwrap().disk_bytes = disk_bytes;
+        Ok(cache)
+    }
+
+    /// Caps the entries on disk at `bytes`; the cap applies from the next insert.
+    pub fn with_disk_limit(mut self, bytes: u64) -> Self {
+        self.disk_limit = bytes;
+        self
+    }
+
+    /// LRU only; nothing touches the disk.
+    pub fn in_memory(capacity: usize) -> Self {
+        Self::build(None, capacity)
+    }
+
+    fn build(dir: Option<PathBuf>, capacity: usize) -> Self {
+        let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN);
+        Self {
+            dir,
+            disk_limit: DEFAULT_DISK_LIMIT,
+            state: Mutex::new(State {
+                model_id: None,
+                lru: LruCache::new(capacity),
+                disk_bytes: 0,
+            }),
+            hits: AtomicU64::new(0),
+            disk_hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Declares which model the entries belong to. Binding a different model
+    /// than before drops every entry, in memory and on disk.
+    pub fn bind_model(&self, model_id: &str) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.model_id.as_deref() == Some(model_id) {
+            return Ok(());
+        }
+        state.lru.clear();
+        state.model_id = Some(model_id.to_string());
+
+        if let Some(dir) = &self.dir {
+            let marker = dir.join(MODEL_FILE);
+            if std::fs::read_to_string(&marker).ok().as_deref() != Some(model_id) {
+                remove_entries(dir)?;
+                state.disk_bytes = 0;
+                std::fs::write(&marker, model_id)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn model_id(&self) -> Option<String> {
+        self.state.lock().unwrap().model_id.clone()
+    }
+
+    /// Looks `text` up under the bound model; `None` if no model is bound.
+    pub fn get(&self, text: &str) -> Option<Vec<f32>> {
+        let mut state = self.state.lock().unwrap();
+        let key = key(state.model_id.as_deref()?, text);
+
+        if let Some(vector) = state.lru.get(&key) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Some(vector.clone());
+        }
+        if let Some(vector) = self.read_entry(&key) {
+            self.disk_hits.fetch_add(1, Ordering::Relaxed);
+            state.lru.put(key, vector.clone());
+            return Some(vector);
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        None
+    }
+
+    /// Stores `vector` for `text` under the bound model. Disk writes and
+    /// eviction are best-effort: a failure only costs a re-embed after
+    /// restart.
+    pub fn insert(&self, text: &str, vector: Vec<f32>) {
+        let mut state = self.state.lock().unwrap();
+        let Some(model_id) = state.model_id.as_deref() else {
+            return;
+        };
+        let key = key(model_id, text);
+        if let Ok((written, replaced)) = self.write_entry(&key, &vector) {
+            state.disk_bytes = (state.disk_bytes + written).saturating_sub(replaced);
+        }
+        state.lru.put(key, vector);
+        if state.disk_bytes > self.disk_limit {
+            let _ = self.evict(&mut state);
+        }
+    }
+
+    /// Forgets every entry of the bound model, in memory and on disk.
+    pub fn clear(&self) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.lru.clear();
+        match &self.dir {
+            Some(dir) => {
+                remove_entries(dir)?;
+                state.disk_bytes = 0;
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let state = self.state.lock().unwrap();
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            disk_hits: self.disk_hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: state.lru.len(),
+            disk_bytes: state.disk_bytes,
+        }
+    }
+
+    fn entry_path(&self, key: &blake3::Hash) -> Option<PathBuf> {
+        let dir = self.dir.as_ref()?;
+        Some(dir.join(format!("{}.{}", key.to_hex(), EXTENSION)))
+    }
+
+    fn read_entry(&self, key: &blake3::Hash) -> Option<Vec<f32>> {
+        let bytes = std::fs::read(self.entry_path(key)?).ok()?;
+        if bytes.is_empty() || bytes.len() % 4 != 0 {
+            return None;
+        }
+        Some(
+            bytes
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect(),
+        )
+    }
+
+    /// Bytes written, and those of the entry it replaced.
+    fn write_entry(&self, key: &blake3::Hash, vector: &[f32]) -> io::Result<(u64, u64)> {
+        let Some(path) = self.entry_path(key) else {
+            return Ok((0, 0));
+        };
+        let replaced = std::fs::metadata(&path).map_or(0, |meta| meta.len());
+        let bytes: Vec<u8> = vector.iter().flat_map(|v| v.to_le_bytes()).collect();
+        // Write-then-rename, so a crash never leaves a short vector behind.
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, &bytes)?;
+        std::fs::rename(&tmp, &path)?;
+        Ok((bytes.len() as u64, replaced))
+    }
+
+    /// Deletes entries until the directory is back under three quarters of
+    /// the cap, so eviction doesn't run again on the very next insert.
+    fn evict(&self, state: &mut State) -> io::Result<()> {
+        let Some(dir) = &self.dir else {
+            return Ok(());
+        };
+        // 0 is the LRU's most recently used entry.
+        let recency: HashMap<blake3::Hash, usize> =
+            state.lru.iter().enumerate().map(|(rank, (key, _))| (*key, rank)).collect();
+        let mut files = entry_files(dir)?;
+        files.sort_by_key(|file| {
+            let rank = file.key.and_then(|key| recency.get(&key).copied());
+            (rank.map(Reverse), file.modified)
+        });
+
+        let target = self.disk_limit / 4 * 3;
+        let mut total: u64 = files.iter().map(|file| file.len).sum();
+        for file in files {
+            if total <= target {
+                break;
+            }
+            std::fs::remove_file(&file.path)?;
+            total -= file.len;
+        }
+        state.disk_bytes = total;
+        Ok(())
+    }
+}
+
+/// An engine plus the cache in front of it. Clones share both.
+#[derive(Clone)]
+pub struct CachedEmbedder {
+    engine: EmbeddingEngine,
+    cache: Arc<EmbeddingCache>,
+}
+
+impl CachedEmbedder {
+    pub fn new(engine: EmbeddingEngine, cache: Arc<EmbeddingCache>) -> Self {
+        Self { engine, cache }
+    }
+
+    pub fn engine(&self) -> &EmbeddingEngine {
+        &self.engine
+    }
+
+    pub fn cache(&self) -> &EmbeddingCache {
+        &self.cache
+    }
+
+    pub fn embed(&self, text: &str) -> Result<EmbeddingResult> {
+        let mut results = self.embed_batch(&[text.to_string()])?;
+        Ok(results.remove(0))
+    }
+
+    /// See `EmbeddingEngine::embed_query`.
+    pub fn embed_query(&self, text: &str) -> Result<EmbeddingResult> {
+        let mut result = self.embed(&self.engine.config().query_text(text))?;
+        result.text = text.to_string();
+        Ok(result)
+    }
+
+    /// See `EmbeddingEngine::embed_passages`.
+    pub fn embed_passages(&self, texts: &[String]) -> Result<Vec<EmbeddingResult>> {
+        let config = self.engine.config();
+        let prefixed: Vec<String> = texts.iter().map(|t| config.passage_text(t)).collect();
+        let mut results = self.embed_batch(&prefixed)?;
+        for (result, text) in results.iter_mut().zip(texts) {
+            result.text = text.clone();
+        }
+        Ok(results)
+    }
+
+    /// Cached texts are served from the memo; the rest go to the engine in a
+    /// single batch, each distinct text once.
+    pub fn embed_batch(&self, texts: &[String]) -> Result<Vec<EmbeddingResult>> {
+        // Rebinding on every call is what catches a model swapped underneath us.
+        self.cache.bind_model(self.engine.model_id())?;
+
+        let mut vectors: Vec<Option<Vec<f32>>> = texts.iter().map(|t| self.cache.get(t)).collect();
+        let mut pending: HashMap<String, Vec<usize>> = HashMap::new();
+        let mut order = Vec::new();
+        for (i, text) in texts.iter().enumerate() {
+            if vectors[i].is_none() {
+                let slots = pending.entry(normalise(text)).or_default();
+                if slots.is_empty() {
+                    order.push(text.clone());
+                }
+                slots.push(i);
+            }
+        }
+
+        for fresh in self.engine.embed_batch(&order)? {
+            self.cache.insert(&fresh.text, fresh.vector.clone());
+            for &i in &pending[&normalise(&fresh.text)] {
+                vectors[i] = Some(fresh.vector.clone());
+            }
+        }
+
+        let dimension = self.engine.embedding_dimension();
+        Ok(vectors
+            .into_iter()
+            .zip(texts)
+            .map(|(vector, text)| EmbeddingResult {
+                vector: vector.unwrap_or_default(),
+                dimension,
+                text: text.clone(),
+            })
+            .collect())
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+}
+
+/// Whitespace differences never change what a text means to the model.
+fn normalise(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn key(model_id: &str, text: &str) -> blake3::Hash {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(model_id.as_bytes());
+    hasher.update(&[0]);
+    hasher.update(normalise(text).as_bytes());
+    hasher.finalize()
+}
+
+struct EntryFile {
+    path: PathBuf,
+    /// `None` for a file name that isn't a key; those go first.
+    key: Option<blake3::Hash>,
+    len: u64,
+    modified: SystemTime,
+}
+
+fn entry_files(dir: &Path) -> io::Result<Vec<EntryFile>> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if path.extension().is_some_and(|ext| ext == EXTENSION) {
+            let meta = entry.metadata()?;
+            files.push(EntryFile {
+                key: path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .and_then(|stem| blake3::Hash::from_hex(stem).ok()),
+                len: meta.len(),
+                modified: meta.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+                path,
+            });
+        }
+    }
+    Ok(files)
+}
+
+fn remove_entries(dir: &Path) -> io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == EXTENSION) {
+            std::fs::remove_file(&path)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lru_then_disk_then_miss() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = EmbeddingCache::open(dir.path(), 1).unwrap();
+        cache.bind_model("bge").unwrap();
+
+        assert_eq!(cache.get("alpha"), None);
+        cache.insert("alpha", vec![1.0, 2.0]);
+        cache.insert("beta", vec![3.0]);
+        // "alpha" was evicted from the single-slot LRU but is still on disk.
+        assert_eq!(cache.get("  alpha\n"), Some(vec![1.0, 2.0]));
+        assert_eq!(cache.get("alpha"), Some(vec![1.0, 2.0]));
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.disk_hits, stats.misses), (1, 1, 1));
+        assert_eq!(stats.entries, 1);
+        assert!((stats.hit_rate() - 2.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_survives_reopen_but_not_model_change() {
+        let dir = tempfile::tempdir().unwrap();
+        {
+            let cache = EmbeddingCache::open(dir.path(), 8).unwrap();
+            cache.bind_model("bge").unwrap();
+            cache.insert("kept", vec![0.5]);
+        }
+
+        let cache = EmbeddingCache::open(dir.path(), 8).unwrap();
+        cache.bind_model("bge").unwrap();
+        assert_eq!(cache.get("kept"), Some(vec![0.5]));
+
+        cache.bind_model("nomic").unwrap();
+        assert_eq!(cache.get("kept"), None);
+        cache.bind_model("bge").unwrap();
+        assert_eq!(cache.get("kept"), None, "Switching models should wipe the disk store");
+    }
+
+    #[test]
+    fn test_unbound_cache_is_inert() {
+        let cache = EmbeddingCache::in_memory(8);
+        cache.insert("text", vec![1.0]);
+        assert_eq!(cache.get("text"), None);
+        assert_eq!(cache.stats(), CacheStats::default());
+    }
+
+    #[test]
+    fn test_disk_store_is_capped() {
+        let dir = tempfile::tempdir().unwrap();
+        // Ten 4-byte entries fit; the eleventh evicts down to seven.
+        let cache = EmbeddingCache::open(dir.path(), 2).unwrap().with_disk_limit(40);
+        cache.bind_model("bge").unwrap();
+        for i in 0..10 {
+            cache.insert(&format!("text {}", i), vec![i as f32]);
+        }
+        assert_eq!(cache.stats().disk_bytes, 40);
+        // Keep "text 0" hot; the LRU's entries are evicted last.
+        assert!(cache.get("text 0").is_some());
+
+        cache.insert("text 10", vec![10.0]);
+        assert_eq!(cache.stats().disk_bytes, 28);
+        assert_eq!(entry_files(dir.path()).unwrap().len(), 7);
+        assert_eq!(cache.get("text 10"), Some(vec![10.0]));
+        assert_eq!(cache.get("text 0"), Some(vec![0.0]));
+
+        let reopened = EmbeddingCache::open(dir.path(), 2).unwrap();
+        assert_eq!(reopened.stats().disk_bytes, 28);
+    }
+
+    #[test]
+    fn test_keys_separate_models() {
+        assert_eq!(key("bge", "a  b"), key("bge", " a b "));
+        assert_ne!(key("bge", "a b"), key("nomic", "a b"));
+        assert_ne!(key("bg", "ea b"), key("bge", "a b"));
+    }
+}
@@ -0,0 +1,137 @@
+//! How an `EmbeddingEngine` turns token states into one vector.
+//!
+//! Retrieval models are usually asymmetric: the query side gets an
+//! instruction prefix the passage side doesn't. Mixing the two up silently
+//! costs recall, so both prefixes live here next to the pooling mode.
+
+use serde::{Deserialize, Serialize};
+
+/// BGE v1.5's instruction for short queries against longer passages.
+pub const BGE_QUERY_PREFIX: &str = "Represent this sentence for searching relevant passages: ";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Pooling {
+    /// First token (`[CLS]`); BGE, most BERT retrievers.
+    Cls,
+    /// Average over all tokens; sentence-transformers, nomic.
+    Mean,
+    /// Final token; decoder-based embedders.
+    Last,
+}
+
+impl Pooling {
+    /// llama.cpp's `llama_pooling_type` value.
+    pub(crate) fn as_llama(self) -> i32 {
+        match self {
+            Pooling::Mean => 1,
+            Pooling::Cls => 2,
+            Pooling::Last => 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EmbeddingConfig {
+    pub pooling: Pooling,
+    /// Scale every vector to unit length, so cosine similarity is a dot product.
+    pub normalize: bool,
+    /// Prepended by `embed_query`.
+    pub query_prefix: String,
+    /// Prepended by `embed_passage`.
+    pub passage_prefix: String,
+}
+
+impl Default for EmbeddingConfig {
+    /// Settings for BGE v1.5, the bundled model.
+    fn default() -> Self {
+        Self {
+            pooling: Pooling::Cls,
+            normalize: true,
+            query_prefix: BGE_QUERY_PREFIX.to_string(),
+            passage_prefix: String::new(),
+        }
+    }
+}
+
+impl EmbeddingConfig {
+    /// Symmetric models: no prefixes on either side.
+    pub fn symmetric(pooling: Pooling) -> Self {
+        Self {
+            pooling,
+            normalize: true,
+            query_prefix: String::new(),
+            passage_prefix: String::new(),
+        }
+    }
+
+    pub fn with_pooling(mut self, pooling: Pooling) -> Self {
+        self.pooling = pooling;
+        self
+    }
+
+    pub fn with_normalize(mut self, normalize: bool) -> Self {
+        self.normalize = normalize;
+        self
+    }
+
+    pub fn with_prefixes(mut self, query: &str, passage: &str) -> Self {
+        self.query_prefix = query.to_string();
+        self.passage_prefix = passage.to_string();
+        self
+    }
+
+    pub fn query_text(&self, text: &str) -> String {
+        format!("{}{}", self.query_prefix, text)
+    }
+
+    pub fn passage_text(&self, text: &str) -> String {
+        format!("{}{}", self.passage_prefix, text)
+    }
+}
+
+/// Scales `vector` to unit L2 norm; all-zero vectors are left alone.
+pub fn l2_normalize(vector: &mut [f32]) {
+    let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > f32::EPSILON {
+        vector.iter_mut().for_each(|x| *x /= norm);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_is_bge() {
+        let config = EmbeddingConfig::default();
+        assert_eq!(config.pooling, Pooling::Cls);
+        assert!(config.normalize);
+        assert_eq!(
+            config.query_text("rust traits"),
+            "Represent this sentence for searching relevant passages: rust traits"
+        );
+        assert_eq!(config.passage_text("rust traits"), "rust traits");
+    }
+
+    #[test]
+    fn test_builders_and_serde() {
+        let config = EmbeddingConfig::symmetric(Pooling::Mean)
+            .with_prefixes("query: ", "passage: ")
+            .with_normalize(false);
+        assert_eq!(config.passage_text("x"), "passage: x");
+        let json = serde_json::to_string(&config).unwrap();
+        assert!(json.contains("\"pooling\":\"mean\""));
+        assert_eq!(serde_json::from_str::<EmbeddingConfig>(&json).unwrap(), config);
+    }
+
+    #[test]
+    fn test_l2_normalize() {
+        let mut v = [3.0, 4.0];
+        l2_normalize(&mut v);
+        assert_eq!(v, [0.6, 0.8]);
+        let mut zero = [0.0, 0.0];
+        l2_normalize(&mut zero);
+        assert_eq!(zero, [0.0, 0.0]);
+    }
+}
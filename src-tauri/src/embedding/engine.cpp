@@ -6,6 +6,13 @@
 #include <iostream>
 #include <vector>
 
+// Per-sequence limit, matching the 512-position window BERT-style encoders
+// are trained with; longer inputs are truncated.
+#define BGE_MAX_SEQ_TOKENS 512
+// One decode packs up to this many tokens / sequences.
+#define BGE_BATCH_TOKENS 4096
+#define BGE_MAX_SEQS 64
+
 /**
  * BGE Embedding Engine Implementation
  *
@@ -40,6 +47,31 @@ struct BGEEngine
 
 static bool backend_initialized = false;
 
+static bool tokenize_for_embedding(const llama_model *model, const char *text, std::vector<llama_token> &tokens)
+{
+    const int n_tokens = -llama_tokenize(model, text, strlen(text), nullptr, 0, true, true);
+    if (n_tokens <= 0)
+    {
+        return false;
+    }
+    tokens.resize(n_tokens);
+    if (llama_tokenize(model, text, strlen(text), tokens.data(), tokens.size(), true, true) < 0)
+    {
+        return false;
+    }
+    if (tokens.size() > BGE_MAX_SEQ_TOKENS)
+    {
+        // Keep the trailing [SEP] so the encoder still sees a closed sequence.
+        tokens.resize(BGE_MAX_SEQ_TOKENS);
+        const llama_token sep = llama_token_sep(model);
+        if (sep >= 0)
+        {
+            tokens.back() = sep;
+        }
+    }
+    return true;
+}
+
 void ensure_backend_initialized()
 {
     if (!backend_initialized)
@@ -53,6 +85,11 @@ extern "C"
 {
 
     void *bge_engine_create(const char *model_path)
+    {
+        return bge_engine_create_with_pooling(model_path, LLAMA_POOLING_TYPE_UNSPECIFIED);
+    }
+
+    void *bge_engine_create_with_pooling(const char *model_path, int pooling_type)
     {
         if (!model_path)
         {
@@ -76,9 +113,14 @@ extern "C"
         }
 
         llama_context_params ctx_params = llama_context_default_params();
-        ctx_params.n_ctx = 512; // BGE models typically use shorter contexts
+        // Room for a whole packed batch; each sequence still starts at position 0.
+        ctx_params.n_ctx = BGE_BATCH_TOKENS;
+        ctx_params.n_batch = BGE_BATCH_TOKENS;
+        ctx_params.n_ubatch = BGE_BATCH_TOKENS; // Non-causal: a sequence may not span ubatches
+        ctx_params.n_seq_max = BGE_MAX_SEQS;
         ctx_params.n_threads = 4;
         ctx_params.embeddings = true; // Critical: enable embedding mode
+        ctx_params.pooling_type = static_cast<enum llama_pooling_type>(pooling_type);
 
         engine->ctx = llama_new_context_with_model(engine->model, ctx_params);
         if (!engine->ctx)
@@ -107,10 +149,7 @@ extern "C"
         }
 
         std::vector<llama_token> tokens;
-        const int n_tokens = -llama_tokenize(engine->model, text, strlen(text), nullptr, 0, true, true);
-        tokens.resize(n_tokens);
-
-        if (llama_tokenize(engine->model, text, strlen(text), tokens.data(), tokens.size(), true, true) < 0)
+        if (!tokenize_for_embedding(engine->model, text, tokens))
         {
             std::cerr << "Tokenization failed - intelligence processing compromised." << std::endl;
             return nullptr;
@@ -143,6 +182,83 @@ extern "C"
         return result;
     }
 
+    int bge_engine_embed_batch(void *engine_ptr, const char **texts, int n_texts, float *out)
+    {
+        if (!engine_ptr || !texts || !out || n_texts < 0)
+        {
+            return -1;
+        }
+
+        auto *engine = static_cast<BGEEngine *>(engine_ptr);
+        if (!engine->is_loaded)
+        {
+            return -1;
+        }
+
+        std::vector<std::vector<llama_token>> inputs(n_texts);
+        for (int i = 0; i < n_texts; ++i)
+        {
+            if (!texts[i] || !tokenize_for_embedding(engine->model, texts[i], inputs[i]))
+            {
+                std::cerr << "Tokenization failed for batch item " << i << " - convoy halted." << std::endl;
+                return -1;
+            }
+        }
+
+        const int dim = engine->embedding_dim;
+        llama_batch batch = llama_batch_init(BGE_BATCH_TOKENS, 0, 1);
+
+        // Packs consecutive texts into one decode each, one sequence id per text.
+        int first = 0;
+        while (first < n_texts)
+        {
+            batch.n_tokens = 0;
+            int n_seqs = 0;
+            while (first + n_seqs < n_texts && n_seqs < BGE_MAX_SEQS)
+            {
+                const auto &tokens = inputs[first + n_seqs];
+                if (batch.n_tokens + static_cast<int>(tokens.size()) > BGE_BATCH_TOKENS)
+                {
+                    break;
+                }
+                for (size_t pos = 0; pos < tokens.size(); ++pos)
+                {
+                    const int n = batch.n_tokens++;
+                    batch.token[n] = tokens[pos];
+                    batch.pos[n] = static_cast<llama_pos>(pos);
+                    batch.n_seq_id[n] = 1;
+                    batch.seq_id[n][0] = n_seqs;
+                    batch.logits[n] = true;
+                }
+                ++n_seqs;
+            }
+
+            llama_kv_cache_clear(engine->ctx);
+            if (llama_decode(engine->ctx, batch) != 0)
+            {
+                std::cerr << "Batched decode failed - formation broken." << std::endl;
+                llama_batch_free(batch);
+                return -1;
+            }
+
+            for (int seq = 0; seq < n_seqs; ++seq)
+            {
+                const float *embedding = llama_get_embeddings_seq(engine->ctx, seq);
+                if (!embedding)
+                {
+                    std::cerr << "No pooled embedding for sequence " << seq << " - vector extraction failed." << std::endl;
+                    llama_batch_free(batch);
+                    return -1;
+                }
+                memcpy(out + static_cast<size_t>(first + seq) * dim, embedding, dim * sizeof(float));
+            }
+            first += n_seqs;
+        }
+
+        llama_batch_free(batch);
+        return n_texts;
+    }
+
     void bge_engine_destroy(void *engine_ptr)
     {
         if (engine_ptr)
@@ -15,8 +15,17 @@ extern "C"
      */
 
     void *bge_engine_create(const char *model_path);
+    /** `pooling_type` is a `llama_pooling_type`; -1 keeps the model's own. */
+    void *bge_engine_create_with_pooling(const char *model_path, int pooling_type);
     void bge_engine_destroy(void *engine);
     float *bge_engine_embed(void *engine, const char *text, int *embedding_size);
+
+    /**
+     * Embeds `n_texts` strings, packing as many as fit into each decode.
+     * `out` must hold `n_texts * embedding_dim` floats; row i is text i.
+     * Returns `n_texts` on success, -1 on failure.
+     */
+    int bge_engine_embed_batch(void *engine, const char **texts, int n_texts, float *out);
     void bge_free_embedding(float *embedding);
     int bge_engine_is_loaded(void *engine);
     int bge_engine_get_embedding_dim(void *engine);
@@ -11,13 +11,19 @@ use std::os::raw::{c_char, c_float, c_int, c_void};
 
 #[link(name = "llama")]
 extern "C" {
-    fn bge_engine_create(model_path: *const c_char) -> *mut c_void;
+    fn bge_engine_create_with_pooling(model_path: *const c_char, pooling_type: c_int) -> *mut c_void;
     fn bge_engine_destroy(engine: *mut c_void);
     fn bge_engine_embed(
         engine: *mut c_void,
         text: *const c_char,
         embedding_size: *mut c_int,
     ) -> *mut c_float;
+    fn bge_engine_embed_batch(
+        engine: *mut c_void,
+        texts: *const *const c_char,
+        n_texts: c_int,
+        out: *mut c_float,
+    ) -> c_int;
     fn bge_free_embedding(embedding: *mut c_float);
     fn bge_engine_is_loaded(engine: *mut c_void) -> c_int;
     fn bge_engine_get_embedding_dim(engine: *mut c_void) -> c_int;
@@ -29,9 +35,10 @@ pub struct RawEmbeddingEngine {
 }
 
 impl RawEmbeddingEngine {
-    pub unsafe fn new(model_path: &str) -> Option<Self> {
+    /// `pooling_type` is a `llama_pooling_type`; -1 keeps the model's own.
+    pub unsafe fn new(model_path: &str, pooling_type: i32) -> Option<Self> {
         let c_path = CString::new(model_path).ok()?;
-        let ptr = bge_engine_create(c_path.as_ptr());
+        let ptr = bge_engine_create_with_pooling(c_path.as_ptr(), pooling_type);
         if ptr.is_null() {
             None
         } else {
@@ -60,6 +67,35 @@ impl RawEmbeddingEngine {
         Some(embedding)
     }
 
+    /// One row per text, in input order, from a single call into the engine.
+    pub unsafe fn embed_batch(&self, texts: &[&str]) -> Option<Vec<Vec<f32>>> {
+        let dim = self.get_embedding_dim();
+        if dim == 0 {
+            return None;
+        }
+        if texts.is_empty() {
+            return Some(Vec::new());
+        }
+        let c_texts: Vec<CString> = texts
+            .iter()
+            .map(|text| CString::new(*text))
+            .collect::<Result<_, _>>()
+            .ok()?;
+        let ptrs: Vec<*const c_char> = c_texts.iter().map(|text| text.as_ptr()).collect();
+        let mut flat = vec![0.0f32; texts.len() * dim];
+
+        let written = bge_engine_embed_batch(
+            self.ptr,
+            ptrs.as_ptr(),
+            c_int::try_from(texts.len()).ok()?,
+            flat.as_mut_ptr(),
+        );
+        if written as usize != texts.len() {
+            return None;
+        }
+        Some(flat.chunks_exact(dim).map(<[f32]>::to_vec).collect())
+    }
+
     pub unsafe fn is_loaded(&self) -> bool {
         bge_engine_is_loaded(self.ptr) != 0
     }
@@ -1,10 +1,16 @@
 use std::path::Path;
 use std::sync::{Arc, Mutex};
 use thiserror::Error;
+use crate::registry::{ModelKind, ModelRegistry};
 
+pub mod cache;
+pub mod config;
 mod ffi;
 use ffi::RawEmbeddingEngine;
 
+pub use cache::{CacheStats, CachedEmbedder, EmbeddingCache};
+pub use config::{EmbeddingConfig, Pooling};
+
 /**
  * Safe Rust Wrapper for BGE Embedding Engine
  * 
@@ -25,6 +31,8 @@ pub enum EmbeddingError {
     ModelNotFound { path: String },
     #[error("Invalid input: {details}")]
     InvalidInput { details: String },
+    #[error("Embedding cache I/O failed: {0}")]
+    CacheIo(#[from] std::io::Error),
 }
 
 pub type Result<T> = std::result::Result<T, EmbeddingError>;
@@ -61,11 +69,18 @@ impl EmbeddingResult {
 pub struct EmbeddingEngine {
     inner: Arc<Mutex<Option<RawEmbeddingEngine>>>,
     model_path: String,
+    model_id: String,
     embedding_dim: usize,
+    config: EmbeddingConfig,
 }
 
 impl EmbeddingEngine {
+    /// Loads with `EmbeddingConfig::default()`, i.e. BGE settings.
     pub fn new<P: AsRef<Path>>(model_path: P) -> Result<Self> {
+        Self::with_config(model_path, EmbeddingConfig::default())
+    }
+    
+    pub fn with_config<P: AsRef<Path>>(model_path: P, config: EmbeddingConfig) -> Result<Self> {
         let path_str = model_path.as_ref().to_string_lossy().to_string();
         
         if !model_path.as_ref().exists() {
@@ -73,7 +88,7 @@ impl EmbeddingEngine {
         }
         
         let raw_engine = unsafe {
-            RawEmbeddingEngine::new(&path_str).ok_or_else(|| {
+            RawEmbeddingEngine::new(&path_str, config.pooling.as_llama()).ok_or_else(|| {
                 EmbeddingError::InitializationFailed {
                     model_path: path_str.clone(),
                 }
@@ -84,40 +99,94 @@ impl EmbeddingEngine {
         
         Ok(EmbeddingEngine {
             inner: Arc::new(Mutex::new(Some(raw_engine))),
+            model_id: fingerprint(model_path.as_ref(), &config),
             model_path: path_str,
             embedding_dim,
+            config,
         })
     }
     
+    pub fn config(&self) -> &EmbeddingConfig {
+        &self.config
+    }
+    
+    /// Embeds a search query, with the configured query prefix.
+    pub fn embed_query(&self, text: &str) -> Result<EmbeddingResult> {
+        let mut result = self.embed(&self.config.query_text(text))?;
+        result.text = text.to_string();
+        Ok(result)
+    }
+    
+    /// Embeds a text to be searched over, with the configured passage prefix.
+    pub fn embed_passage(&self, text: &str) -> Result<EmbeddingResult> {
+        let mut results = self.embed_passages(&[text.to_string()])?;
+        Ok(results.remove(0))
+    }
+    
+    pub fn embed_passages(&self, texts: &[String]) -> Result<Vec<EmbeddingResult>> {
+        let prefixed: Vec<String> = texts.iter().map(|t| self.config.passage_text(t)).collect();
+        let mut results = self.embed_batch(&prefixed)?;
+        for (result, text) in results.iter_mut().zip(texts) {
+            result.text = text.clone();
+        }
+        Ok(results)
+    }
+    
+    /// Embeds `text` as is, without any prefix.
     pub fn embed(&self, text: &str) -> Result<EmbeddingResult> {
-        if text.trim().is_empty() {
+        // Same path as batches, so a text embeds identically either way.
+        let mut results = self.embed_batch(&[text.to_string()])?;
+        Ok(results.remove(0))
+    }
+    
+    /// Embeds all `texts`, unprefixed, in one engine call; the engine packs
+    /// as many sequences as fit into each decode. Results are in input order.
+    pub fn embed_batch(&self, texts: &[String]) -> Result<Vec<EmbeddingResult>> {
+        if let Some(index) = texts.iter().position(|text| text.trim().is_empty()) {
             return Err(EmbeddingError::InvalidInput {
-                details: "Empty text provided".to_string(),
+                details: format!("Empty text provided at index {}", index),
             });
         }
+        if texts.is_empty() {
+            return Ok(Vec::new());
+        }
         
+        let refs: Vec<&str> = texts.iter().map(String::as_str).collect();
         let guard = self.inner.lock().unwrap();
-        match guard.as_ref() {
-            Some(engine) => {
-                let vector = unsafe { engine.embed(text) }
-                    .ok_or_else(|| EmbeddingError::EmbeddingFailed {
-                        reason: "Failed to extract embedding vector".to_string(),
-                    })?;
-                
-                Ok(EmbeddingResult {
+        let vectors = match guard.as_ref() {
+            Some(engine) => unsafe { engine.embed_batch(&refs) }
+                .ok_or_else(|| EmbeddingError::EmbeddingFailed {
+                    reason: format!("Failed to extract embeddings for {} texts", texts.len()),
+                })?,
+            None => return Err(EmbeddingError::EngineNotLoaded),
+        };
+        drop(guard);
+        
+        Ok(vectors
+            .into_iter()
+            .zip(texts)
+            .map(|(mut vector, text)| {
+                if self.config.normalize {
+                    config::l2_normalize(&mut vector);
+                }
+                EmbeddingResult {
                     vector,
                     dimension: self.embedding_dim,
-                    text: text.to_string(),
-                })
-            }
-            None => Err(EmbeddingError::EngineNotLoaded),
-        }
+                    text: text.clone(),
+                }
+            })
+            .collect())
     }
     
-    pub fn embed_batch(&self, texts: &[String]) -> Result<Vec<EmbeddingResult>> {
-        texts.iter()
-            .map(|text| self.embed(text))
-            .collect()
+    /// `embed_batch` on tokio's blocking pool, for async callers such as
+    /// cache flushes and document ingestion. The engine is a cheap clone.
+    pub async fn embed_batch_async(&self, texts: Vec<String>) -> Result<Vec<EmbeddingResult>> {
+        let engine = self.clone();
+        tokio::task::spawn_blocking(move || engine.embed_batch(&texts))
+            .await
+            .map_err(|e| EmbeddingError::EmbeddingFailed {
+                reason: format!("Embedding task failed: {}", e),
+            })?
     }
     
     pub fn is_loaded(&self) -> bool {
@@ -140,6 +209,13 @@ impl EmbeddingEngine {
         &self.model_path
     }
     
+    /// Identifies the vectors this engine produces: model path, size and
+    /// mtime plus pooling and normalisation, hashed. Replacing the file or
+    /// changing either setting yields a new id.
+    pub fn model_id(&self) -> &str {
+        &self.model_id
+    }
+    
     pub fn embedding_dimension(&self) -> usize {
         self.embedding_dim
     }
@@ -158,36 +234,76 @@ impl Clone for EmbeddingEngine {
         EmbeddingEngine {
             inner: Arc::clone(&self.inner),
             model_path: self.model_path.clone(),
+            model_id: self.model_id.clone(),
             embedding_dim: self.embedding_dim,
+            config: self.config.clone(),
         }
     }
 }
 
+fn fingerprint(path: &Path, config: &EmbeddingConfig) -> String {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(path.to_string_lossy().as_bytes());
+    hasher.update(&[config.pooling.as_llama() as u8, config.normalize as u8]);
+    if let Ok(meta) = std::fs::metadata(path) {
+        hasher.update(&meta.len().to_le_bytes());
+        let modified = meta
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_nanos());
+        hasher.update(&modified.to_le_bytes());
+    }
+    hasher.finalize().to_hex()[..16].to_string()
+}
+
 // Convenience constructors and methods
 impl EmbeddingEngine {
+    /// Loads the preferred embedding model under `ModelRegistry::default_dir()`.
     pub fn from_models_dir() -> Result<Self> {
-        let model_path = "llama/models/bge-small-en-v1.5-q8_0.gguf";
-        Self::new(model_path)
+        let dir = ModelRegistry::default_dir();
+        let not_found = || EmbeddingError::ModelNotFound {
+            path: dir.display().to_string(),
+        };
+        let registry = ModelRegistry::scan(&dir).map_err(|_| not_found())?;
+        let model = registry.preferred(ModelKind::Embedding).map_err(|_| not_found())?;
+        Self::new(&model.path)
     }
     
     pub fn encode(&self, text: &str) -> Result<Vec<f32>> {
         Ok(self.embed(text)?.vector)
     }
     
+    /// Ranks `documents` against `query`, embedding everything in one batch.
+    /// For a corpus that is searched repeatedly, embed it once and use `rank`.
     pub fn semantic_search(&self, query: &str, documents: &[String]) -> Result<Vec<(usize, f32)>> {
-        let query_embedding = self.embed(query)?;
-        let doc_embeddings: Result<Vec<_>> = documents.iter()
-            .map(|doc| self.embed(doc))
-            .collect();
+        let mut texts = Vec::with_capacity(documents.len() + 1);
+        texts.push(self.config.query_text(query));
+        texts.extend(documents.iter().map(|doc| self.config.passage_text(doc)));
         
-        let doc_embeddings = doc_embeddings?;
-        let mut similarities: Vec<(usize, f32)> = doc_embeddings.iter()
+        let mut embeddings = self.embed_batch(&texts)?;
+        let query_embedding = embeddings.remove(0);
+        Ok(Self::rank(&query_embedding, &embeddings))
+    }
+    
+    /// Indices of `documents` sorted by cosine similarity to `query`, best first.
+    pub fn rank(query: &EmbeddingResult, documents: &[EmbeddingResult]) -> Vec<(usize, f32)> {
+        #[cfg(feature = "parallel")]
+        let mut similarities: Vec<(usize, f32)> = {
+            use rayon::prelude::*;
+            documents.par_iter()
+                .enumerate()
+                .map(|(i, doc_emb)| (i, query.cosine_similarity(doc_emb)))
+                .collect()
+        };
+        #[cfg(not(feature = "parallel"))]
+        let mut similarities: Vec<(usize, f32)> = documents.iter()
             .enumerate()
-            .map(|(i, doc_emb)| (i, query_embedding.cosine_similarity(doc_emb)))
+            .map(|(i, doc_emb)| (i, query.cosine_similarity(doc_emb)))
             .collect();
         
         similarities.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
-        Ok(similarities)
+        similarities
     }
 }
 
@@ -226,4 +342,77 @@ mod tests {
         assert!(sim_similar > sim_different, "Similar sentences should have higher similarity");
         println!("Similar: {:.4}, Different: {:.4}", sim_similar, sim_different);
     }
+    
+    #[test]
+    fn test_batch_matches_single() {
+        let engine = EmbeddingEngine::from_models_dir().unwrap();
+        // More texts than fit in one decode, so packing is exercised too.
+        let texts: Vec<String> = (0..150)
+            .map(|i| format!("Sentence number {} about embedding throughput.", i))
+            .collect();
+        let batch = engine.embed_batch(&texts).unwrap();
+        assert_eq!(batch.len(), texts.len());
+        
+        for i in [0, 63, 64, 149] {
+            let single = engine.embed(&texts[i]).unwrap();
+            assert_eq!(batch[i].text, texts[i]);
+            assert!(batch[i].cosine_similarity(&single) > 0.999, "Row {} should match its single embedding", i);
+        }
+    }
+    
+    #[test]
+    fn test_query_and_passage_prefixes() {
+        let engine = EmbeddingEngine::from_models_dir().unwrap();
+        let query = engine.embed_query("how do rust lifetimes work").unwrap();
+        let passage = engine.embed_passage("how do rust lifetimes work").unwrap();
+        assert_eq!(query.text, passage.text);
+        assert_ne!(query.vector, passage.vector, "Query prefix should change the vector");
+        assert_eq!(passage.vector, engine.embed("how do rust lifetimes work").unwrap().vector);
+        
+        let norm: f32 = query.vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+        assert!((norm - 1.0).abs() < 1e-4, "Default config normalises vectors");
+    }
+    
+    #[test]
+    fn test_config_changes_model_id() {
+        let path = EmbeddingEngine::from_models_dir().unwrap().model_path().to_string();
+        let cls = EmbeddingEngine::new(&path).unwrap();
+        let mean = EmbeddingEngine::with_config(&path, EmbeddingConfig::default().with_pooling(Pooling::Mean)).unwrap();
+        assert_ne!(cls.model_id(), mean.model_id());
+        assert_eq!(mean.config().pooling, Pooling::Mean);
+    }
+    
+    #[test]
+    fn test_batch_rejects_blank_entries() {
+        let engine = EmbeddingEngine::from_models_dir().unwrap();
+        let texts = vec!["fine".to_string(), "  ".to_string()];
+        assert!(matches!(engine.embed_batch(&texts), Err(EmbeddingError::InvalidInput { .. })));
+        assert!(engine.embed_batch(&[]).unwrap().is_empty());
+    }
+    
+    #[test]
+    fn test_cached_embedder_reuses_vectors() {
+        let engine = EmbeddingEngine::from_models_dir().unwrap();
+        let embedder = CachedEmbedder::new(engine, Arc::new(EmbeddingCache::in_memory(16)));
+        let texts = vec!["a cat".to_string(), "a  cat".to_string(), "a dog".to_string()];
+        
+        let first = embedder.embed_batch(&texts).unwrap();
+        assert_eq!(first[0].vector, first[1].vector);
+        let second = embedder.embed_batch(&texts).unwrap();
+        assert_eq!(second[2].vector, first[2].vector);
+        
+        let stats = embedder.stats();
+        assert_eq!(stats.misses, 3);
+        assert_eq!(stats.hits, 3);
+        assert_eq!(stats.entries, 2);
+    }
+    
+    #[tokio::test]
+    async fn test_async_batch() {
+        let engine = EmbeddingEngine::from_models_dir().unwrap();
+        let texts = vec!["cats".to_string(), "dogs".to_string()];
+        let results = engine.embed_batch_async(texts).await.unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[1].vector.len(), engine.embedding_dimension());
+    }
 }
\ No newline at end of file
@@ -6,6 +6,7 @@ use crate::{
     retrieval::router::Router,
     types::{EngineState, Task},
 };
+use crate::llama::LlmBackend;
 use anyhow::Result;
 
 pub struct Orchestrator {
@@ -30,16 +31,15 @@ impl Orchestrator {
         }
     }
 
-    /// Drive one conversational turn.
-    pub async fn turn(&mut self, user_input: &str) -> Result<String> {
+    /// Drive one conversational turn, generating the reply with `llm`.
+    pub async fn turn(&mut self, user_input: &str, llm: &dyn LlmBackend) -> Result<String> {
         let task = parse_input(user_input);
 
         match task {
             Task::Command(cmd) => Ok(format!("[command: {}]", cmd)), // stub
             Task::Chat(text) => {
-                let prompt = self.builder.build(&text, 5).await?;
-                // TODO: feed prompt to LLM via llama::Engine
-                let reply = "LLM reply placeholder".to_string();
+                let prompt = self.builder.build(&text, 5, llm).await?;
+                let reply = llm.generate(&prompt, None)?;
 
                 update_state(&mut self.state, user_input, &reply);
                 Ok(reply)
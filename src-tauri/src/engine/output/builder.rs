@@ -2,13 +2,24 @@
 
 use crate::{
     formatter::format_results, injector::inject, schema::PromptPayload,
-    templates::persona_system,
+    traits::{DefaultStrategy, PromptStrategy},
 };
-use engine::retrieval::{query::SearchQuery, router::Router};
+use crate::llama::{ChatMessage, ChatTemplate, LlmBackend, MessageRole};
+use crate::preprocessing::Language;
+use engine::retrieval::{query::SearchQuery, result::SearchResult, router::Router};
+use std::sync::Arc;
+
+/// Tokens kept free for the reply when sizing the prompt.
+pub const DEFAULT_REPLY_RESERVE: usize = 512;
 
 pub struct PromptBuilder {
     router: Router,
     persona: String,
+    language: Language,
+    strategy: Arc<dyn PromptStrategy>,
+    /// Overrides the template of the backend passed to `build`.
+    template: Option<Arc<dyn ChatTemplate>>,
+    reply_reserve: usize,
 }
 
 impl PromptBuilder {
@@ -16,22 +27,95 @@ impl PromptBuilder {
         Self {
             router,
             persona: persona.to_string(),
+            language: Language::default(),
+            strategy: Arc::new(DefaultStrategy),
+            template: None,
+            reply_reserve: DEFAULT_REPLY_RESERVE,
         }
     }
 
+    /// Render with `template` whatever model `build` is given.
+    pub fn with_template(mut self, template: Arc<dyn ChatTemplate>) -> Self {
+        self.template = Some(template);
+        self
+    }
+
+    /// Language the reply must be written in; English unless set.
+    pub fn with_language(mut self, language: Language) -> Self {
+        self.language = language;
+        self
+    }
+
+    /// Mode-specific instructions; plain persona prompt unless set.
+    pub fn with_strategy(mut self, strategy: Arc<dyn PromptStrategy>) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Tokens of the context window left over for generation.
+    pub fn with_reply_reserve(mut self, tokens: usize) -> Self {
+        self.reply_reserve = tokens;
+        self
+    }
+
     /// Build the final prompt for the LLM.
-    pub async fn build(&self, user_text: &str, top_k: usize) -> anyhow::Result<String> {
+    ///
+    /// The prompt is rendered in `llm`'s chat format, unless `with_template`
+    /// picked another, and measured with its tokenizer. While it overflows
+    /// `context_length - reply_reserve`, retrieved lines are dropped, the
+    /// lowest-ranked first: web, then cache, then memory.
+    pub async fn build(
+        &self,
+        user_text: &str,
+        top_k: usize,
+        llm: &dyn LlmBackend,
+    ) -> anyhow::Result<String> {
         let query = SearchQuery::new(user_text, top_k);
         let all_results = self.router.search(&query).await?;
 
         // Split by source
-        let (memory, rest): (Vec<_>, Vec<_>) = all_results
+        let (mut memory, rest): (Vec<_>, Vec<_>) = all_results
             .into_iter()
             .partition(|r| r.source == "memory");
-        let (cache, web): (Vec<_>, Vec<_>) = rest.into_iter().partition(|r| r.source == "cache");
+        let (mut cache, mut web): (Vec<_>, Vec<_>) =
+            rest.into_iter().partition(|r| r.source == "cache");
+
+        let template = self.template.clone().unwrap_or_else(|| llm.template());
+        let budget = llm.context_length().saturating_sub(self.reply_reserve);
+        loop {
+            let prompt = self.render(template.as_ref(), user_text, &memory, &cache, &web);
+            let used = llm.count_tokens(&prompt)?;
+            if used <= budget {
+                return Ok(prompt);
+            }
+            let trimmed = [&mut web, &mut cache, &mut memory]
+                .into_iter()
+                .find(|results| !results.is_empty())
+                .and_then(|results| results.pop());
+            if trimmed.is_none() {
+                anyhow::bail!(
+                    "Prompt needs {} tokens but only {} fit in the context window",
+                    used,
+                    budget
+                );
+            }
+        }
+    }
 
-        let system = persona_system(&self.persona);
-        let payload = format_results(system, memory, cache, web);
-        Ok(inject(payload))
+    fn render(
+        &self,
+        template: &dyn ChatTemplate,
+        user_text: &str,
+        memory: &[SearchResult],
+        cache: &[SearchResult],
+        web: &[SearchResult],
+    ) -> String {
+        let system = self.strategy.system_msg(&self.persona, self.language);
+        let payload = format_results(system, memory.to_vec(), cache.to_vec(), web.to_vec());
+        let messages = [
+            ChatMessage { role: MessageRole::System, content: inject(payload) },
+            ChatMessage { role: MessageRole::User, content: user_text.to_string() },
+        ];
+        template.render(&messages)
     }
-}
\ No newline at end of file
+}
@@ -4,5 +4,6 @@ pub use builder::*;
 pub use formatter::*;
 pub use injector::*;
 pub use schema::*;
+pub use strategies::*;
 pub use templates::*;
 pub use traits::*;
\ No newline at end of file
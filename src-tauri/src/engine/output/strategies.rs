@@ -0,0 +1,130 @@
+//! Per-mode prompt strategies.
+//!
+//! Tutor and Assistant get by on the persona prompt alone. Translator, Code
+//! Reviewer and Quiz each ask for a fixed layout, which `postprocessing`
+//! parses back out of the reply, so the labels below must stay in step with
+//! the parsers in `postprocessing::shaping`.
+
+use crate::preprocessing::{FormattedInput, Language, Mode};
+use crate::traits::{DefaultStrategy, PromptStrategy};
+use std::sync::Arc;
+
+/// Translates the learner's text, with alternatives and a note on register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TranslatorStrategy {
+    /// What the learner wrote in, when it could be told.
+    pub source: Option<Language>,
+    /// `None` leaves the direction to the model.
+    pub target: Option<Language>,
+}
+
+impl TranslatorStrategy {
+    /// Translates into the language being studied, or back into the
+    /// interface language when the learner already wrote in the former.
+    pub fn for_input(source: Option<Language>, studied: Option<Language>, ui: Language) -> Self {
+        let target = [studied, Some(ui)]
+            .into_iter()
+            .flatten()
+            .find(|candidate| Some(*candidate) != source);
+        Self { source, target }
+    }
+}
+
+impl PromptStrategy for TranslatorStrategy {
+    fn instructions(&self) -> Option<String> {
+        let direction = match (self.source, self.target) {
+            (Some(source), Some(target)) => format!("from {} into {}", source.name(), target.name()),
+            (None, Some(target)) => format!("into {}", target.name()),
+            _ => "into the language the user asks for".to_string(),
+        };
+        Some(format!(
+            "You are a translator. Translate the user's text {}; if they name \
+             other languages, use those instead.\n\
+             Answer in exactly this layout:\n\
+             Translation: <the most natural translation>\n\
+             Alternatives:\n\
+             - <another phrasing> — <when to prefer it>\n\
+             Register: <formal, neutral or informal, and anything to watch for>",
+            direction
+        ))
+    }
+}
+
+/// Reviews a pasted snippet as a list of findings.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct CodeReviewStrategy {
+    /// Taken from the fence tag or the segmenter's guess.
+    pub code_language: Option<String>,
+}
+
+impl PromptStrategy for CodeReviewStrategy {
+    fn instructions(&self) -> Option<String> {
+        let subject = match &self.code_language {
+            Some(language) => format!("the user's {} code", language),
+            None => "the user's code".to_string(),
+        };
+        Some(format!(
+            "You are a code reviewer. Review {} for bugs, unsafe patterns, \
+             readability and idiom. If no code was pasted, ask for the snippet.\n\
+             List one finding per line, most serious first:\n\
+             [critical|warning|suggestion] line <n>: <problem> — <fix>\n\
+             Leave out the line number when a finding is about the whole snippet, \
+             and end with one line:\n\
+             Summary: <overall verdict>",
+            subject
+        ))
+    }
+}
+
+/// Asks a question on a topic, or grades the answer to the last one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QuizStrategy {
+    Ask { topic: String },
+    Grade { question: String, expected: String },
+}
+
+impl PromptStrategy for QuizStrategy {
+    fn instructions(&self) -> Option<String> {
+        Some(match self {
+            QuizStrategy::Ask { topic } => format!(
+                "You are a quiz master. Ask the user one question about {}, pitched \
+                 at their level. Answer in exactly this layout:\n\
+                 Question: <the question>\n\
+                 A) <choice> (up to four choices, only for multiple choice)\n\
+                 Answer: <the correct answer>\n\
+                 Explanation: <why it is correct>\n\
+                 The answer and explanation are hidden from the user until they reply.",
+                topic
+            ),
+            QuizStrategy::Grade { question, expected } => format!(
+                "You are a quiz master grading the user's answer.\n\
+                 Question: {}\n\
+                 Expected answer: {}\n\
+                 Answer in exactly this layout:\n\
+                 Verdict: correct, partially correct or incorrect\n\
+                 Feedback: <what was right, what was missing, and the correct answer>",
+                question, expected
+            ),
+        })
+    }
+}
+
+/// The strategy for `formatted.mode`. Quiz mode always asks here; grading
+/// needs the pending question, which only the conversation state knows.
+pub fn strategy_for(formatted: &FormattedInput, ui: Language) -> Arc<dyn PromptStrategy> {
+    match formatted.mode {
+        Mode::Translator => Arc::new(TranslatorStrategy::for_input(
+            formatted.context.detected_language.as_ref().map(|guess| guess.language),
+            Language::from_name(&formatted.context.domain),
+            ui,
+        )),
+        Mode::CodeReview => Arc::new(CodeReviewStrategy {
+            code_language: formatted
+                .code_segments()
+                .find_map(|segment| segment.language())
+                .map(str::to_string),
+        }),
+        Mode::Quiz => Arc::new(QuizStrategy::Ask { topic: formatted.context.topic.clone() }),
+        Mode::Tutor | Mode::Assistant => Arc::new(DefaultStrategy),
+    }
+}
@@ -1,13 +1,16 @@
 //! Re-usable prompt skeletons.
 
+use crate::preprocessing::Language;
+
 pub fn base_system() -> &'static str {
     r#"You are a helpful assistant. Use the following context to answer the user."#
 }
 
-pub fn persona_system(persona: &str) -> String {
+pub fn persona_system(persona: &str, language: Language) -> String {
     format!(
-        "{}\n\nPersona: {}",
+        "{}\n\nPersona: {}\n\n{}",
         base_system(),
-        persona
+        persona,
+        language.reply_instruction()
     )
 }
\ No newline at end of file
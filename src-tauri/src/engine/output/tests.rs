@@ -15,4 +15,17 @@ mod tests {
         let prompt = inject(payload);
         assert!(prompt.contains("m1"));
     }
+
+    #[test]
+    fn translator_picks_direction() {
+        use crate::preprocessing::Language::{English, French};
+
+        let to_french = TranslatorStrategy::for_input(Some(English), Some(French), English);
+        assert_eq!(to_french.target, Some(French));
+        let back = TranslatorStrategy::for_input(Some(French), Some(French), English);
+        assert_eq!(back.target, Some(English));
+        let open = TranslatorStrategy::for_input(Some(English), None, English);
+        assert_eq!(open.target, None);
+        assert!(open.instructions().unwrap().contains("the language the user asks for"));
+    }
 }
\ No newline at end of file
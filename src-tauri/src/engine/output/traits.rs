@@ -1,13 +1,24 @@
-//! Pluggable prompt strategies (placeholder).
+//! Pluggable prompt strategies.
+
+use crate::preprocessing::Language;
+use crate::templates::persona_system;
 
 pub trait PromptStrategy: Send + Sync {
-    fn system_msg(&self, persona: &str) -> String;
+    /// What the mode asks of the model on top of the persona; `None` for
+    /// plain conversation.
+    fn instructions(&self) -> Option<String> {
+        None
+    }
+
+    fn system_msg(&self, persona: &str, language: Language) -> String {
+        let system = persona_system(persona, language);
+        match self.instructions() {
+            Some(instructions) => format!("{}\n\n{}", system, instructions),
+            None => system,
+        }
+    }
 }
 
 pub struct DefaultStrategy;
 
-impl PromptStrategy for DefaultStrategy {
-    fn system_msg(&self, persona: &str) -> String {
-        crate::templates::persona_system(persona)
-    }
-}
\ No newline at end of file
+impl PromptStrategy for DefaultStrategy {}
\ No newline at end of file
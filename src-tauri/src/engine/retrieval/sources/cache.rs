@@ -4,14 +4,19 @@ use super::Source;
 use super::super::result::SearchResult;
 use async_trait::async_trait;
 use cache::Cache; // thin wrapper around cache::Cache
+use embedding::CachedEmbedder;
 
 pub struct CacheSource {
     cache: Cache,
+    embedder: CachedEmbedder,
 }
 
 impl CacheSource {
-    pub fn new(cache: Cache) -> Self {
-        Self { cache }
+    /// Buffered entries (as passages) and queries are embedded through
+    /// `embedder`, so each text is only embedded once however often it's
+    /// searched.
+    pub fn new(cache: Cache, embedder: CachedEmbedder) -> Self {
+        Self { cache, embedder }
     }
 }
 
@@ -21,27 +26,28 @@ impl Source for CacheSource {
 
     async fn search(&self, query: &str, top_k: usize) -> anyhow::Result<Vec<SearchResult>> {
         // TODO: naive linear scan for now; later add ANN on cache vectors
-        let guard = self.cache.inner.lock().unwrap();
-        let mut hits = Vec::new();
-        for entry in &guard.buffer {
-            let input_vec  = embedding::Engine::new().embed(&entry.input.content);
-            let output_vec = embedding::Engine::new().embed(&entry.output.content);
-            let q_vec      = embedding::Engine::new().embed(query);
+        let mut texts = Vec::new();
+        {
+            let guard = self.cache.inner.lock().unwrap();
+            for entry in &guard.buffer {
+                texts.push(entry.input.content.clone());
+                texts.push(entry.output.content.clone());
+            }
+        }
+        if texts.is_empty() {
+            return Ok(Vec::new());
+        }
 
-            let input_score  = cosine(&q_vec, &input_vec);
-            let output_score = cosine(&q_vec, &output_vec);
+        let q_vec = self.embedder.embed_query(query)?.vector;
+        let vectors = self.embedder.embed_passages(&texts)?.into_iter().map(|e| e.vector);
 
-            if input_score > 0.0 {
-                hits.push(SearchResult {
-                    score: input_score,
-                    content: entry.input.content.clone(),
-                    source: self.name(),
-                });
-            }
-            if output_score > 0.0 {
+        let mut hits = Vec::new();
+        for (content, vec) in texts.into_iter().zip(vectors) {
+            let score = cosine(&q_vec, &vec);
+            if score > 0.0 {
                 hits.push(SearchResult {
-                    score: output_score,
-                    content: entry.output.content.clone(),
+                    score,
+                    content,
                     source: self.name(),
                 });
             }
@@ -3,20 +3,25 @@
 use super::Source;
 use super::super::result::SearchResult;
 use async_trait::async_trait;
+use embedding::CachedEmbedder;
 use memory::{Searcher, Store};
 
 pub struct MemorySource {
     searcher: Searcher,
+    embedder: CachedEmbedder,
 }
 
 impl MemorySource {
-    pub fn new() -> anyhow::Result<Self> {
+    /// Stored records are passages, so queries go through `embed_query`.
+    /// In the app, `embedder` comes from `ActiveModels::embedder`, so queries
+    /// share the memo the cache flusher fills.
+    pub fn new(embedder: CachedEmbedder) -> anyhow::Result<Self> {
         let store = Store::open("memory.vec")?;
         let mmap  = store.mmap()?; // read-only mmap
         // dimension discovery: read header or use 384 (bge-small)
         let dim = 384;
         let searcher = Searcher::new(mmap, dim);
-        Ok(Self { searcher })
+        Ok(Self { searcher, embedder })
     }
 }
 
@@ -25,7 +30,7 @@ impl Source for MemorySource {
     fn name(&self) -> &'static str { "memory" }
 
     async fn search(&self, query: &str, top_k: usize) -> anyhow::Result<Vec<SearchResult>> {
-        let q_vec = embedding::Engine::new().embed(query);
+        let q_vec = self.embedder.embed_query(query)?.vector;
         let hits = self.searcher.search(&q_vec, top_k);
         Ok(hits
             .into_iter()
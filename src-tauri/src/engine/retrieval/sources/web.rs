@@ -1,20 +1,30 @@
 //! Optional DuckDuckGo instant-answer search as fallback.
+//!
+//! Queries leave the machine, so they are redacted for the web sink first.
 
 use super::Source;
 use super::super::result::SearchResult;
+use crate::preprocessing::{Redactor, Sink};
 use async_trait::async_trait;
 use reqwest::Client;
 
 pub struct WebSource {
     client: Client,
+    redactor: Redactor,
 }
 
 impl WebSource {
     pub fn new() -> Self {
         Self {
             client: Client::new(),
+            redactor: Redactor::default(),
         }
     }
+
+    pub fn with_redactor(mut self, redactor: Redactor) -> Self {
+        self.redactor = redactor;
+        self
+    }
 }
 
 #[async_trait]
@@ -25,7 +35,7 @@ impl Source for WebSource {
         // DuckDuckGo instant answer API — no key required
         let url = format!(
             "https://api.duckduckgo.com/?q={}&format=json&no_html=1&skip_disambig=1",
-            urlencoding::encode(query)
+            urlencoding::encode(&self.redactor.scrub(Sink::Web, query))
         );
         let resp: serde_json::Value = self.client.get(&url).send().await?.json().await?;
         let mut results = Vec::new();
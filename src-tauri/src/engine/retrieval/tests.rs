@@ -2,11 +2,18 @@
 mod tests {
     use super::*;
     use crate::sources::{cache::CacheSource, memory::MemorySource, web::WebSource};
+    use embedding::{CachedEmbedder, EmbeddingCache, EmbeddingEngine};
+    use std::sync::Arc;
+
+    fn embedder() -> CachedEmbedder {
+        let engine = EmbeddingEngine::from_models_dir().unwrap();
+        CachedEmbedder::new(engine, Arc::new(EmbeddingCache::in_memory(64)))
+    }
 
     #[tokio::test]
     async fn router_flow() {
-        let cache  = CacheSource::new(cache::Cache::new(100, 60));
-        let memory = MemorySource::new().unwrap();
+        let cache  = CacheSource::new(cache::Cache::new(100, 60), embedder());
+        let memory = MemorySource::new(embedder()).unwrap();
         let web    = WebSource::new();
 
         let router = Router::new(cache, memory, web);
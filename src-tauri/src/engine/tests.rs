@@ -2,19 +2,79 @@
 mod tests {
     use super::*;
     use crate::{output::builder::PromptBuilder, retrieval::router::Router};
+    use crate::llama::{LlmBackend, MockBackend, TemplateKind};
+    use crate::preprocessing::Language;
     use engine::retrieval::sources::{cache::CacheSource, memory::MemorySource, web::WebSource};
+    use embedding::{CachedEmbedder, EmbeddingCache, EmbeddingEngine};
+    use std::sync::Arc;
+
+    fn embedder() -> CachedEmbedder {
+        let engine = EmbeddingEngine::from_models_dir().unwrap();
+        CachedEmbedder::new(engine, Arc::new(EmbeddingCache::in_memory(64)))
+    }
+
+    fn router() -> Router {
+        let cache = CacheSource::new(cache::Cache::new(10, 60), embedder());
+        Router::new(cache, MemorySource::new(embedder()).unwrap(), WebSource::new())
+    }
 
     #[tokio::test]
     async fn end_to_end_turn() {
-        let cache = CacheSource::new(cache::Cache::new(10, 60));
-        let memory = MemorySource::new().unwrap();
-        let web = WebSource::new();
-
-        let router = Router::new(cache, memory, web);
+        let router = router();
         let builder = PromptBuilder::new(router.clone(), "test");
         let mut orch = Orchestrator::new(router, builder, "test");
 
-        let reply = orch.turn("hello").await.unwrap();
-        assert!(!reply.is_empty());
+        let llm = MockBackend::scripted(["hi there"]);
+        let reply = orch.turn("hello", &llm).await.unwrap();
+        assert_eq!(reply, "hi there");
+        assert!(llm.prompts()[0].contains("hello"));
+    }
+
+    #[tokio::test]
+    async fn prompt_must_fit_context_window() {
+        let router = router();
+        let llm = MockBackend::echo();
+
+        let roomy = PromptBuilder::new(router.clone(), "test");
+        assert!(roomy.build("hello", 5, &llm).await.is_ok());
+
+        let cramped = PromptBuilder::new(router, "test").with_reply_reserve(llm.context_length());
+        assert!(cramped.build("hello", 5, &llm).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn prompt_asks_for_selected_language() {
+        let router = router();
+        let llm = MockBackend::echo();
+
+        let builder = PromptBuilder::new(router, "test").with_language(Language::Spanish);
+        let prompt = builder.build("hello", 5, &llm).await.unwrap();
+        assert!(prompt.contains("replies in Spanish"));
+    }
+
+    #[tokio::test]
+    async fn prompt_uses_backend_template() {
+        let llm = MockBackend::echo().with_template(TemplateKind::Gemma);
+        let prompt = PromptBuilder::new(router(), "test").build("hello", 5, &llm).await.unwrap();
+        assert!(prompt.ends_with("<start_of_turn>model\n"));
+        assert!(!prompt.contains("<|im_start|>"));
+
+        let forced = PromptBuilder::new(router(), "test").with_template(TemplateKind::Phi.template());
+        let prompt = forced.build("hello", 5, &llm).await.unwrap();
+        assert!(prompt.ends_with("<|assistant|>\n"));
+    }
+
+    #[tokio::test]
+    async fn prompt_carries_mode_strategy() {
+        use crate::output::strategies::QuizStrategy;
+
+        let router = router();
+        let llm = MockBackend::echo();
+
+        let strategy = QuizStrategy::Ask { topic: "borrowing".into() };
+        let builder = PromptBuilder::new(router, "test").with_strategy(Arc::new(strategy));
+        let prompt = builder.build("quiz me", 5, &llm).await.unwrap();
+        assert!(prompt.contains("one question about borrowing"));
+        assert!(prompt.contains("Persona: test"));
     }
 }
\ No newline at end of file
@@ -0,0 +1,270 @@
+//! Per-domain estimate of how proficient the learner really is.
+//!
+//! The welcome screen asks once, for everything. People are rarely equally
+//! good at Rust and French, and they improve, so every exchange nudges a
+//! per-domain estimate towards what its `Signals` suggest. The estimate
+//! starts at the level the learner picked and moves fast at first, then
+//! settles. Once enough turns back it up, a clearly different level is
+//! suggested, or applied when auto-adjust is on.
+//!
+//! Estimates persist as JSON next to the rest of the app data, rewritten
+//! after every observation.
+
+pub mod signals;
+
+pub use signals::Signals;
+
+use crate::preprocessing::{FormattedInput, Proficiency};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Turns needed before the estimate is trusted over the learner's own pick.
+pub const MIN_OBSERVATIONS: u32 = 5;
+
+/// How far, in levels, the estimate must drift before a change is suggested.
+/// Keeps a learner sitting between two levels from flip-flopping.
+pub const SUGGEST_MARGIN: f32 = 0.75;
+
+/// Floor on the moving-average weight, so old habits still fade.
+const MIN_WEIGHT: f32 = 0.1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DomainEstimate {
+    /// 0.0 (`Beginner`) to 3.0 (`Expert`).
+    pub level: f32,
+    pub observations: u32,
+    /// Unix seconds of the last observation.
+    pub updated_at: i64,
+}
+
+impl DomainEstimate {
+    pub fn proficiency(&self) -> Proficiency {
+        Proficiency::from_rank(self.level.round().clamp(0.0, 3.0) as u8)
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Profile {
+    #[serde(default)]
+    auto_adjust: bool,
+    #[serde(default)]
+    domains: HashMap<String, DomainEstimate>,
+}
+
+pub struct LearnerModel {
+    path: Option<PathBuf>,
+    profile: Mutex<Profile>,
+}
+
+impl LearnerModel {
+    /// Loads estimates from `path`; a missing file starts a fresh profile.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let profile = match std::fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Profile::default(),
+            Err(e) => return Err(e),
+        };
+        Ok(Self {
+            path: Some(path),
+            profile: Mutex::new(profile),
+        })
+    }
+
+    /// Nothing touches the disk.
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            profile: Mutex::new(Profile::default()),
+        }
+    }
+
+    pub fn auto_adjust(&self) -> bool {
+        self.profile.lock().unwrap().auto_adjust
+    }
+
+    pub fn set_auto_adjust(&self, enabled: bool) -> io::Result<()> {
+        let mut profile = self.profile.lock().unwrap();
+        profile.auto_adjust = enabled;
+        self.save(&profile)
+    }
+
+    pub fn estimate(&self, domain: &str) -> Option<DomainEstimate> {
+        self.profile.lock().unwrap().domains.get(&key(domain)).copied()
+    }
+
+    /// Every domain seen so far, most recently observed first.
+    pub fn estimates(&self) -> Vec<(String, DomainEstimate)> {
+        let mut all: Vec<_> = self
+            .profile
+            .lock()
+            .unwrap()
+            .domains
+            .iter()
+            .map(|(domain, estimate)| (domain.clone(), *estimate))
+            .collect();
+        all.sort_by(|a, b| b.1.updated_at.cmp(&a.1.updated_at).then_with(|| a.0.cmp(&b.0)));
+        all
+    }
+
+    /// A different level for `domain` than `selected`, once enough turns
+    /// back it up and the estimate has drifted clearly away.
+    pub fn suggest(&self, domain: &str, selected: Proficiency) -> Option<Proficiency> {
+        let estimate = self.estimate(domain)?;
+        if estimate.observations < MIN_OBSERVATIONS {
+            return None;
+        }
+        if (estimate.level - selected.rank() as f32).abs() < SUGGEST_MARGIN {
+            return None;
+        }
+        Some(estimate.proficiency()).filter(|suggested| *suggested != selected)
+    }
+
+    /// The proficiency to answer `domain` questions at: the suggestion when
+    /// auto-adjust is on, otherwise whatever the learner picked.
+    pub fn adjust(&self, domain: &str, selected: Proficiency) -> Proficiency {
+        if !self.auto_adjust() {
+            return selected;
+        }
+        self.suggest(domain, selected).unwrap_or(selected)
+    }
+
+    /// Folds one finished exchange into the estimate for its domain.
+    pub fn observe(&self, formatted: &FormattedInput, reply: &str) -> io::Result<DomainEstimate> {
+        let signals = Signals::from_turn(formatted, reply);
+        self.record(&formatted.context.domain, formatted.proficiency, &signals)
+    }
+
+    /// Moves the estimate for `domain` towards `signals`, starting from
+    /// `prior` the first time the domain comes up.
+    pub fn record(&self, domain: &str, prior: Proficiency, signals: &Signals) -> io::Result<DomainEstimate> {
+        let mut profile = self.profile.lock().unwrap();
+        let estimate = profile.domains.entry(key(domain)).or_insert(DomainEstimate {
+            level: prior.rank() as f32,
+            observations: 0,
+            updated_at: 0,
+        });
+        // The prior counts as one observation, so the first real one moves
+        // the estimate halfway.
+        let weight = (1.0 / (estimate.observations as f32 + 2.0)).max(MIN_WEIGHT);
+        estimate.level += weight * (signals.observed_level() - estimate.level);
+        estimate.observations += 1;
+        estimate.updated_at = now();
+        let updated = *estimate;
+        self.save(&profile)?;
+        Ok(updated)
+    }
+
+    /// Forgets every estimate; the auto-adjust setting is kept.
+    pub fn reset(&self) -> io::Result<()> {
+        let mut profile = self.profile.lock().unwrap();
+        profile.domains.clear();
+        self.save(&profile)
+    }
+
+    fn save(&self, profile: &Profile) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_string_pretty(profile)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        // Write-then-rename, so a crash never leaves half a profile behind.
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, json)?;
+        std::fs::rename(&tmp, path)
+    }
+}
+
+/// "Rust" and " rust " are the same domain.
+fn key(domain: &str) -> String {
+    domain.trim().to_lowercase()
+}
+
+fn now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strong() -> Signals {
+        Signals { complexity: 0.8, vocabulary: Some(0.9), error_rate: Some(0.0) }
+    }
+
+    fn weak() -> Signals {
+        Signals { complexity: 0.1, vocabulary: Some(0.1), error_rate: Some(1.0) }
+    }
+
+    #[test]
+    fn test_estimates_are_per_domain() {
+        let learner = LearnerModel::in_memory();
+        for _ in 0..MIN_OBSERVATIONS {
+            learner.record("Rust", Proficiency::Intermediate, &strong()).unwrap();
+            learner.record("French", Proficiency::Intermediate, &weak()).unwrap();
+        }
+
+        let rust = learner.estimate("rust").unwrap();
+        let french = learner.estimate(" French ").unwrap();
+        assert_eq!(rust.observations, MIN_OBSERVATIONS);
+        assert!(rust.level > 2.0, "{:?}", rust);
+        assert!(french.level < 1.0, "{:?}", french);
+        assert_eq!(learner.estimates().len(), 2);
+    }
+
+    #[test]
+    fn test_suggestion_needs_evidence_and_margin() {
+        let learner = LearnerModel::in_memory();
+        learner.record("rust", Proficiency::Beginner, &strong()).unwrap();
+        assert_eq!(learner.suggest("rust", Proficiency::Beginner), None);
+
+        for _ in 1..MIN_OBSERVATIONS {
+            learner.record("rust", Proficiency::Beginner, &strong()).unwrap();
+        }
+        let suggested = learner.suggest("rust", Proficiency::Beginner).unwrap();
+        assert!(suggested.rank() >= Proficiency::Advanced.rank());
+        // Already at the estimated level: nothing to suggest.
+        assert_eq!(learner.suggest("rust", suggested), None);
+        assert_eq!(learner.suggest("cobol", Proficiency::Beginner), None);
+    }
+
+    #[test]
+    fn test_adjust_only_when_enabled() {
+        let learner = LearnerModel::in_memory();
+        for _ in 0..MIN_OBSERVATIONS {
+            learner.record("rust", Proficiency::Expert, &weak()).unwrap();
+        }
+        assert_eq!(learner.adjust("rust", Proficiency::Expert), Proficiency::Expert);
+
+        learner.set_auto_adjust(true).unwrap();
+        assert!(learner.adjust("rust", Proficiency::Expert).rank() < Proficiency::Expert.rank());
+        assert_eq!(learner.adjust("french", Proficiency::Expert), Proficiency::Expert);
+    }
+
+    #[test]
+    fn test_persists_across_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("learner.json");
+        {
+            let learner = LearnerModel::open(&path).unwrap();
+            learner.set_auto_adjust(true).unwrap();
+            learner.record("rust", Proficiency::Advanced, &strong()).unwrap();
+        }
+
+        let learner = LearnerModel::open(&path).unwrap();
+        assert!(learner.auto_adjust());
+        assert_eq!(learner.estimate("rust").unwrap().observations, 1);
+
+        learner.reset().unwrap();
+        assert!(LearnerModel::open(&path).unwrap().estimate("rust").is_none());
+    }
+}
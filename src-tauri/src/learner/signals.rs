@@ -0,0 +1,133 @@
+//! What one exchange says about the learner.
+//!
+//! Three signals, each scaled to 0.0–1.0: how complex the question was, how
+//! rich its vocabulary is, and, in tutor mode, how much of it the tutor had
+//! to correct. None of them is reliable alone; `LearnerModel` only moves its
+//! estimate a little per turn.
+
+use crate::preprocessing::{FormattedInput, Mode, Segmenter};
+
+/// Lines of a tutor reply that point out a mistake.
+const CORRECTION_MARKERS: &[&str] = &[
+    "correction",
+    "corrected",
+    "incorrect",
+    "should be",
+    "instead of",
+    "not quite",
+    "mistake",
+    "✗",
+    "❌",
+];
+
+/// Below this many words a vocabulary estimate is noise.
+const MIN_VOCABULARY_WORDS: usize = 5;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Signals {
+    /// `complexity_score` scaled from 0–5.
+    pub complexity: f32,
+    /// Word length, variety and share of long words; `None` for short input.
+    pub vocabulary: Option<f32>,
+    /// Corrections per sentence of learner input; only measured in tutor mode.
+    pub error_rate: Option<f32>,
+}
+
+impl Signals {
+    pub fn from_turn(formatted: &FormattedInput, reply: &str) -> Self {
+        let prose = Segmenter::prose(&formatted.context.raw_input);
+        let error_rate = match formatted.mode {
+            Mode::Tutor => Some(error_rate(reply, formatted.tokens.sentence_count)),
+            _ => None,
+        };
+        Self {
+            complexity: (formatted.metadata.complexity_score / 5.0).clamp(0.0, 1.0),
+            vocabulary: vocabulary_level(&prose),
+            error_rate,
+        }
+    }
+
+    /// The level these signals point at, from 0.0 (`Beginner`) to 3.0
+    /// (`Expert`). Accuracy weighs most, complexity least, since the latter
+    /// mostly tracks how long the question was.
+    pub fn observed_level(&self) -> f32 {
+        let parts = [
+            Some((0.2, self.complexity)),
+            self.vocabulary.map(|v| (0.3, v)),
+            self.error_rate.map(|e| (0.5, 1.0 - e.min(1.0))),
+        ];
+        let (weight, total) = parts
+            .iter()
+            .flatten()
+            .fold((0.0, 0.0), |(w, t), (weight, value)| (w + weight, t + weight * value));
+        total / weight * 3.0
+    }
+}
+
+/// Lines of `reply` carrying a correction, per learner sentence, capped at 1.
+pub fn error_rate(reply: &str, sentences: usize) -> f32 {
+    let corrections = reply
+        .lines()
+        .map(str::to_lowercase)
+        .filter(|line| CORRECTION_MARKERS.iter().any(|m| line.contains(m)) || line.contains("~~"))
+        .count();
+    (corrections as f32 / sentences.max(1) as f32).min(1.0)
+}
+
+/// Crude lexical sophistication: longer words, fewer repeats and more words
+/// of eight letters or more all push the level up.
+pub fn vocabulary_level(prose: &str) -> Option<f32> {
+    let words: Vec<String> = prose
+        .split(|c: char| !c.is_alphabetic() && c != '\'')
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    if words.len() < MIN_VOCABULARY_WORDS {
+        return None;
+    }
+
+    let count = words.len() as f32;
+    let mean_length = words.iter().map(|w| w.chars().count()).sum::<usize>() as f32 / count;
+    let mut distinct = words.clone();
+    distinct.sort();
+    distinct.dedup();
+    let variety = distinct.len() as f32 / count;
+    let long = words.iter().filter(|w| w.chars().count() >= 8).count() as f32 / count;
+
+    let length = ((mean_length - 3.0) / 4.0).clamp(0.0, 1.0);
+    let variety = ((variety - 0.5) / 0.5).clamp(0.0, 1.0);
+    let long = (long / 0.3).min(1.0);
+    Some(0.4 * length + 0.3 * variety + 0.3 * long)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_rate_counts_correction_lines() {
+        let reply = "Good try!\nCorrection: \"je suis allé\", not \"j'ai allé\".\n~~le~~ la maison\nKeep going.";
+        assert_eq!(error_rate(reply, 4), 0.5);
+        assert_eq!(error_rate(reply, 1), 1.0);
+        assert_eq!(error_rate("Perfect, well done!", 2), 0.0);
+    }
+
+    #[test]
+    fn test_vocabulary_level_orders_texts() {
+        let simple = vocabulary_level("I like my cat and my cat likes me a lot").unwrap();
+        let rich = vocabulary_level("Explain how ownership semantics interact with asynchronous cancellation guarantees").unwrap();
+        assert!(rich > simple);
+        assert!(vocabulary_level("too short").is_none());
+    }
+
+    #[test]
+    fn test_observed_level_uses_available_signals() {
+        let flawless = Signals { complexity: 0.2, vocabulary: Some(0.9), error_rate: Some(0.0) };
+        let struggling = Signals { complexity: 0.2, vocabulary: Some(0.2), error_rate: Some(1.0) };
+        assert!(flawless.observed_level() > 2.0);
+        assert!(struggling.observed_level() < 1.0);
+
+        let assistant = Signals { complexity: 1.0, vocabulary: None, error_rate: None };
+        assert_eq!(assistant.observed_level(), 3.0);
+    }
+}
@@ -1,21 +1,85 @@
 mod commands;
+pub mod cache;
+pub mod engine;
+pub mod postprocessing;
 pub mod preprocessing;
 pub mod llama;
+pub mod embedding;
+pub mod personalities;
+pub mod registry;
 pub mod llm;
+pub mod learner;
+pub mod records;
 
+use crate::cache::{Cache, StoreWriter};
 use crate::commands::*;
+use crate::embedding::{cache::DEFAULT_CAPACITY, EmbeddingCache};
+use crate::learner::LearnerModel;
+use crate::preprocessing::{RedactionConfig, Redactor};
+use crate::records::RecordStore;
+use crate::llama::SessionStore;
+use crate::registry::{ActiveModels, ModelRegistry};
+use std::sync::{Arc, Mutex};
+use tauri::Manager;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    // A missing models directory shouldn't stop the app; `list_models` rescans.
+    let mut models = ModelRegistry::new(ModelRegistry::default_dir());
+    let _ = models.rescan();
+
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
+        .manage(Mutex::new(models))
+        .manage(Mutex::new(AppState::default()))
+        .setup(|app| {
+            // KV-cache snapshots live with the rest of the conversation data.
+            let data = app.path().app_data_dir()?;
+            app.manage(SessionStore::new(data.join("conversations"))?);
+            // Engines load on first use. Chat, retrieval and the cache flusher
+            // share the selection, and every embedder the one embedding memo.
+            let embeddings = Arc::new(EmbeddingCache::open(data.join("embeddings"), DEFAULT_CAPACITY)?);
+            let active = Arc::new(ActiveModels::new(embeddings.clone()));
+            app.manage(embeddings);
+            app.manage(active.clone());
+            app.manage(LearnerModel::open(data.join("learner.json"))?);
+            app.manage(RecordStore::open(data.join("records.sqlite3"))?);
+            // What memory, web search and logs must never see; all of it by default.
+            let redactor = Redactor::new(RedactionConfig::load(data.join("redaction.toml"))?);
+            // Exchanges reach memory 16 at a time, or once a minute; until
+            // then they survive a crash in the write-ahead log. The flusher
+            // task has to be spawned inside the async runtime.
+            let writer = StoreWriter::new(active, data.join("memory.sqlite3"));
+            let cache = tauri::async_runtime::block_on(async {
+                Cache::open(data.join("cache.wal"), 16, 60, redactor.clone(), writer)
+            })?;
+            app.manage(cache);
+            app.manage(redactor);
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             receive_input,
             receive_mode,
             receive_proficiency,
             receive_personality,
-            send_output
+            send_output,
+            cancel_generation,
+            reset_conversation,
+            list_models,
+            select_model,
+            embedding_cache_stats,
+            memory_status,
+            learner_profile,
+            set_proficiency_auto_adjust,
+            reset_learner_profile
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            // Whatever memory hasn't stored yet is written before the process goes.
+            if let tauri::RunEvent::Exit = event {
+                let cache = app.state::<Cache>().inner().clone();
+                tauri::async_runtime::block_on(cache.shutdown());
+            }
+        });
 }
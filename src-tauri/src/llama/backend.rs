@@ -0,0 +1,110 @@
+//! Backend abstraction over text generation.
+//!
+//! The pipeline (preprocessing, post-processing, orchestrator) only ever
+//! talks to `&dyn LlmBackend`, so tests can swap the llama.cpp engine for
+//! `MockBackend` and run without any GGUF file on disk.
+
+use super::{structured, ChatMessage, ChatTemplate, GenerationConfig, LLMEngine, Result};
+use serde::de::DeserializeOwned;
+use std::sync::Arc;
+
+pub trait LlmBackend: Send + Sync {
+    fn generate(&self, prompt: &str, config: Option<GenerationConfig>) -> Result<String>;
+
+    /// `on_token` receives each text delta; the full response is returned too.
+    fn generate_stream(
+        &self,
+        prompt: &str,
+        config: Option<GenerationConfig>,
+        on_token: &mut dyn FnMut(&str),
+    ) -> Result<String>;
+
+    fn chat(&self, messages: &[ChatMessage], config: Option<GenerationConfig>) -> Result<String>;
+
+    fn chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        config: Option<GenerationConfig>,
+        on_token: &mut dyn FnMut(&str),
+    ) -> Result<String>;
+
+    /// Length of `text` in the model's own tokens.
+    fn count_tokens(&self, text: &str) -> Result<usize>;
+
+    /// Context window in tokens, prompt and reply combined.
+    fn context_length(&self) -> usize;
+
+    /// Human-readable model description, for logs and the UI.
+    fn model_info(&self) -> String;
+
+    /// Chat format the model was trained on; prompts built outside `chat`
+    /// must be rendered with it too.
+    fn template(&self) -> Arc<dyn ChatTemplate>;
+}
+
+// Generic helpers can't live on the trait itself without breaking `dyn`.
+impl dyn LlmBackend + '_ {
+    /// See `LLMEngine::generate_json`.
+    pub fn generate_json<T: DeserializeOwned>(
+        &self,
+        prompt: &str,
+        schema: &serde_json::Value,
+        config: Option<GenerationConfig>,
+    ) -> Result<T> {
+        structured(schema, config, |config| self.generate(prompt, Some(config)))
+    }
+
+    /// See `LLMEngine::chat_json`.
+    pub fn chat_json<T: DeserializeOwned>(
+        &self,
+        messages: &[ChatMessage],
+        schema: &serde_json::Value,
+        config: Option<GenerationConfig>,
+    ) -> Result<T> {
+        structured(schema, config, |config| self.chat(messages, Some(config)))
+    }
+}
+
+impl LlmBackend for LLMEngine {
+    fn generate(&self, prompt: &str, config: Option<GenerationConfig>) -> Result<String> {
+        LLMEngine::generate(self, prompt, config)
+    }
+
+    fn generate_stream(
+        &self,
+        prompt: &str,
+        config: Option<GenerationConfig>,
+        on_token: &mut dyn FnMut(&str),
+    ) -> Result<String> {
+        LLMEngine::generate_stream(self, prompt, config, on_token)
+    }
+
+    fn chat(&self, messages: &[ChatMessage], config: Option<GenerationConfig>) -> Result<String> {
+        LLMEngine::chat(self, messages, config)
+    }
+
+    fn chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        config: Option<GenerationConfig>,
+        on_token: &mut dyn FnMut(&str),
+    ) -> Result<String> {
+        LLMEngine::chat_stream(self, messages, config, on_token)
+    }
+
+    fn count_tokens(&self, text: &str) -> Result<usize> {
+        LLMEngine::count_tokens(self, text)
+    }
+
+    fn context_length(&self) -> usize {
+        LLMEngine::context_length(self)
+    }
+
+    fn model_info(&self) -> String {
+        self.get_model_info()
+    }
+
+    fn template(&self) -> Arc<dyn ChatTemplate> {
+        Arc::clone(&self.template)
+    }
+}
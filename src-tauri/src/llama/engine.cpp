@@ -4,6 +4,8 @@
 #include <memory>
 #include <cstring>
 #include <iostream>
+#include <vector>
+#include <algorithm>
 
 /**
  * LLM Engine Implementation
@@ -17,11 +19,23 @@ struct QwenEngine {
     llama_model* model;
     llama_context* ctx;
     std::string model_path;
+    std::string model_info;
     bool is_loaded;
+    // Tokens currently held by the KV cache, in position order.
+    std::vector<llama_token> cached_tokens;
+    // Second context for one-off prompts (input analysis, grading, ...), so
+    // they never evict the conversation cached in `ctx`. Created on first
+    // use; sessions never save or restore it.
+    llama_context* scratch_ctx;
+    std::vector<llama_token> scratch_tokens;
+    int last_prompt_eval;
     
-    QwenEngine() : model(nullptr), ctx(nullptr), is_loaded(false) {}
+    QwenEngine() : model(nullptr), ctx(nullptr), is_loaded(false), scratch_ctx(nullptr), last_prompt_eval(0) {}
     
     ~QwenEngine() {
+        if (scratch_ctx) {
+            llama_free(scratch_ctx);
+        }
         if (ctx) {
             llama_free(ctx);
         }
@@ -34,6 +48,13 @@ struct QwenEngine {
 
 static bool backend_initialized = false;
 
+static llama_context_params context_params() {
+    llama_context_params ctx_params = llama_context_default_params();
+    ctx_params.n_ctx = 2048; 
+    ctx_params.n_threads = 4; 
+    return ctx_params;
+}
+
 void ensure_backend_initialized() {
     if (!backend_initialized) {
         llama_backend_init();
@@ -63,30 +84,86 @@ void* qwen_engine_create(const char* model_path) {
         return nullptr;
     }
     
-    llama_context_params ctx_params = llama_context_default_params();
-    ctx_params.n_ctx = 2048; 
-    ctx_params.n_threads = 4; 
-
-    engine->ctx = llama_new_context_with_model(engine->model, ctx_params);
+    engine->ctx = llama_new_context_with_model(engine->model, context_params());
     if (!engine->ctx) {
         std::cerr << "Failed to create context - tactical failure in initialization." << std::endl;
         return nullptr;
     }
     
+    char name[256];
+    if (llama_model_meta_val_str(engine->model, "general.name", name, sizeof(name)) > 0) {
+        engine->model_info = std::string(name) + " - Tactical AI Asset Deployed";
+    } else {
+        engine->model_info = "Unnamed model - Tactical AI Asset Deployed";
+    }
+
     engine->is_loaded = true;
     return engine.release();
 }
 
-char* qwen_engine_generate(void* engine_ptr, const char* prompt, int max_tokens, float temperature) {
-    if (!engine_ptr || !prompt) {
-        return nullptr; 
+static qwen_sampling_params default_params(int max_tokens, float temperature) {
+    qwen_sampling_params params;
+    params.max_tokens = max_tokens;
+    params.temperature = temperature;
+    params.top_p = 1.0f;
+    params.top_k = 0;
+    params.min_p = 0.0f;
+    params.repeat_penalty = 1.0f;
+    params.repeat_last_n = 64;
+    params.frequency_penalty = 0.0f;
+    params.presence_penalty = 0.0f;
+    params.seed = LLAMA_DEFAULT_SEED;
+    params.grammar = nullptr;
+    return params;
+}
+
+// Order matters: the grammar masks illegal tokens first, penalties shape the
+// raw logits, truncation narrows the candidate set, temperature rescales what
+// is left, dist draws the token.
+static llama_sampler* build_sampler(const llama_model* model, const qwen_sampling_params& p) {
+    llama_sampler* chain = llama_sampler_chain_init(llama_sampler_chain_default_params());
+    if (p.grammar && p.grammar[0] != '\0') {
+        llama_sampler* grammar = llama_sampler_init_grammar(model, p.grammar, "root");
+        if (!grammar) {
+            std::cerr << "Grammar failed to parse - rules of engagement unclear." << std::endl;
+            llama_sampler_free(chain);
+            return nullptr;
+        }
+        llama_sampler_chain_add(chain, grammar);
     }
-    
-    auto* engine = static_cast<QwenEngine*>(engine_ptr);
-    if (!engine->is_loaded) {
-        return nullptr;
+    if (p.repeat_penalty != 1.0f || p.frequency_penalty != 0.0f || p.presence_penalty != 0.0f) {
+        llama_sampler_chain_add(chain, llama_sampler_init_penalties(
+            p.repeat_last_n, p.repeat_penalty, p.frequency_penalty, p.presence_penalty));
     }
-    
+    if (p.temperature <= 0.0f) {
+        llama_sampler_chain_add(chain, llama_sampler_init_greedy());
+        return chain;
+    }
+    if (p.top_k > 0) {
+        llama_sampler_chain_add(chain, llama_sampler_init_top_k(p.top_k));
+    }
+    if (p.top_p < 1.0f) {
+        llama_sampler_chain_add(chain, llama_sampler_init_top_p(p.top_p, 1));
+    }
+    if (p.min_p > 0.0f) {
+        llama_sampler_chain_add(chain, llama_sampler_init_min_p(p.min_p, 1));
+    }
+    llama_sampler_chain_add(chain, llama_sampler_init_temp(p.temperature));
+    llama_sampler_chain_add(chain, llama_sampler_init_dist(p.seed));
+    return chain;
+}
+
+// Prompt tokens evaluated per llama_decode call. Small enough that a stop
+// request during a long prefill is seen within a fraction of a second.
+static const size_t PREFILL_CHUNK = 256;
+
+static char* empty_result() {
+    return static_cast<char*>(calloc(1, 1));
+}
+
+// Runs on `ctx`, whose KV cache holds `cached`: the conversation's context or
+// the scratch one.
+static char* generate_impl(QwenEngine* engine, llama_context* ctx, std::vector<llama_token>& cached, const char* prompt, const qwen_sampling_params& params, qwen_token_callback callback, void* user_data) {
     std::vector<llama_token> tokens;
     const int n_prompt_tokens = -llama_tokenize(engine->model, prompt, strlen(prompt), nullptr, 0, true, true);
     tokens.resize(n_prompt_tokens);
@@ -96,26 +173,51 @@ char* qwen_engine_generate(void* engine_ptr, const char* prompt, int max_tokens,
         return nullptr;
     }
     
-    llama_kv_cache_clear(engine->ctx);
+    // Reuse whatever prefix of the prompt the KV cache already holds and only
+    // evaluate the rest. At least one token is always re-evaluated so there
+    // are fresh logits to sample from.
+    size_t n_past = 0;
+    while (n_past < cached.size() && n_past < tokens.size()
+           && cached[n_past] == tokens[n_past]) {
+        ++n_past;
+    }
+    if (n_past > 0 && n_past == tokens.size()) {
+        --n_past;
+    }
+    llama_kv_cache_seq_rm(ctx, 0, n_past, -1);
+    cached.resize(n_past);
+    engine->last_prompt_eval = static_cast<int>(tokens.size() - n_past);
+    
+    // Prefill in chunks, polling the callback with an empty piece in between
+    // so the caller can stop before a single token is generated. Whatever
+    // was evaluated stays cached for the next prompt.
+    const size_t chunk = std::min<size_t>(PREFILL_CHUNK, std::max<uint32_t>(llama_n_batch(ctx), 1));
+    size_t evaluated = n_past;
+    while (evaluated < tokens.size()) {
+        const size_t n_eval = std::min(chunk, tokens.size() - evaluated);
+        if (llama_decode(ctx, llama_batch_get_one(tokens.data() + evaluated, n_eval, evaluated, 0)) != 0) {
+            std::cerr << "Failed to evaluate prompt - computational assets under stress." << std::endl;
+            llama_kv_cache_clear(ctx);
+            cached.clear();
+            return nullptr;
+        }
+        cached.insert(cached.end(), tokens.begin() + evaluated, tokens.begin() + evaluated + n_eval);
+        evaluated += n_eval;
+        if (evaluated < tokens.size() && callback && callback("", 0, user_data) != 0) {
+            return empty_result();
+        }
+    }
     
-    if (llama_decode(engine->ctx, llama_batch_get_one(tokens.data(), tokens.size(), 0, 0)) != 0) {
-        std::cerr << "Failed to evaluate prompt - computational assets under stress." << std::endl;
+    std::unique_ptr<llama_sampler, decltype(&llama_sampler_free)> sampler(build_sampler(engine->model, params), llama_sampler_free);
+    if (!sampler) {
         return nullptr;
     }
     
     std::string response;
     
-    for (int i = 0; i < max_tokens; ++i) {
-        llama_token next_token;
-        
-        if (temperature <= 0.0f) {
-            next_token = llama_sampler_sample_greedy(engine->ctx, 0);
-        } else {
-            auto* candidates = llama_sampler_chain_init({});
-            llama_sampler_chain_add(candidates, llama_sampler_init_temp(temperature));
-            next_token = llama_sampler_sample(candidates, engine->ctx, 0);
-            llama_sampler_free(candidates);
-        }
+    for (int i = 0; i < params.max_tokens; ++i) {
+        // Samples from the last logits and feeds the token back into the penalty history.
+        llama_token next_token = llama_sampler_sample(sampler.get(), ctx, -1);
         
         if (llama_token_is_eog(engine->model, next_token)) {
             break;
@@ -126,11 +228,18 @@ char* qwen_engine_generate(void* engine_ptr, const char* prompt, int max_tokens,
         if (n_chars > 0) {
             response.append(token_str, n_chars);
         }
+        // Every token reaches the callback, even one with no text of its own,
+        // so a stop request never waits on the next printable piece.
+        if (callback && callback(n_chars > 0 ? token_str : "", std::max(n_chars, 0), user_data) != 0) {
+            break;
+        }
         
-        if (llama_decode(engine->ctx, llama_batch_get_one(&next_token, 1, tokens.size() + i, 0)) != 0) {
+        if (llama_decode(ctx, llama_batch_get_one(&next_token, 1, tokens.size() + i, 0)) != 0) {
             std::cerr << "Decode failed during generation - tactical retreat initiated." << std::endl;
+            llama_kv_cache_seq_rm(ctx, 0, cached.size(), -1);
             break;
         }
+        cached.push_back(next_token);
     }
     
     char* result = static_cast<char*>(malloc(response.length() + 1));
@@ -141,6 +250,43 @@ char* qwen_engine_generate(void* engine_ptr, const char* prompt, int max_tokens,
     return result;
 }
 
+static QwenEngine* loaded(void* engine_ptr) {
+    auto* engine = static_cast<QwenEngine*>(engine_ptr);
+    return engine && engine->is_loaded ? engine : nullptr;
+}
+
+char* qwen_engine_generate(void* engine_ptr, const char* prompt, int max_tokens, float temperature) {
+    return qwen_engine_generate_stream(engine_ptr, prompt, max_tokens, temperature, nullptr, nullptr);
+}
+
+char* qwen_engine_generate_stream(void* engine_ptr, const char* prompt, int max_tokens, float temperature, qwen_token_callback callback, void* user_data) {
+    const qwen_sampling_params params = default_params(max_tokens, temperature);
+    return qwen_engine_generate_ex(engine_ptr, prompt, &params, callback, user_data);
+}
+
+char* qwen_engine_generate_ex(void* engine_ptr, const char* prompt, const qwen_sampling_params* params, qwen_token_callback callback, void* user_data) {
+    auto* engine = loaded(engine_ptr);
+    if (!engine || !prompt || !params) {
+        return nullptr;
+    }
+    return generate_impl(engine, engine->ctx, engine->cached_tokens, prompt, *params, callback, user_data);
+}
+
+char* qwen_engine_generate_scratch(void* engine_ptr, const char* prompt, const qwen_sampling_params* params, qwen_token_callback callback, void* user_data) {
+    auto* engine = loaded(engine_ptr);
+    if (!engine || !prompt || !params) {
+        return nullptr;
+    }
+    if (!engine->scratch_ctx) {
+        engine->scratch_ctx = llama_new_context_with_model(engine->model, context_params());
+        if (!engine->scratch_ctx) {
+            std::cerr << "Failed to create the scratch context - no room for side missions." << std::endl;
+            return nullptr;
+        }
+    }
+    return generate_impl(engine, engine->scratch_ctx, engine->scratch_tokens, prompt, *params, callback, user_data);
+}
+
 char* qwen_engine_chat(void* engine_ptr, const char* system_prompt, const char* user_message, int max_tokens) {
     if (!engine_ptr || !user_message) {
         return nullptr;
@@ -155,6 +301,107 @@ char* qwen_engine_chat(void* engine_ptr, const char* system_prompt, const char*
     return qwen_engine_generate(engine_ptr, full_prompt.c_str(), max_tokens, 0.7f);
 }
 
+int qwen_engine_tokenize(void* engine_ptr, const char* text, int add_special, int parse_special, int* out, int max_tokens) {
+    if (!engine_ptr || !text) {
+        return 0;
+    }
+    auto* engine = static_cast<QwenEngine*>(engine_ptr);
+    if (!engine->model) {
+        return 0;
+    }
+    // llama_tokenize already speaks the "negative means buffer too small" protocol.
+    return llama_tokenize(engine->model, text, strlen(text), out, max_tokens, add_special != 0, parse_special != 0);
+}
+
+char* qwen_engine_detokenize(void* engine_ptr, const int* tokens, int n_tokens) {
+    if (!engine_ptr || (!tokens && n_tokens > 0)) {
+        return nullptr;
+    }
+    auto* engine = static_cast<QwenEngine*>(engine_ptr);
+    if (!engine->model) {
+        return nullptr;
+    }
+    std::string text;
+    char piece[256];
+    for (int i = 0; i < n_tokens; ++i) {
+        int n_chars = llama_token_to_piece(engine->model, tokens[i], piece, sizeof(piece), 0, true);
+        if (n_chars < 0) {
+            std::cerr << "Token " << tokens[i] << " does not decode - signal lost in transit." << std::endl;
+            return nullptr;
+        }
+        text.append(piece, n_chars);
+    }
+    char* result = static_cast<char*>(malloc(text.length() + 1));
+    if (result) {
+        memcpy(result, text.c_str(), text.length() + 1);
+    }
+    return result;
+}
+
+int qwen_engine_context_length(void* engine_ptr) {
+    if (!engine_ptr) return 0;
+    auto* engine = static_cast<QwenEngine*>(engine_ptr);
+    return engine->ctx ? static_cast<int>(llama_n_ctx(engine->ctx)) : 0;
+}
+
+int qwen_engine_get_meta(void* engine_ptr, const char* key, char* buf, int buf_size) {
+    if (!engine_ptr || !key) {
+        return -1;
+    }
+    auto* engine = static_cast<QwenEngine*>(engine_ptr);
+    if (!engine->model) {
+        return -1;
+    }
+    return llama_model_meta_val_str(engine->model, key, buf, buf_size);
+}
+
+int qwen_engine_session_save(void* engine_ptr, const char* path) {
+    if (!engine_ptr || !path) {
+        return 0;
+    }
+    auto* engine = static_cast<QwenEngine*>(engine_ptr);
+    if (!engine->is_loaded) {
+        return 0;
+    }
+    return llama_state_save_file(engine->ctx, path, engine->cached_tokens.data(), engine->cached_tokens.size()) ? 1 : 0;
+}
+
+int qwen_engine_session_load(void* engine_ptr, const char* path) {
+    if (!engine_ptr || !path) {
+        return -1;
+    }
+    auto* engine = static_cast<QwenEngine*>(engine_ptr);
+    if (!engine->is_loaded) {
+        return -1;
+    }
+    std::vector<llama_token> tokens(llama_n_ctx(engine->ctx));
+    size_t n_loaded = 0;
+    if (!llama_state_load_file(engine->ctx, path, tokens.data(), tokens.size(), &n_loaded)) {
+        // A half-restored cache is worse than an empty one.
+        std::cerr << "Session restore failed: " << path << " - starting from a clean slate." << std::endl;
+        llama_kv_cache_clear(engine->ctx);
+        engine->cached_tokens.clear();
+        return -1;
+    }
+    tokens.resize(n_loaded);
+    engine->cached_tokens = std::move(tokens);
+    return static_cast<int>(n_loaded);
+}
+
+void qwen_engine_session_reset(void* engine_ptr) {
+    if (!engine_ptr) return;
+    auto* engine = static_cast<QwenEngine*>(engine_ptr);
+    if (engine->ctx) {
+        llama_kv_cache_clear(engine->ctx);
+    }
+    engine->cached_tokens.clear();
+}
+
+int qwen_engine_last_prompt_eval(void* engine_ptr) {
+    if (!engine_ptr) return 0;
+    return static_cast<QwenEngine*>(engine_ptr)->last_prompt_eval;
+}
+
 void qwen_engine_destroy(void* engine_ptr) {
     if (engine_ptr) {
         delete static_cast<QwenEngine*>(engine_ptr);
@@ -175,7 +422,7 @@ int qwen_engine_is_loaded(void* engine_ptr) {
 const char* qwen_engine_get_model_info(void* engine_ptr) {
     if (!engine_ptr) return "Engine not initialized";
     auto* engine = static_cast<QwenEngine*>(engine_ptr);
-    return engine->is_loaded ? "Qwen 2.5 0.5B - Tactical AI Asset Deployed" : "Asset offline";
+    return engine->is_loaded ? engine->model_info.c_str() : "Asset offline";
 }
 
 } 
\ No newline at end of file
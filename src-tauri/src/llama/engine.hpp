@@ -12,10 +12,76 @@ extern "C" {
  * Handle with appropriate tactical caution - pointers are live ammunition.
  */
 
+/**
+ * Streaming callback: receives each decoded piece as it is produced.
+ * Pieces are raw bytes and may split a UTF-8 sequence - reassembly is
+ * the caller's problem. It is also called with an empty piece (length 0)
+ * for tokens that decode to no text and between prompt batches, so a stop
+ * request is always seen promptly. Return non-zero to stop generation early.
+ */
+typedef int (*qwen_token_callback)(const char* piece, int length, void* user_data);
+
+/**
+ * Sampling knobs. Neutral values disable a stage: top_k <= 0, top_p >= 1,
+ * min_p <= 0, repeat_penalty == 1, frequency/presence == 0.
+ * temperature <= 0 means greedy. seed == 0xFFFFFFFF picks a random seed.
+ * grammar is an optional GBNF source (start rule "root"); when set, every
+ * sampled token must keep the output inside the grammar. NULL disables it.
+ */
+typedef struct {
+    int max_tokens;
+    float temperature;
+    float top_p;
+    int top_k;
+    float min_p;
+    float repeat_penalty;
+    int repeat_last_n;
+    float frequency_penalty;
+    float presence_penalty;
+    unsigned int seed;
+    const char* grammar;
+} qwen_sampling_params;
+
 void* qwen_engine_create(const char* model_path);
 void qwen_engine_destroy(void* engine);
 char* qwen_engine_generate(void* engine, const char* prompt, int max_tokens, float temperature);
+char* qwen_engine_generate_stream(void* engine, const char* prompt, int max_tokens, float temperature, qwen_token_callback callback, void* user_data);
+char* qwen_engine_generate_ex(void* engine, const char* prompt, const qwen_sampling_params* params, qwen_token_callback callback, void* user_data);
+/**
+ * Same as qwen_engine_generate_ex, on a second context of the same model.
+ * One-off prompts (input analysis, grading) go here, so they never evict
+ * the conversation from the main KV cache; sessions only ever cover the
+ * main one. The context is allocated on first use.
+ */
+char* qwen_engine_generate_scratch(void* engine, const char* prompt, const qwen_sampling_params* params, qwen_token_callback callback, void* user_data);
 char* qwen_engine_chat(void* engine, const char* system_prompt, const char* user_message, int max_tokens);
+/**
+ * Tokenizes `text` into `out`. Returns the token count, or its negation when
+ * `max_tokens` is too small (call again with a buffer that size).
+ * add_special prepends BOS where the model wants one; parse_special maps
+ * control-token text such as "<|im_start|>" to the control token itself.
+ */
+int qwen_engine_tokenize(void* engine, const char* text, int add_special, int parse_special, int* out, int max_tokens);
+/** Concatenated pieces of `tokens`; free with qwen_free_string. */
+char* qwen_engine_detokenize(void* engine, const int* tokens, int n_tokens);
+/** Size of the allocated context window, in tokens. */
+int qwen_engine_context_length(void* engine);
+/**
+ * Copies the GGUF metadata value for `key` (e.g. "tokenizer.chat_template")
+ * into `buf`. Returns the full value length, or -1 if the key is absent.
+ */
+int qwen_engine_get_meta(void* engine, const char* key, char* buf, int buf_size);
+/**
+ * KV-cache sessions. Each generation reuses the longest prefix of its prompt
+ * already in the cache and only evaluates the remainder; save/load persist
+ * that cache (plus the tokens it holds) so a conversation can resume warm.
+ * save returns 1 on success; load returns the restored token count or -1.
+ */
+int qwen_engine_session_save(void* engine, const char* path);
+int qwen_engine_session_load(void* engine, const char* path);
+void qwen_engine_session_reset(void* engine);
+/** Prompt tokens the most recent generation actually had to evaluate. */
+int qwen_engine_last_prompt_eval(void* engine);
 void qwen_free_string(char* str);
 int qwen_engine_is_loaded(void* engine);
 const char* qwen_engine_get_model_info(void* engine);
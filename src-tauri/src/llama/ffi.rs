@@ -18,17 +18,141 @@ extern "C" {
         max_tokens: c_int,
         temperature: c_float,
     ) -> *mut c_char;
+    fn qwen_engine_generate_stream(
+        engine: *mut c_void,
+        prompt: *const c_char,
+        max_tokens: c_int,
+        temperature: c_float,
+        callback: Option<TokenCallback>,
+        user_data: *mut c_void,
+    ) -> *mut c_char;
+    fn qwen_engine_generate_ex(
+        engine: *mut c_void,
+        prompt: *const c_char,
+        params: *const SamplingParams,
+        callback: Option<TokenCallback>,
+        user_data: *mut c_void,
+    ) -> *mut c_char;
+    fn qwen_engine_generate_scratch(
+        engine: *mut c_void,
+        prompt: *const c_char,
+        params: *const SamplingParams,
+        callback: Option<TokenCallback>,
+        user_data: *mut c_void,
+    ) -> *mut c_char;
     fn qwen_engine_chat(
         engine: *mut c_void,
         system_prompt: *const c_char,
         user_message: *const c_char,
         max_tokens: c_int,
     ) -> *mut c_char;
+    fn qwen_engine_get_meta(
+        engine: *mut c_void,
+        key: *const c_char,
+        buf: *mut c_char,
+        buf_size: c_int,
+    ) -> c_int;
+    fn qwen_engine_tokenize(
+        engine: *mut c_void,
+        text: *const c_char,
+        add_special: c_int,
+        parse_special: c_int,
+        out: *mut c_int,
+        max_tokens: c_int,
+    ) -> c_int;
+    fn qwen_engine_detokenize(engine: *mut c_void, tokens: *const c_int, n_tokens: c_int) -> *mut c_char;
+    fn qwen_engine_context_length(engine: *mut c_void) -> c_int;
+    fn qwen_engine_session_save(engine: *mut c_void, path: *const c_char) -> c_int;
+    fn qwen_engine_session_load(engine: *mut c_void, path: *const c_char) -> c_int;
+    fn qwen_engine_session_reset(engine: *mut c_void);
+    fn qwen_engine_last_prompt_eval(engine: *mut c_void) -> c_int;
     fn qwen_free_string(str: *mut c_char);
     fn qwen_engine_is_loaded(engine: *mut c_void) -> c_int;
     fn qwen_engine_get_model_info(engine: *mut c_void) -> *const c_char;
 }
 
+/// Mirror of `qwen_sampling_params` in engine.hpp - field order is ABI.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SamplingParams {
+    pub max_tokens: c_int,
+    pub temperature: c_float,
+    pub top_p: c_float,
+    pub top_k: c_int,
+    pub min_p: c_float,
+    pub repeat_penalty: c_float,
+    pub repeat_last_n: c_int,
+    pub frequency_penalty: c_float,
+    pub presence_penalty: c_float,
+    pub seed: u32,
+    /// GBNF source, or null. Must outlive the call it is passed to.
+    pub grammar: *const c_char,
+}
+
+/// `LLAMA_DEFAULT_SEED`: let llama.cpp pick a random seed.
+pub const RANDOM_SEED: u32 = 0xFFFF_FFFF;
+
+type TokenCallback = unsafe extern "C" fn(piece: *const c_char, length: c_int, user_data: *mut c_void) -> c_int;
+
+/// `qwen_engine_generate_ex` or `qwen_engine_generate_scratch`.
+type GenerateFn = unsafe extern "C" fn(
+    engine: *mut c_void,
+    prompt: *const c_char,
+    params: *const SamplingParams,
+    callback: Option<TokenCallback>,
+    user_data: *mut c_void,
+) -> *mut c_char;
+
+/// Per-call state handed to the C++ side as `user_data`.
+/// Pieces arrive as raw bytes and a multi-byte character can straddle two
+/// tokens, so incomplete UTF-8 tails are held back until the next piece.
+struct StreamState<'a> {
+    on_token: &'a mut dyn FnMut(&str) -> bool,
+    pending: Vec<u8>,
+}
+
+impl StreamState<'_> {
+    fn push(&mut self, bytes: &[u8]) -> bool {
+        self.pending.extend_from_slice(bytes);
+        let valid_up_to = match std::str::from_utf8(&self.pending) {
+            Ok(_) => self.pending.len(),
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            // Genuinely invalid bytes: flush lossily rather than stalling forever.
+            Err(_) => {
+                let text = String::from_utf8_lossy(&self.pending).into_owned();
+                self.pending.clear();
+                return (self.on_token)(&text);
+            }
+        };
+        if valid_up_to == 0 {
+            // Nothing printable yet; the caller still gets to say stop.
+            return (self.on_token)("");
+        }
+        let rest = self.pending.split_off(valid_up_to);
+        let text = std::mem::replace(&mut self.pending, rest);
+        // SAFETY: validated just above.
+        (self.on_token)(unsafe { std::str::from_utf8_unchecked(&text) })
+    }
+}
+
+unsafe extern "C" fn stream_trampoline(
+    piece: *const c_char,
+    length: c_int,
+    user_data: *mut c_void,
+) -> c_int {
+    // Empty pieces are polls; they still reach `on_token`.
+    if piece.is_null() || user_data.is_null() || length < 0 {
+        return 0;
+    }
+    let state = &mut *(user_data as *mut StreamState);
+    let bytes = std::slice::from_raw_parts(piece as *const u8, length as usize);
+    // Unwinding across the C boundary is UB - treat a panicking callback as "stop".
+    match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| state.push(bytes))) {
+        Ok(true) => 0,
+        _ => 1,
+    }
+}
+
 pub struct RawEngine {
     pub(crate) ptr: *mut c_void,
 }
@@ -64,6 +188,58 @@ impl RawEngine {
         qwen_free_string(result_ptr);
         Some(rust_string)
     }
+    /// Same as `generate`, but `on_token` sees every decoded delta as it lands.
+    /// It also gets empty deltas during prefill and for tokens with no text
+    /// yet; returning `false` from the callback stops generation there.
+    pub unsafe fn generate_stream(
+        &self,
+        prompt: &str,
+        params: &SamplingParams,
+        on_token: &mut dyn FnMut(&str) -> bool,
+    ) -> Option<String> {
+        self.stream_with(qwen_engine_generate_ex, prompt, params, on_token)
+    }
+    /// `generate_stream` on the scratch context, leaving the conversation's
+    /// KV cache (and so its session) untouched.
+    pub unsafe fn generate_scratch(
+        &self,
+        prompt: &str,
+        params: &SamplingParams,
+        on_token: &mut dyn FnMut(&str) -> bool,
+    ) -> Option<String> {
+        self.stream_with(qwen_engine_generate_scratch, prompt, params, on_token)
+    }
+    unsafe fn stream_with(
+        &self,
+        generate: GenerateFn,
+        prompt: &str,
+        params: &SamplingParams,
+        on_token: &mut dyn FnMut(&str) -> bool,
+    ) -> Option<String> {
+        let c_prompt = CString::new(prompt).ok()?;
+        let mut state = StreamState {
+            on_token,
+            pending: Vec::new(),
+        };
+        let result_ptr = generate(
+            self.ptr,
+            c_prompt.as_ptr(),
+            params as *const SamplingParams,
+            Some(stream_trampoline),
+            &mut state as *mut StreamState as *mut c_void,
+        );
+        if !state.pending.is_empty() {
+            let tail = String::from_utf8_lossy(&state.pending).into_owned();
+            (state.on_token)(&tail);
+        }
+        if result_ptr.is_null() {
+            return None;
+        }
+        let c_str = CStr::from_ptr(result_ptr);
+        let rust_string = c_str.to_string_lossy().into_owned();
+        qwen_free_string(result_ptr);
+        Some(rust_string)
+    }
     pub unsafe fn chat(
         &self,
         system_prompt: Option<&str>,
@@ -93,6 +269,90 @@ impl RawEngine {
         qwen_free_string(result_ptr);
         Some(rust_string)
     }
+    /// GGUF metadata lookup; chat templates can run to several KB, so the
+    /// buffer is grown to whatever length llama.cpp reports.
+    pub unsafe fn meta(&self, key: &str) -> Option<String> {
+        let c_key = CString::new(key).ok()?;
+        let mut buf = vec![0u8; 256];
+        loop {
+            let len = qwen_engine_get_meta(
+                self.ptr,
+                c_key.as_ptr(),
+                buf.as_mut_ptr() as *mut c_char,
+                buf.len() as c_int,
+            );
+            if len < 0 {
+                return None;
+            }
+            let len = len as usize;
+            if len < buf.len() {
+                buf.truncate(len);
+                return Some(String::from_utf8_lossy(&buf).into_owned());
+            }
+            buf.resize(len + 1, 0);
+        }
+    }
+    /// Two-pass: a first call sized for a typical input, a second sized to
+    /// whatever llama.cpp says it actually needs.
+    pub unsafe fn tokenize(&self, text: &str, add_special: bool, parse_special: bool) -> Option<Vec<i32>> {
+        let c_text = CString::new(text).ok()?;
+        let mut tokens = vec![0 as c_int; text.len() / 2 + 8];
+        loop {
+            let n = qwen_engine_tokenize(
+                self.ptr,
+                c_text.as_ptr(),
+                add_special as c_int,
+                parse_special as c_int,
+                tokens.as_mut_ptr(),
+                tokens.len() as c_int,
+            );
+            if n >= 0 {
+                tokens.truncate(n as usize);
+                return Some(tokens);
+            }
+            let needed = n.unsigned_abs() as usize;
+            if needed <= tokens.len() {
+                return None;
+            }
+            tokens.resize(needed, 0);
+        }
+    }
+    pub unsafe fn detokenize(&self, tokens: &[i32]) -> Option<String> {
+        let result_ptr = qwen_engine_detokenize(self.ptr, tokens.as_ptr(), tokens.len() as c_int);
+        if result_ptr.is_null() {
+            return None;
+        }
+        let c_str = CStr::from_ptr(result_ptr);
+        let rust_string = c_str.to_string_lossy().into_owned();
+        qwen_free_string(result_ptr);
+        Some(rust_string)
+    }
+    pub unsafe fn context_length(&self) -> usize {
+        qwen_engine_context_length(self.ptr).max(0) as usize
+    }
+    pub unsafe fn save_session(&self, path: &str) -> bool {
+        match CString::new(path) {
+            Ok(c_path) => qwen_engine_session_save(self.ptr, c_path.as_ptr()) != 0,
+            Err(_) => false,
+        }
+    }
+    /// Number of tokens restored, or `None` if the file was unusable
+    /// (the cache is left empty in that case).
+    pub unsafe fn load_session(&self, path: &str) -> Option<usize> {
+        let c_path = CString::new(path).ok()?;
+        let n = qwen_engine_session_load(self.ptr, c_path.as_ptr());
+        if n < 0 {
+            None
+        } else {
+            Some(n as usize)
+        }
+    }
+    pub unsafe fn reset_session(&self) {
+        qwen_engine_session_reset(self.ptr);
+    }
+    pub unsafe fn last_prompt_eval(&self) -> usize {
+        qwen_engine_last_prompt_eval(self.ptr).max(0) as usize
+    }
     pub unsafe fn is_loaded(&self) -> bool {
         qwen_engine_is_loaded(self.ptr) != 0
     }
@@ -131,4 +391,27 @@ pub(crate) mod utils {
         let c_str = CStr::from_ptr(ptr);
         Some(c_str.to_string_lossy().into_owned())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_pieces_still_poll_the_callback() {
+        let mut calls = Vec::new();
+        let mut on_token = |delta: &str| {
+            calls.push(delta.to_string());
+            calls.len() < 3
+        };
+        let mut state = StreamState { on_token: &mut on_token, pending: Vec::new() };
+        let user_data = &mut state as *mut StreamState as *mut c_void;
+        unsafe {
+            assert_eq!(stream_trampoline(c"".as_ptr(), 0, user_data), 0);
+            // First half of "é": held back, but still a chance to stop.
+            assert_eq!(stream_trampoline(c"\xC3".as_ptr(), 1, user_data), 0);
+            assert_eq!(stream_trampoline(c"\xA9".as_ptr(), 1, user_data), 1);
+        }
+        assert_eq!(calls, ["", "", "é"]);
+    }
+}
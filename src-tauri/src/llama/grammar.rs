@@ -0,0 +1,297 @@
+//! JSON-schema to GBNF conversion for constrained decoding, plus a repair
+//! pass for output that still fails to parse (usually because generation hit
+//! `max_tokens` halfway through an object).
+//!
+//! Only the subset structured calls actually need is supported: objects,
+//! arrays, strings, numbers, integers, booleans, null, `enum` and `const`.
+//! A schema node without a `type` accepts any JSON value. Every declared
+//! object property is emitted, `required` ones first, in schema order.
+
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+// Shared terminals appended to every grammar. `ws` is deliberately bounded:
+// a small model left free to emit whitespace will happily burn its whole
+// token budget on it.
+const PRIMITIVES: &str = r#"value ::= object | array | string | number | boolean | null
+object ::= "{" ws ( string ":" ws value ( "," ws string ":" ws value )* )? "}" ws
+array ::= "[" ws ( value ( "," ws value )* )? "]" ws
+string ::= "\"" ( [^"\\\x7F\x00-\x1F] | "\\" ( ["\\/bfnrt] | "u" [0-9a-fA-F] [0-9a-fA-F] [0-9a-fA-F] [0-9a-fA-F] ) )* "\"" ws
+number ::= int-part ( "." [0-9]+ )? ( [eE] [-+]? [0-9]+ )? ws
+integer ::= int-part ws
+int-part ::= "-"? ( "0" | [1-9] [0-9]* )
+boolean ::= ( "true" | "false" ) ws
+null ::= "null" ws
+ws ::= [ \t\n]?
+"#;
+
+/// Compiles a JSON schema into a GBNF grammar whose start rule is `root`.
+pub fn from_schema(schema: &Value) -> Result<String, String> {
+    let mut builder = Builder::default();
+    let root = builder.visit(schema, "root")?;
+    if root != "root" {
+        builder.rules.insert(0, format!("root ::= {}", root));
+    }
+    let mut grammar = builder.rules.join("\n");
+    grammar.push('\n');
+    grammar.push_str(PRIMITIVES);
+    Ok(grammar)
+}
+
+#[derive(Default)]
+struct Builder {
+    rules: Vec<String>,
+}
+
+impl Builder {
+    // Returns the rule (or primitive) name that matches `schema`.
+    fn visit(&mut self, schema: &Value, name: &str) -> Result<String, String> {
+        if let Some(options) = schema.get("enum") {
+            let options = options
+                .as_array()
+                .filter(|options| !options.is_empty())
+                .ok_or_else(|| format!("`enum` at {} must be a non-empty array", name))?;
+            let alternatives: Vec<String> = options.iter().map(|v| literal(&v.to_string())).collect();
+            return Ok(self.rule(name, format!("( {} ) ws", alternatives.join(" | "))));
+        }
+        if let Some(value) = schema.get("const") {
+            return Ok(self.rule(name, format!("{} ws", literal(&value.to_string()))));
+        }
+        match schema.get("type").and_then(Value::as_str) {
+            Some("object") => self.object(schema, name),
+            Some("array") => {
+                let item = match schema.get("items") {
+                    Some(items) => self.visit(items, &format!("{}-item", name))?,
+                    None => "value".to_string(),
+                };
+                Ok(self.rule(
+                    name,
+                    format!(r#""[" ws ( {item} ( "," ws {item} )* )? "]" ws"#, item = item),
+                ))
+            }
+            Some(primitive @ ("string" | "number" | "integer" | "boolean" | "null")) => {
+                Ok(primitive.to_string())
+            }
+            Some(other) => Err(format!("unsupported schema type `{}` at {}", other, name)),
+            None => Ok("value".to_string()),
+        }
+    }
+
+    fn object(&mut self, schema: &Value, name: &str) -> Result<String, String> {
+        let properties = match schema.get("properties").and_then(Value::as_object) {
+            Some(properties) if !properties.is_empty() => properties,
+            _ => return Ok("object".to_string()),
+        };
+        let mut keys: Vec<&str> = schema
+            .get("required")
+            .and_then(Value::as_array)
+            .map(|required| required.iter().filter_map(Value::as_str).collect())
+            .unwrap_or_default();
+        keys.retain(|key| properties.contains_key(*key));
+        for key in properties.keys() {
+            if !keys.contains(&key.as_str()) {
+                keys.push(key);
+            }
+        }
+
+        let mut members = Vec::with_capacity(keys.len());
+        for key in keys {
+            let value = self.visit(&properties[key], &format!("{}-{}", name, rule_name(key)))?;
+            members.push(format!(r#"{} ws ":" ws {}"#, literal(&Value::from(key).to_string()), value));
+        }
+        Ok(self.rule(
+            name,
+            format!(r#""{{" ws {} "}}" ws"#, members.join(r#" "," ws "#)),
+        ))
+    }
+
+    fn rule(&mut self, name: &str, body: String) -> String {
+        let mut unique = name.to_string();
+        let mut n = 1;
+        while self.rules.iter().any(|r| r.starts_with(&format!("{} ::=", unique))) {
+            n += 1;
+            unique = format!("{}{}", name, n);
+        }
+        self.rules.push(format!("{} ::= {}", unique, body));
+        unique
+    }
+}
+
+// GBNF rule names are limited to `[a-zA-Z0-9-]`.
+fn rule_name(key: &str) -> String {
+    key.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect()
+}
+
+// Quotes already-serialised JSON text as a GBNF string literal.
+fn literal(json: &str) -> String {
+    format!("\"{}\"", json.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Parses `raw` as `T`, falling back to `repair` when the text is not valid
+/// JSON as-is. The error is the one from the original, unrepaired text.
+pub fn parse<T: DeserializeOwned>(raw: &str) -> Result<T, String> {
+    let original = match serde_json::from_str(raw.trim()) {
+        Ok(value) => return Ok(value),
+        Err(e) => e,
+    };
+    repair(raw)
+        .and_then(|fixed| serde_json::from_str(&fixed).ok())
+        .ok_or_else(|| original.to_string())
+}
+
+/// Best-effort fix-up of almost-JSON: skips chatter and code fences before
+/// the first object or array, cuts anything after it, drops trailing commas
+/// and closes whatever was still open when generation stopped.
+pub fn repair(raw: &str) -> Option<String> {
+    let start = raw.find(['{', '['])?;
+    let mut out = String::with_capacity(raw.len() - start);
+    let mut open = Vec::new();
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for c in raw[start..].chars() {
+        if in_string {
+            out.push(c);
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_string = false;
+            }
+            continue;
+        }
+        match c {
+            '"' => {
+                in_string = true;
+                out.push(c);
+            }
+            '{' => {
+                open.push('}');
+                out.push(c);
+            }
+            '[' => {
+                open.push(']');
+                out.push(c);
+            }
+            '}' | ']' => {
+                if open.pop() != Some(c) {
+                    return None;
+                }
+                strip_dangling(&mut out);
+                out.push(c);
+                if open.is_empty() {
+                    return Some(out);
+                }
+            }
+            _ => out.push(c),
+        }
+    }
+
+    if in_string {
+        if escaped {
+            out.pop();
+        }
+        out.push('"');
+    }
+    while let Some(close) = open.pop() {
+        strip_dangling(&mut out);
+        out.push(close);
+    }
+    Some(out)
+}
+
+// Trailing `,` is dropped; a key left without its value gets `null`.
+fn strip_dangling(out: &mut String) {
+    let trimmed = out.trim_end().len();
+    out.truncate(trimmed);
+    if out.ends_with(',') {
+        out.pop();
+    } else if out.ends_with(':') {
+        out.push_str(" null");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Analysis {
+        action: String,
+        domain: String,
+        topic: String,
+    }
+
+    #[test]
+    fn test_object_schema_orders_required_keys() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "topic": { "type": "string" },
+                "action": { "type": "string" },
+                "score": { "type": "number" }
+            },
+            "required": ["topic", "action"]
+        });
+        let grammar = from_schema(&schema).unwrap();
+        let root = grammar.lines().next().unwrap();
+        let topic = root.find("topic").unwrap();
+        let action = root.find("action").unwrap();
+        let score = root.find("score").unwrap();
+        assert!(topic < action && action < score, "{}", root);
+        assert!(root.starts_with(r#"root ::= "{" ws "\"topic\"" ws ":" ws string"#));
+    }
+
+    #[test]
+    fn test_nested_schema_emits_named_rules() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "level": { "enum": ["beginner", "advanced"] },
+                "tags": { "type": "array", "items": { "type": "string" } }
+            }
+        });
+        let grammar = from_schema(&schema).unwrap();
+        assert!(grammar.contains(r#"root-level ::= ( "\"beginner\"" | "\"advanced\"" ) ws"#));
+        assert!(grammar.contains("root-tags ::= \"[\" ws ( string"));
+        assert!(grammar.contains("ws ::= "));
+    }
+
+    #[test]
+    fn test_primitive_root_and_unknown_type() {
+        assert!(from_schema(&json!({ "type": "integer" })).unwrap().starts_with("root ::= integer\n"));
+        assert!(from_schema(&json!({})).unwrap().starts_with("root ::= value\n"));
+        assert!(from_schema(&json!({ "type": "tuple" })).is_err());
+        assert!(from_schema(&json!({ "enum": [] })).is_err());
+    }
+
+    #[test]
+    fn test_parse_strips_chatter_and_fences() {
+        let raw = "Sure! Here you go:\n```json\n{\"action\":\"explain\",\"domain\":\"Rust\",\"topic\":\"lifetimes\"}\n```";
+        let parsed: Analysis = parse(raw).unwrap();
+        assert_eq!(parsed.topic, "lifetimes");
+    }
+
+    #[test]
+    fn test_repair_closes_truncated_output() {
+        assert_eq!(
+            repair(r#"{"action":"explain","domain":"Rust","topic":"life"#).unwrap(),
+            r#"{"action":"explain","domain":"Rust","topic":"life"}"#
+        );
+        assert_eq!(repair(r#"{"a":[1,2,"#).unwrap(), r#"{"a":[1,2]}"#);
+        assert_eq!(repair(r#"{"a":"#).unwrap(), r#"{"a": null}"#);
+        assert_eq!(repair(r#"{"a":"x\"#).unwrap(), r#"{"a":"x"}"#);
+    }
+
+    #[test]
+    fn test_repair_drops_trailing_commas_but_keeps_strings() {
+        assert_eq!(repair(r#"{"a":"x, }",}"#).unwrap(), r#"{"a":"x, }"}"#);
+        assert!(repair("no json here").is_none());
+        assert!(repair(r#"{"a":1]"#).is_none());
+    }
+}
@@ -0,0 +1,225 @@
+//! Fits a multi-turn conversation into the model's context window.
+//!
+//! System messages and the newest turn are always kept; when even they don't
+//! fit, the caller gets `ContextOverflow` rather than a truncated prompt.
+//! Older turns are dropped oldest-first, and whatever was dropped is folded
+//! into a short extractive recap so the model still knows what was discussed.
+//! Costs come from the model's tokenizer when one is available,
+//! `estimate_tokens` if not, plus the template's framing around each message.
+
+use super::{ChatMessage, ChatTemplate, LLMError, MessageRole, Result};
+
+/// Context size the C++ side allocates (`ctx_params.n_ctx` in engine.cpp);
+/// the fallback whenever the live value can't be asked for.
+pub const CONTEXT_TOKENS: usize = 2048;
+
+// Ceiling for the recap of dropped turns.
+const RECAP_TOKENS: usize = 160;
+// Per-line clip inside the recap.
+const RECAP_LINE_CHARS: usize = 120;
+const RECAP_HEADER: &str = "Earlier in this conversation (summarised):";
+
+/// Pessimistic token estimate: BPE vocabularies average ~4 bytes per token on
+/// English and fewer on code or non-Latin scripts, so 3 keeps us on the safe side.
+pub fn estimate_tokens(text: &str) -> usize {
+    (text.len() + 2) / 3
+}
+
+struct Costs<'a> {
+    overhead: usize,
+    count: &'a dyn Fn(&str) -> usize,
+}
+
+impl Costs<'_> {
+    fn message(&self, message: &ChatMessage) -> usize {
+        (self.count)(&message.content) + self.overhead
+    }
+}
+
+/// Returns the subset of `messages` (plus an optional recap) that fits in
+/// `budget` tokens once rendered with `template`, as judged by
+/// `estimate_tokens`.
+pub fn fit_to_window(
+    messages: &[ChatMessage],
+    budget: usize,
+    template: &dyn ChatTemplate,
+) -> Result<Vec<ChatMessage>> {
+    fit_to_window_with(messages, budget, template, &estimate_tokens)
+}
+
+/// `fit_to_window` with costs measured by `count`, typically the real tokenizer.
+pub fn fit_to_window_with(
+    messages: &[ChatMessage],
+    budget: usize,
+    template: &dyn ChatTemplate,
+    count: &dyn Fn(&str) -> usize,
+) -> Result<Vec<ChatMessage>> {
+    let costs = Costs { overhead: template.message_overhead(), count };
+    let (system, dialogue): (Vec<&ChatMessage>, Vec<&ChatMessage>) = messages
+        .iter()
+        .partition(|msg| matches!(msg.role, MessageRole::System));
+
+    let system_cost: usize = system.iter().map(|m| costs.message(m)).sum();
+    let total_cost = system_cost + dialogue.iter().map(|m| costs.message(m)).sum::<usize>();
+    if total_cost <= budget {
+        return Ok(messages.to_vec());
+    }
+    let needed = system_cost + dialogue.last().map_or(0, |m| costs.message(m));
+    if needed > budget {
+        return Err(LLMError::ContextOverflow { needed, available: budget });
+    }
+
+    // Walk back from the newest turn, which is known to fit, reserving room
+    // for the recap.
+    let available = budget.saturating_sub(system_cost + RECAP_TOKENS + costs.overhead);
+    let mut used = costs.message(dialogue[dialogue.len() - 1]);
+    let mut start = dialogue.len() - 1;
+    while start > 0 {
+        let cost = costs.message(dialogue[start - 1]);
+        if used + cost > available {
+            break;
+        }
+        used += cost;
+        start -= 1;
+    }
+    // Never open the kept window on an orphaned assistant reply.
+    while start < dialogue.len() - 1 && matches!(dialogue[start].role, MessageRole::Assistant) {
+        start += 1;
+    }
+
+    // The newest turn alone may leave less than a full recap's worth.
+    let recap_limit = budget
+        .saturating_sub(system_cost + used + costs.overhead)
+        .min(RECAP_TOKENS);
+    let mut fitted: Vec<ChatMessage> = system.into_iter().cloned().collect();
+    if let Some(recap) = recap(&dialogue[..start], recap_limit, count) {
+        fitted.push(recap);
+    }
+    fitted.extend(dialogue[start..].iter().map(|m| (*m).clone()));
+    Ok(fitted)
+}
+
+fn recap(dropped: &[&ChatMessage], limit: usize, count: &dyn Fn(&str) -> usize) -> Option<ChatMessage> {
+    if dropped.is_empty() {
+        return None;
+    }
+    // Newest dropped turns matter most; collect backwards, then restore order.
+    let mut lines = Vec::new();
+    let mut used = count(RECAP_HEADER);
+    for msg in dropped.iter().rev() {
+        let speaker = match msg.role {
+            MessageRole::User => "User",
+            MessageRole::Assistant => "You",
+            MessageRole::System => continue,
+        };
+        let line = format!("- {}: {}", speaker, first_sentence(&msg.content));
+        let cost = count(&line) + 1;
+        if used + cost > limit {
+            break;
+        }
+        used += cost;
+        lines.push(line);
+    }
+    if lines.is_empty() {
+        return None;
+    }
+    lines.reverse();
+    Some(ChatMessage {
+        role: MessageRole::System,
+        content: format!("{}\n{}", RECAP_HEADER, lines.join("\n")),
+    })
+}
+
+fn first_sentence(text: &str) -> String {
+    let flat = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    let end = flat
+        .find(|c: char| matches!(c, '.' | '!' | '?'))
+        .map(|i| i + 1)
+        .unwrap_or(flat.len());
+    let sentence = &flat[..end];
+    if sentence.chars().count() > RECAP_LINE_CHARS {
+        let clipped: String = sentence.chars().take(RECAP_LINE_CHARS).collect();
+        format!("{}…", clipped.trim_end())
+    } else {
+        sentence.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llama::template::{ChatMl, MistralInstruct};
+
+    fn msg(role: MessageRole, content: &str) -> ChatMessage {
+        ChatMessage { role, content: content.to_string() }
+    }
+
+    fn conversation(turns: usize) -> Vec<ChatMessage> {
+        let mut messages = vec![msg(MessageRole::System, "You are a tutor.")];
+        for i in 0..turns {
+            messages.push(msg(MessageRole::User, &format!("Question {}. {}", i, "word ".repeat(60))));
+            messages.push(msg(MessageRole::Assistant, &format!("Answer {}. {}", i, "word ".repeat(60))));
+        }
+        messages.push(msg(MessageRole::User, "Final question?"));
+        messages
+    }
+
+    #[test]
+    fn short_history_is_untouched() {
+        let messages = conversation(2);
+        let fitted = fit_to_window(&messages, CONTEXT_TOKENS, &ChatMl).unwrap();
+        assert_eq!(fitted.len(), messages.len());
+    }
+
+    #[test]
+    fn long_history_is_trimmed_and_recapped() {
+        let messages = conversation(40);
+        let budget = 1024;
+        let fitted = fit_to_window(&messages, budget, &ChatMl).unwrap();
+
+        let costs = Costs { overhead: ChatMl.message_overhead(), count: &estimate_tokens };
+        let cost: usize = fitted.iter().map(|m| costs.message(m)).sum();
+        assert!(cost <= budget, "fitted cost {} exceeds budget {}", cost, budget);
+        assert!(matches!(fitted[0].role, MessageRole::System));
+        assert!(fitted[1].content.starts_with(RECAP_HEADER));
+        assert_eq!(fitted.last().unwrap().content, "Final question?");
+        // Window opens on a user turn, right after the recap.
+        assert!(matches!(fitted[2].role, MessageRole::User));
+    }
+
+    #[test]
+    fn custom_counter_drives_the_budget() {
+        // One "token" per word: far cheaper than the byte estimate.
+        let words = |text: &str| text.split_whitespace().count();
+        let messages = conversation(6);
+        assert!(fit_to_window(&messages, 1024, &ChatMl).unwrap().len() < messages.len());
+        assert_eq!(fit_to_window_with(&messages, 1024, &ChatMl, &words).unwrap().len(), messages.len());
+    }
+
+    #[test]
+    fn overhead_comes_from_template() {
+        let words = |text: &str| text.split_whitespace().count();
+        let messages = conversation(6);
+        let budget = fit_to_window_with(&messages, usize::MAX, &ChatMl, &words)
+            .unwrap()
+            .iter()
+            .map(|m| words(&m.content) + ChatMl.message_overhead())
+            .sum();
+        // Exactly enough with ChatML framing; the heavier Mistral tags overflow it.
+        assert_eq!(fit_to_window_with(&messages, budget, &ChatMl, &words).unwrap().len(), messages.len());
+        assert!(fit_to_window_with(&messages, budget, &MistralInstruct, &words).unwrap().len() < messages.len());
+    }
+
+    #[test]
+    fn newest_turn_that_cannot_fit_is_an_error() {
+        let messages = conversation(3);
+        let fitted = fit_to_window(&messages, 1, &ChatMl);
+        assert!(matches!(fitted, Err(LLMError::ContextOverflow { available: 1, .. })));
+
+        // Room for the system prompt and the question, but not for any recap.
+        let tight = estimate_tokens("You are a tutor.") + estimate_tokens("Final question?") + 2 * ChatMl.message_overhead();
+        let fitted = fit_to_window(&messages, tight, &ChatMl).unwrap();
+        assert_eq!(fitted.len(), 2);
+        assert_eq!(fitted.last().unwrap().content, "Final question?");
+    }
+}
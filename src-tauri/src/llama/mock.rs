@@ -0,0 +1,249 @@
+//! Deterministic stand-in for `LLMEngine`.
+//!
+//! Replies come from a script, in order; once it runs dry (or if there never
+//! was one) the backend echoes the last user message back. Every prompt it
+//! sees is recorded so tests can assert on what the pipeline actually sent.
+//! One whitespace-delimited word counts as one "token", both for streaming
+//! and `count_tokens`; `max_tokens`, stop strings, cancellation and
+//! deadlines behave as they do on the real engine.
+
+use super::{
+    stop, ChatMessage, ChatTemplate, GenerationConfig, LLMError, LlmBackend, MessageRole, Result,
+    TemplateKind, CONTEXT_TOKENS,
+};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+pub struct MockBackend {
+    script: Mutex<VecDeque<String>>,
+    prompts: Mutex<Vec<String>>,
+    template: Arc<dyn ChatTemplate>,
+}
+
+impl Default for MockBackend {
+    fn default() -> Self {
+        Self {
+            script: Mutex::default(),
+            prompts: Mutex::default(),
+            template: TemplateKind::ChatMl.template(),
+        }
+    }
+}
+
+impl MockBackend {
+    /// Answers every request with the user's own words.
+    pub fn echo() -> Self {
+        Self::default()
+    }
+
+    /// Answers with `replies` in order, then falls back to echoing.
+    pub fn scripted<I, S>(replies: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            script: Mutex::new(replies.into_iter().map(Into::into).collect()),
+            ..Self::default()
+        }
+    }
+
+    /// Renders chats as `kind` instead of ChatML, like a model that ships
+    /// another format.
+    pub fn with_template(mut self, kind: TemplateKind) -> Self {
+        self.template = kind.template();
+        self
+    }
+
+    pub fn push_reply(&self, reply: impl Into<String>) {
+        self.script.lock().unwrap().push_back(reply.into());
+    }
+
+    /// Every prompt received so far; chats are rendered with the template
+    /// first.
+    pub fn prompts(&self) -> Vec<String> {
+        self.prompts.lock().unwrap().clone()
+    }
+
+    fn respond(
+        &self,
+        prompt: String,
+        echo: &str,
+        config: Option<GenerationConfig>,
+        on_token: &mut dyn FnMut(&str),
+    ) -> Result<String> {
+        let config = config.unwrap_or_default();
+        self.prompts.lock().unwrap().push(prompt);
+        let mut reply = self
+            .script
+            .lock()
+            .unwrap()
+            .pop_front()
+            .unwrap_or_else(|| echo.to_string());
+        if let Some(pos) = stop::find_stop(&reply, &config.stop) {
+            reply.truncate(pos);
+        }
+
+        let mut output = String::new();
+        for word in reply.split_inclusive(' ').take(config.max_tokens.max(0) as usize) {
+            if let Some(reason) = config.interrupt_reason() {
+                return Err(LLMError::Cancelled { partial: output, reason });
+            }
+            output.push_str(word);
+            on_token(word);
+        }
+        Ok(output)
+    }
+}
+
+impl LlmBackend for MockBackend {
+    fn generate(&self, prompt: &str, config: Option<GenerationConfig>) -> Result<String> {
+        self.generate_stream(prompt, config, &mut |_| {})
+    }
+
+    fn generate_stream(
+        &self,
+        prompt: &str,
+        config: Option<GenerationConfig>,
+        on_token: &mut dyn FnMut(&str),
+    ) -> Result<String> {
+        if prompt.trim().is_empty() {
+            return Err(LLMError::InvalidInput {
+                details: "Empty prompt provided".to_string(),
+            });
+        }
+        self.respond(prompt.to_string(), prompt, config, on_token)
+    }
+
+    fn chat(&self, messages: &[ChatMessage], config: Option<GenerationConfig>) -> Result<String> {
+        self.chat_stream(messages, config, &mut |_| {})
+    }
+
+    fn chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        config: Option<GenerationConfig>,
+        on_token: &mut dyn FnMut(&str),
+    ) -> Result<String> {
+        let last_user = messages
+            .iter()
+            .rev()
+            .find(|msg| matches!(msg.role, MessageRole::User))
+            .ok_or_else(|| LLMError::InvalidInput {
+                details: "No user message found".to_string(),
+            })?;
+        let prompt = self.template.render(messages);
+        self.respond(prompt, &last_user.content, config, on_token)
+    }
+
+    fn count_tokens(&self, text: &str) -> Result<usize> {
+        Ok(text.split_whitespace().count())
+    }
+
+    fn context_length(&self) -> usize {
+        CONTEXT_TOKENS
+    }
+
+    fn model_info(&self) -> String {
+        "Mock backend - no model loaded".to_string()
+    }
+
+    fn template(&self) -> Arc<dyn ChatTemplate> {
+        Arc::clone(&self.template)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llama::{CancelReason, CancellationToken};
+
+    fn user(content: &str) -> ChatMessage {
+        ChatMessage { role: MessageRole::User, content: content.to_string() }
+    }
+
+    #[test]
+    fn test_script_then_echo() {
+        let mock = MockBackend::scripted(["first", "second"]);
+        assert_eq!(mock.chat(&[user("hi")], None).unwrap(), "first");
+        assert_eq!(mock.chat(&[user("hi")], None).unwrap(), "second");
+        assert_eq!(mock.chat(&[user("hi")], None).unwrap(), "hi");
+        assert_eq!(mock.prompts().len(), 3);
+        assert!(mock.prompts()[0].contains("<|im_start|>user\nhi<|im_end|>"));
+    }
+
+    #[test]
+    fn test_chats_use_selected_template() {
+        let mock = MockBackend::echo().with_template(TemplateKind::Llama3);
+        mock.chat(&[user("hi")], None).unwrap();
+        assert!(mock.prompts()[0].contains("<|start_header_id|>user<|end_header_id|>\n\nhi"));
+        assert_eq!(mock.template().name(), "llama-3");
+    }
+
+    #[test]
+    fn test_stream_deltas_match_reply() {
+        let mock = MockBackend::scripted(["one two three"]);
+        let mut deltas = Vec::new();
+        let full = mock
+            .generate_stream("count", None, &mut |d| deltas.push(d.to_string()))
+            .unwrap();
+        assert_eq!(deltas, ["one ", "two ", "three"]);
+        assert_eq!(deltas.concat(), full);
+    }
+
+    #[test]
+    fn test_limits_and_stop_strings() {
+        let mock = MockBackend::scripted(["a b c d", "keep END drop"]);
+        let short = GenerationConfig { max_tokens: 2, ..Default::default() };
+        assert_eq!(mock.generate("x", Some(short)).unwrap(), "a b ");
+        let stop = GenerationConfig { stop: vec!["END".to_string()], ..Default::default() };
+        assert_eq!(mock.generate("x", Some(stop)).unwrap(), "keep ");
+    }
+
+    #[test]
+    fn test_cancellation_keeps_partial() {
+        let mock = MockBackend::scripted(["one two three"]);
+        let token = CancellationToken::new();
+        let config = GenerationConfig::default().with_cancel(token.clone());
+        let result = mock.generate_stream("x", Some(config), &mut |_| token.cancel());
+        match result {
+            Err(LLMError::Cancelled { partial, reason }) => {
+                assert_eq!(partial, "one ");
+                assert_eq!(reason, CancelReason::Requested);
+            }
+            other => panic!("Expected cancellation, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_structured_output_through_dyn_backend() {
+        #[derive(serde::Deserialize)]
+        struct Answer {
+            value: i64,
+        }
+        let mock = MockBackend::scripted(["not json", "{\"value\": 42"]);
+        let backend: &dyn LlmBackend = &mock;
+        let schema = serde_json::json!({
+            "type": "object",
+            "properties": { "value": { "type": "integer" } }
+        });
+        let answer: Answer = backend.generate_json("x", &schema, None).unwrap();
+        assert_eq!(answer.value, 42);
+        assert_eq!(mock.prompts().len(), 2, "First reply should have been retried");
+    }
+
+    #[test]
+    fn test_counts_words_as_tokens() {
+        let mock = MockBackend::echo();
+        assert_eq!(mock.count_tokens("  three little  words ").unwrap(), 3);
+        assert_eq!(mock.count_tokens("").unwrap(), 0);
+        assert_eq!(mock.context_length(), CONTEXT_TOKENS);
+    }
+
+    #[test]
+    fn test_rejects_missing_user_turn() {
+        let mock = MockBackend::echo();
+        let system = ChatMessage { role: MessageRole::System, content: "sys".to_string() };
+        assert!(matches!(mock.chat(&[system], None), Err(LLMError::InvalidInput { .. })));
+    }
+}
@@ -1,9 +1,28 @@
+use serde::de::DeserializeOwned;
+use std::ffi::{CStr, CString};
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use thiserror::Error;
+use crate::registry::{ModelKind, ModelRegistry};
 
+mod backend;
 mod ffi;
-use ffi::RawEngine;
+pub mod grammar;
+mod history;
+mod mock;
+mod session;
+mod stop;
+pub mod template;
+use ffi::{RawEngine, SamplingParams};
+use stop::StopBuffer;
+
+pub use backend::LlmBackend;
+pub use history::CONTEXT_TOKENS;
+pub use mock::MockBackend;
+pub use session::SessionStore;
+pub use template::{ChatTemplate, TemplateKind};
 
 /**
  * Safe Rust Wrapper for LLM Engine
@@ -25,25 +44,137 @@ pub enum LLMError {
     ModelNotFound { path: String },
     #[error("Invalid input parameters: {details}")]
     InvalidInput { details: String },
+    #[error("Generation cancelled ({reason:?}) after {} bytes", .partial.len())]
+    Cancelled { partial: String, reason: CancelReason },
+    #[error("Model output is not valid JSON for the schema: {reason}")]
+    InvalidJson { reason: String, raw: String },
+    #[error("Session snapshot failed: {path}")]
+    SessionFailed { path: String },
+    #[error("Prompt needs {needed} tokens but only {available} fit in the context")]
+    ContextOverflow { needed: usize, available: usize },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CancelReason {
+    /// `CancellationToken::cancel` was called.
+    Requested,
+    /// The wall-clock deadline in `GenerationConfig` passed.
+    DeadlineExceeded,
+}
+
+/// Cheap, cloneable stop flag shared between the generating thread and
+/// whoever wants to abort it (e.g. the `cancel_generation` command).
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
 }
 
 pub type Result<T> = std::result::Result<T, LLMError>;
 
+/// Vocabulary id as used by llama.cpp.
+pub type Token = i32;
+
+/// Structured calls are re-run this many times in total when the output
+/// still fails to parse after repair. Retries are greedy and get twice the
+/// token budget, since truncation is the usual failure under a grammar.
+const JSON_ATTEMPTS: usize = 2;
+
 #[derive(Debug, Clone)]
 pub struct GenerationConfig {
     pub max_tokens: i32,
     pub temperature: f32,
+    /// Nucleus sampling; 1.0 disables it.
+    pub top_p: f32,
+    /// 0 disables top-k truncation.
+    pub top_k: i32,
+    /// Drops tokens below `min_p * p(best)`; 0.0 disables it.
+    pub min_p: f32,
+    /// 1.0 disables the repetition penalty.
+    pub repeat_penalty: f32,
+    /// How many recent tokens the penalties look at.
+    pub repeat_last_n: i32,
+    pub frequency_penalty: f32,
+    pub presence_penalty: f32,
+    /// Fixed seed for reproducible sampling; `None` picks a random one.
+    pub seed: Option<u32>,
+    /// Generation ends before the first occurrence of any of these.
+    pub stop: Vec<String>,
+    /// Checked between tokens and prompt batches; cancelling it aborts
+    /// generation.
+    pub cancel: Option<CancellationToken>,
+    /// Absolute wall-clock cut-off, checked between tokens.
+    pub deadline: Option<Instant>,
+    /// GBNF grammar (start rule `root`) every sampled token must satisfy.
+    pub grammar: Option<String>,
 }
 
 impl Default for GenerationConfig {
     fn default() -> Self {
+        // Sampling defaults follow llama.cpp's own CLI defaults.
         Self {
             max_tokens: 512,
             temperature: 0.7,
+            top_p: 0.95,
+            top_k: 40,
+            min_p: 0.05,
+            repeat_penalty: 1.1,
+            repeat_last_n: 64,
+            frequency_penalty: 0.0,
+            presence_penalty: 0.0,
+            seed: None,
+            stop: Vec::new(),
+            cancel: None,
+            deadline: None,
+            grammar: None,
         }
     }
 }
 
+impl GenerationConfig {
+    pub fn with_cancel(mut self, token: CancellationToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.deadline = Some(Instant::now() + timeout);
+        self
+    }
+    fn sampling_params(&self, grammar: Option<&CStr>) -> SamplingParams {
+        SamplingParams {
+            max_tokens: self.max_tokens,
+            temperature: self.temperature,
+            top_p: self.top_p,
+            top_k: self.top_k,
+            min_p: self.min_p,
+            repeat_penalty: self.repeat_penalty,
+            repeat_last_n: self.repeat_last_n,
+            frequency_penalty: self.frequency_penalty,
+            presence_penalty: self.presence_penalty,
+            seed: self.seed.unwrap_or(ffi::RANDOM_SEED),
+            grammar: grammar.map_or(std::ptr::null(), CStr::as_ptr),
+        }
+    }
+    fn interrupt_reason(&self) -> Option<CancelReason> {
+        if self.cancel.as_ref().map_or(false, |t| t.is_cancelled()) {
+            return Some(CancelReason::Requested);
+        }
+        if self.deadline.map_or(false, |d| Instant::now() >= d) {
+            return Some(CancelReason::DeadlineExceeded);
+        }
+        None
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ChatMessage {
     pub role: MessageRole,
@@ -60,6 +191,18 @@ pub enum MessageRole {
 pub struct LLMEngine {
     inner: Arc<Mutex<Option<RawEngine>>>,
     model_path: String,
+    template: Arc<dyn ChatTemplate>,
+    // Conversation whose snapshot the KV cache currently matches, if any.
+    session: Arc<Mutex<Option<String>>>,
+}
+
+// Which llama context a generation runs on. Only `Conversation` is ever
+// snapshotted; everything else (input analysis, grading, plain `generate`)
+// goes to `Scratch`, so it never evicts the conversation being cached.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Slot {
+    Conversation,
+    Scratch,
 }
 
 impl LLMEngine {
@@ -75,27 +218,151 @@ impl LLMEngine {
                 }
             })?
         };
+        // An explicit `ATHENA_CHAT_TEMPLATE` beats whatever the metadata says.
+        let kind = TemplateKind::from_env().unwrap_or_else(|| unsafe {
+            TemplateKind::detect(
+                raw_engine.meta("tokenizer.chat_template").as_deref(),
+                raw_engine.meta("general.architecture").as_deref(),
+            )
+        });
         Ok(LLMEngine {
             inner: Arc::new(Mutex::new(Some(raw_engine))),
             model_path: path_str,
+            template: kind.template(),
+            session: Arc::new(Mutex::new(None)),
         })
     }
+    /// Overrides the template detected from GGUF metadata.
+    pub fn with_template(mut self, kind: TemplateKind) -> Self {
+        self.template = kind.template();
+        self
+    }
+    pub fn chat_template(&self) -> &dyn ChatTemplate {
+        self.template.as_ref()
+    }
+    /// Renders `messages` with the model's chat template, ready for `generate`.
+    pub fn render_chat(&self, messages: &[ChatMessage]) -> String {
+        self.template.render(messages)
+    }
+    /// Tokenizes with the model's own vocabulary. No BOS is added, and
+    /// control-token text (e.g. `<|im_start|>`) maps to the control token, so
+    /// counts of rendered prompts match what generation will see.
+    pub fn tokenize(&self, text: &str) -> Result<Vec<Token>> {
+        let guard = self.inner.lock().unwrap();
+        match guard.as_ref() {
+            Some(engine) => unsafe { engine.tokenize(text, false, true) }.ok_or_else(|| {
+                LLMError::InvalidInput {
+                    details: "Text could not be tokenized".to_string(),
+                }
+            }),
+            None => Err(LLMError::EngineNotLoaded),
+        }
+    }
+    pub fn detokenize(&self, tokens: &[Token]) -> Result<String> {
+        let guard = self.inner.lock().unwrap();
+        match guard.as_ref() {
+            Some(engine) => unsafe { engine.detokenize(tokens) }.ok_or_else(|| {
+                LLMError::InvalidInput {
+                    details: "Token ids are outside the vocabulary".to_string(),
+                }
+            }),
+            None => Err(LLMError::EngineNotLoaded),
+        }
+    }
+    pub fn count_tokens(&self, text: &str) -> Result<usize> {
+        self.tokenize(text).map(|tokens| tokens.len())
+    }
+    /// Context window the engine was created with, in tokens.
+    pub fn context_length(&self) -> usize {
+        let guard = self.inner.lock().unwrap();
+        match guard.as_ref() {
+            Some(engine) => unsafe { engine.context_length() },
+            None => CONTEXT_TOKENS,
+        }
+    }
     pub fn generate(&self, prompt: &str, config: Option<GenerationConfig>) -> Result<String> {
+        self.run(Slot::Scratch, prompt, config.unwrap_or_default(), &mut |_| {})
+    }
+    /// Streaming counterpart of `generate`. `on_token` receives each text delta
+    /// as soon as it is decoded; the full response is still returned at the end.
+    pub fn generate_stream<F>(
+        &self,
+        prompt: &str,
+        config: Option<GenerationConfig>,
+        mut on_token: F,
+    ) -> Result<String>
+    where
+        F: FnMut(&str),
+    {
+        self.run(Slot::Scratch, prompt, config.unwrap_or_default(), &mut on_token)
+    }
+    // Every generation goes through the streaming entry point so the
+    // cancellation token and deadline can be honoured between tokens, and
+    // between prompt batches during prefill.
+    fn run(
+        &self,
+        slot: Slot,
+        prompt: &str,
+        config: GenerationConfig,
+        on_token: &mut dyn FnMut(&str),
+    ) -> Result<String> {
         if prompt.trim().is_empty() {
             return Err(LLMError::InvalidInput {
                 details: "Empty prompt provided".to_string(),
             });
         }
-        let config = config.unwrap_or_default();
+        if let Some(reason) = config.interrupt_reason() {
+            return Err(LLMError::Cancelled { partial: String::new(), reason });
+        }
+        let grammar = config
+            .grammar
+            .as_deref()
+            .map(CString::new)
+            .transpose()
+            .map_err(|_| LLMError::InvalidInput {
+                details: "Grammar contains a NUL byte".to_string(),
+            })?;
         let guard = self.inner.lock().unwrap();
+        if slot == Slot::Conversation {
+            // Whatever conversation was cached, this prompt is about to replace it.
+            *self.session.lock().unwrap() = None;
+        }
         match guard.as_ref() {
             Some(engine) => {
+                let params = config.sampling_params(grammar.as_deref());
+                let mut stops = StopBuffer::new(&config.stop);
+                let mut stopped = None;
+                let mut forward = |delta: &str| {
+                    let ready = stops.push(delta);
+                    if !ready.is_empty() {
+                        on_token(&ready);
+                    }
+                    if stops.is_stopped() {
+                        return false;
+                    }
+                    stopped = config.interrupt_reason();
+                    stopped.is_none()
+                };
                 let result = unsafe {
-                    engine.generate(prompt, config.max_tokens, config.temperature)
+                    match slot {
+                        Slot::Conversation => engine.generate_stream(prompt, &params, &mut forward),
+                        Slot::Scratch => engine.generate_scratch(prompt, &params, &mut forward),
+                    }
                 };
-                result.ok_or_else(|| LLMError::GenerationFailed {
+                let tail = stops.finish();
+                if !tail.is_empty() {
+                    on_token(&tail);
+                }
+                let mut output = result.ok_or_else(|| LLMError::GenerationFailed {
                     reason: "C++ engine returned null result".to_string(),
-                })
+                })?;
+                if let Some(pos) = stop::find_stop(&output, &config.stop) {
+                    output.truncate(pos);
+                }
+                match stopped {
+                    Some(reason) => Err(LLMError::Cancelled { partial: output, reason }),
+                    None => Ok(output),
+                }
             }
             None => Err(LLMError::EngineNotLoaded),
         }
@@ -105,35 +372,155 @@ impl LLMEngine {
         messages: &[ChatMessage],
         config: Option<GenerationConfig>,
     ) -> Result<String> {
+        let config = config.unwrap_or_default();
+        let prompt = self.conversation_prompt(messages, &config)?;
+        self.generate(&prompt, Some(config))
+    }
+    pub fn chat_stream<F>(
+        &self,
+        messages: &[ChatMessage],
+        config: Option<GenerationConfig>,
+        on_token: F,
+    ) -> Result<String>
+    where
+        F: FnMut(&str),
+    {
+        let config = config.unwrap_or_default();
+        let prompt = self.conversation_prompt(messages, &config)?;
+        self.generate_stream(&prompt, Some(config), on_token)
+    }
+    /// `chat_stream` for a persistent conversation. The conversation's KV-cache
+    /// snapshot is restored from `store` first (unless it is still live), so
+    /// only the new turn gets evaluated, and the cache is snapshotted again
+    /// afterwards. Snapshots are best-effort: failing to read or write one only
+    /// costs speed, never correctness. This is the only entry point that runs
+    /// on the conversation's context; other prompts leave it live.
+    pub fn chat_stream_in_session<F>(
+        &self,
+        store: &SessionStore,
+        conversation_id: &str,
+        messages: &[ChatMessage],
+        config: Option<GenerationConfig>,
+        mut on_token: F,
+    ) -> Result<String>
+    where
+        F: FnMut(&str),
+    {
+        let path = store.path_for(conversation_id, &self.model_path);
+        let live = self.session.lock().unwrap().as_deref() == Some(conversation_id);
+        if !live && path.exists() {
+            let _ = self.load_session(&path);
+        }
+        let config = config.unwrap_or_default();
+        let result = self
+            .conversation_prompt(messages, &config)
+            .and_then(|prompt| self.run(Slot::Conversation, &prompt, config, &mut on_token));
+        if matches!(result, Ok(_) | Err(LLMError::Cancelled { .. })) && self.save_session(&path).is_ok() {
+            *self.session.lock().unwrap() = Some(conversation_id.to_string());
+        }
+        result
+    }
+    /// Writes the KV cache, and the tokens it holds, to `path`.
+    pub fn save_session<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path_str = path.as_ref().to_string_lossy().to_string();
+        let guard = self.inner.lock().unwrap();
+        match guard.as_ref() {
+            Some(engine) if unsafe { engine.save_session(&path_str) } => Ok(()),
+            Some(_) => Err(LLMError::SessionFailed { path: path_str }),
+            None => Err(LLMError::EngineNotLoaded),
+        }
+    }
+    /// Restores a snapshot written by `save_session` and returns how many
+    /// tokens it holds. On failure the cache is left empty.
+    pub fn load_session<P: AsRef<Path>>(&self, path: P) -> Result<usize> {
+        let path_str = path.as_ref().to_string_lossy().to_string();
+        let guard = self.inner.lock().unwrap();
+        *self.session.lock().unwrap() = None;
+        match guard.as_ref() {
+            Some(engine) => unsafe { engine.load_session(&path_str) }
+                .ok_or(LLMError::SessionFailed { path: path_str }),
+            None => Err(LLMError::EngineNotLoaded),
+        }
+    }
+    pub fn reset_session(&self) {
+        let guard = self.inner.lock().unwrap();
+        *self.session.lock().unwrap() = None;
+        if let Some(engine) = guard.as_ref() {
+            unsafe { engine.reset_session() }
+        }
+    }
+    /// Prompt tokens the most recent generation actually had to evaluate,
+    /// i.e. the part not already in the KV cache.
+    pub fn last_prompt_evaluated(&self) -> usize {
+        let guard = self.inner.lock().unwrap();
+        match guard.as_ref() {
+            Some(engine) => unsafe { engine.last_prompt_eval() },
+            None => 0,
+        }
+    }
+    // Full history, trimmed to whatever the context has left after reserving
+    // room for the reply.
+    fn conversation_prompt(&self, messages: &[ChatMessage], config: &GenerationConfig) -> Result<String> {
         if messages.is_empty() {
             return Err(LLMError::InvalidInput {
                 details: "No messages provided".to_string(),
             });
         }
-        let config = config.unwrap_or_default();
-        let system_prompt = messages
-            .iter()
-            .find(|msg| matches!(msg.role, MessageRole::System))
-            .map(|msg| msg.content.as_str());
-        let user_message = messages
-            .iter()
-            .rev()
-            .find(|msg| matches!(msg.role, MessageRole::User))
-            .ok_or_else(|| LLMError::InvalidInput {
+        if !messages.iter().any(|msg| matches!(msg.role, MessageRole::User)) {
+            return Err(LLMError::InvalidInput {
                 details: "No user message found".to_string(),
-            })?;
-        let guard = self.inner.lock().unwrap();
-        match guard.as_ref() {
-            Some(engine) => {
-                let result = unsafe {
-                    engine.chat(system_prompt, &user_message.content, config.max_tokens)
-                };
-                result.ok_or_else(|| LLMError::GenerationFailed {
-                    reason: "Chat generation failed".to_string(),
-                })
-            }
-            None => Err(LLMError::EngineNotLoaded),
+            });
+        }
+        let budget = self.context_length().saturating_sub(config.max_tokens.max(0) as usize);
+        let count = |text: &str| {
+            self.count_tokens(text)
+                .unwrap_or_else(|_| history::estimate_tokens(text))
+        };
+        let fitted = history::fit_to_window_with(messages, budget, self.template.as_ref(), &count)?;
+        Ok(self.render_chat(&fitted))
+    }
+    /// Generates JSON constrained by `schema` and deserialises it into `T`.
+    /// Output that still fails to parse goes through `grammar::repair` and,
+    /// failing that, a greedy retry before giving up with `InvalidJson`.
+    pub fn generate_json<T: DeserializeOwned>(
+        &self,
+        prompt: &str,
+        schema: &serde_json::Value,
+        config: Option<GenerationConfig>,
+    ) -> Result<T> {
+        structured(schema, config, |config| self.generate(prompt, Some(config)))
+    }
+    /// `generate_json` over a chat conversation.
+    pub fn chat_json<T: DeserializeOwned>(
+        &self,
+        messages: &[ChatMessage],
+        schema: &serde_json::Value,
+        config: Option<GenerationConfig>,
+    ) -> Result<T> {
+        structured(schema, config, |config| self.chat(messages, Some(config)))
+    }
+    pub fn simple_chat_stream<F>(
+        &self,
+        user_message: &str,
+        system_prompt: Option<&str>,
+        config: Option<GenerationConfig>,
+        on_token: F,
+    ) -> Result<String>
+    where
+        F: FnMut(&str),
+    {
+        let mut messages = Vec::new();
+        if let Some(sys) = system_prompt {
+            messages.push(ChatMessage {
+                role: MessageRole::System,
+                content: sys.to_string(),
+            });
         }
+        messages.push(ChatMessage {
+            role: MessageRole::User,
+            content: user_message.to_string(),
+        });
+        self.chat_stream(&messages, config, on_token)
     }
     pub fn simple_chat(
         &self,
@@ -177,6 +564,37 @@ impl LLMEngine {
     }
 }
 
+// Shared by `LLMEngine` and `dyn LlmBackend`: constrain decoding to `schema`,
+// parse (repairing if needed) and retry before giving up.
+fn structured<T, F>(
+    schema: &serde_json::Value,
+    config: Option<GenerationConfig>,
+    attempt: F,
+) -> Result<T>
+where
+    T: DeserializeOwned,
+    F: Fn(GenerationConfig) -> Result<String>,
+{
+    let mut config = config.unwrap_or_default();
+    config.grammar = Some(grammar::from_schema(schema).map_err(|details| {
+        LLMError::InvalidInput { details }
+    })?);
+    let mut failure = None;
+    for i in 0..JSON_ATTEMPTS {
+        if i > 0 {
+            config.temperature = 0.0;
+            config.max_tokens = config.max_tokens.saturating_mul(2);
+        }
+        let raw = attempt(config.clone())?;
+        match grammar::parse(&raw) {
+            Ok(value) => return Ok(value),
+            Err(reason) => failure = Some((reason, raw)),
+        }
+    }
+    let (reason, raw) = failure.unwrap_or_default();
+    Err(LLMError::InvalidJson { reason, raw })
+}
+
 unsafe impl Send for LLMEngine {}
 unsafe impl Sync for LLMEngine {}
 
@@ -185,14 +603,22 @@ impl Clone for LLMEngine {
         LLMEngine {
             inner: Arc::clone(&self.inner),
             model_path: self.model_path.clone(),
+            template: Arc::clone(&self.template),
+            session: Arc::clone(&self.session),
         }
     }
 }
 
 impl LLMEngine {
+    /// Loads the preferred chat model under `ModelRegistry::default_dir()`.
     pub fn from_models_dir() -> Result<Self> {
-        let model_path = "llama/models/qwen2.5-0.5b-instruct-q5_k_m.gguf";
-        Self::new(model_path)
+        let dir = ModelRegistry::default_dir();
+        let not_found = || LLMError::ModelNotFound {
+            path: dir.display().to_string(),
+        };
+        let registry = ModelRegistry::scan(&dir).map_err(|_| not_found())?;
+        let model = registry.preferred(ModelKind::Chat).map_err(|_| not_found())?;
+        Self::new(&model.path)
     }
     pub fn complete(&self, prompt: &str) -> Result<String> {
         self.generate(prompt, None)
@@ -218,5 +644,152 @@ mod tests {
         assert!(result.is_ok(), "Simple generation should work");
         println!("Generated: {}", result.unwrap());
     }
+    #[test]
+    fn test_streaming_matches_full_output() {
+        let engine = LLMEngine::from_models_dir().unwrap();
+        let mut streamed = String::new();
+        let config = GenerationConfig { temperature: 0.0, ..Default::default() };
+        let full = engine
+            .generate_stream("Hello, world!", Some(config), |delta| streamed.push_str(delta))
+            .unwrap();
+        assert_eq!(streamed, full, "Concatenated deltas should equal the final text");
+    }
+    #[test]
+    fn test_tokenize_round_trip() {
+        let engine = LLMEngine::from_models_dir().unwrap();
+        let text = "Rust lifetimes, explained: 'a outlives 'b.";
+        let tokens = engine.tokenize(text).unwrap();
+        assert_eq!(engine.count_tokens(text).unwrap(), tokens.len());
+        assert!(tokens.len() < text.split_whitespace().count() * 4);
+        assert_eq!(engine.detokenize(&tokens).unwrap(), text);
+        assert_eq!(engine.context_length(), CONTEXT_TOKENS);
+        // Control tokens count as one each.
+        assert_eq!(engine.count_tokens("<|im_start|>").unwrap(), 1);
+    }
+    #[test]
+    fn test_qwen_template_detected() {
+        let engine = LLMEngine::from_models_dir().unwrap();
+        assert_eq!(engine.chat_template().name(), "chatml");
+    }
+    #[test]
+    fn test_seed_makes_sampling_reproducible() {
+        let engine = LLMEngine::from_models_dir().unwrap();
+        let config = GenerationConfig { seed: Some(42), max_tokens: 32, ..Default::default() };
+        let first = engine.generate("Name a chess opening:", Some(config.clone())).unwrap();
+        let second = engine.generate("Name a chess opening:", Some(config)).unwrap();
+        assert_eq!(first, second, "Same seed should sample the same tokens");
+    }
+    #[test]
+    fn test_stop_sequence_truncates_output() {
+        let engine = LLMEngine::from_models_dir().unwrap();
+        let config = GenerationConfig { stop: vec!["3".to_string()], temperature: 0.0, ..Default::default() };
+        let output = engine.generate("Count from 1 to 5: 1, 2,", Some(config)).unwrap();
+        assert!(!output.contains('3'), "Output should end before the stop string: {:?}", output);
+    }
+    #[test]
+    fn test_cancellation_returns_partial_output() {
+        let engine = LLMEngine::from_models_dir().unwrap();
+        let token = CancellationToken::new();
+        let config = GenerationConfig::default().with_cancel(token.clone());
+        let mut seen = 0;
+        let result = engine.generate_stream("Write a long story about chess.", Some(config), |_| {
+            seen += 1;
+            if seen == 3 {
+                token.cancel();
+            }
+        });
+        match result {
+            Err(LLMError::Cancelled { partial, reason }) => {
+                assert_eq!(reason, CancelReason::Requested);
+                assert!(!partial.is_empty(), "Partial output should be returned");
+            }
+            other => panic!("Expected cancellation, got {:?}", other),
+        }
+    }
+    #[test]
+    fn test_generate_json_follows_schema() {
+        #[derive(serde::Deserialize)]
+        struct Opening {
+            name: String,
+            moves: Vec<String>,
+        }
+        let engine = LLMEngine::from_models_dir().unwrap();
+        let schema = serde_json::json!({
+            "type": "object",
+            "properties": {
+                "name": { "type": "string" },
+                "moves": { "type": "array", "items": { "type": "string" } }
+            },
+            "required": ["name", "moves"]
+        });
+        let config = GenerationConfig { max_tokens: 96, ..Default::default() };
+        let opening: Opening = engine
+            .generate_json("Describe a chess opening as JSON:", &schema, Some(config))
+            .unwrap();
+        assert!(!opening.name.is_empty());
+        assert!(opening.moves.len() < 96);
+    }
+    #[test]
+    fn test_session_only_evaluates_new_turn() {
+        let engine = LLMEngine::from_models_dir().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let store = SessionStore::new(dir.path()).unwrap();
+        let config = GenerationConfig { max_tokens: 8, temperature: 0.0, ..Default::default() };
+        let mut messages = vec![
+            ChatMessage { role: MessageRole::System, content: "You are a patient chess tutor. ".repeat(20) },
+            ChatMessage { role: MessageRole::User, content: "Name a chess opening.".to_string() },
+        ];
+        let reply = engine
+            .chat_stream_in_session(&store, "c1", &messages, Some(config.clone()), |_| {})
+            .unwrap();
+        let cold = engine.last_prompt_evaluated();
+
+        // A reset clobbers the cache; the snapshot has to bring it back.
+        engine.reset_session();
+        messages.push(ChatMessage { role: MessageRole::Assistant, content: reply });
+        messages.push(ChatMessage { role: MessageRole::User, content: "Another one?".to_string() });
+        engine
+            .chat_stream_in_session(&store, "c1", &messages, Some(config), |_| {})
+            .unwrap();
+        let warm = engine.last_prompt_evaluated();
+        assert!(warm * 2 < cold, "Expected only the new turn to be evaluated: {} vs {}", warm, cold);
+    }
+    #[test]
+    fn test_one_off_prompts_keep_the_conversation_cached() {
+        let engine = LLMEngine::from_models_dir().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let store = SessionStore::new(dir.path()).unwrap();
+        let config = GenerationConfig { max_tokens: 8, temperature: 0.0, ..Default::default() };
+        let mut messages = vec![
+            ChatMessage { role: MessageRole::System, content: "You are a patient chess tutor. ".repeat(20) },
+            ChatMessage { role: MessageRole::User, content: "Name a chess opening.".to_string() },
+        ];
+        let reply = engine
+            .chat_stream_in_session(&store, "c1", &messages, Some(config.clone()), |_| {})
+            .unwrap();
+        let cold = engine.last_prompt_evaluated();
+
+        // Analysis-style prompts run on the scratch context; with the snapshot
+        // gone, only the live cache can keep the next turn warm.
+        engine.generate("Classify this input: Name a chess opening.", Some(config.clone())).unwrap();
+        std::fs::remove_file(store.path_for("c1", &engine.model_path)).unwrap();
+        messages.push(ChatMessage { role: MessageRole::Assistant, content: reply });
+        messages.push(ChatMessage { role: MessageRole::User, content: "Another one?".to_string() });
+        engine
+            .chat_stream_in_session(&store, "c1", &messages, Some(config), |_| {})
+            .unwrap();
+        let warm = engine.last_prompt_evaluated();
+        assert!(warm * 2 < cold, "Expected the conversation to stay cached: {} vs {}", warm, cold);
+    }
+    #[test]
+    fn test_expired_deadline_never_starts() {
+        let engine = LLMEngine::from_models_dir().unwrap();
+        let config = GenerationConfig::default().with_timeout(Duration::ZERO);
+        let result = engine.generate("Hello, world!", Some(config));
+        assert!(matches!(
+            result,
+            Err(LLMError::Cancelled { reason: CancelReason::DeadlineExceeded, .. })
+        ));
+    }
     
 }
\ No newline at end of file
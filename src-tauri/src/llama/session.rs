@@ -0,0 +1,92 @@
+//! On-disk layout for per-conversation KV-cache snapshots.
+//!
+//! One file per (conversation, model) pair: a snapshot taken with one model
+//! is meaningless to another, so the model's file stem is part of the name.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+const EXTENSION: &str = "session";
+
+#[derive(Debug, Clone)]
+pub struct SessionStore {
+    dir: PathBuf,
+}
+
+impl SessionStore {
+    /// Uses `dir` for snapshots, creating it if needed.
+    pub fn new<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+        std::fs::create_dir_all(dir.as_ref())?;
+        Ok(Self {
+            dir: dir.as_ref().to_path_buf(),
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn path_for(&self, conversation_id: &str, model_path: &str) -> PathBuf {
+        let model = Path::new(model_path)
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        self.dir.join(format!(
+            "{}.{}.{}",
+            sanitize(conversation_id),
+            sanitize(&model),
+            EXTENSION
+        ))
+    }
+
+    /// Deletes every snapshot of `conversation_id`, whatever the model.
+    pub fn remove(&self, conversation_id: &str) -> io::Result<()> {
+        let prefix = format!("{}.", sanitize(conversation_id));
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+            if name.starts_with(&prefix) && path.extension().is_some_and(|ext| ext == EXTENSION) {
+                std::fs::remove_file(&path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Conversation IDs come from the frontend; keep them from escaping the dir.
+// Dots are replaced too, since they separate the name's fields.
+fn sanitize(part: &str) -> String {
+    part.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paths_are_scoped_per_model_and_sanitized() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SessionStore::new(dir.path().join("sessions")).unwrap();
+        let path = store.path_for("../etc/passwd", "models/qwen2.5-0.5b.gguf");
+        assert_eq!(path.parent(), Some(store.dir()));
+        assert_eq!(path.file_name().unwrap(), "___etc_passwd.qwen2_5-0_5b.session");
+        assert_ne!(path, store.path_for("../etc/passwd", "models/llama.gguf"));
+    }
+
+    #[test]
+    fn test_remove_only_touches_one_conversation() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SessionStore::new(dir.path()).unwrap();
+        let a1 = store.path_for("a", "qwen.gguf");
+        let a2 = store.path_for("a", "llama.gguf");
+        let ab = store.path_for("ab", "qwen.gguf");
+        for path in [&a1, &a2, &ab] {
+            std::fs::write(path, b"kv").unwrap();
+        }
+        store.remove("a").unwrap();
+        assert!(!a1.exists() && !a2.exists());
+        assert!(ab.exists());
+    }
+}
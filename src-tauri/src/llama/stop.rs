@@ -0,0 +1,113 @@
+//! Stop-sequence detection over a stream of decoded deltas.
+//!
+//! A stop string can straddle several tokens, so anything that could still be
+//! the start of one is held back until the next delta settles it.
+
+pub struct StopBuffer<'a> {
+    stops: &'a [String],
+    held: String,
+    hit: bool,
+}
+
+impl<'a> StopBuffer<'a> {
+    pub fn new(stops: &'a [String]) -> Self {
+        Self {
+            stops,
+            held: String::new(),
+            hit: false,
+        }
+    }
+
+    /// Feeds a delta and returns the text that is now safe to emit.
+    pub fn push(&mut self, delta: &str) -> String {
+        if self.hit {
+            return String::new();
+        }
+        self.held.push_str(delta);
+        if let Some(pos) = find_stop(&self.held, self.stops) {
+            self.hit = true;
+            let out = self.held[..pos].to_string();
+            self.held.clear();
+            return out;
+        }
+        let keep = self.partial_suffix_len();
+        self.held.drain(..self.held.len() - keep).collect()
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.hit
+    }
+
+    /// Releases whatever was held back once generation is over.
+    pub fn finish(&mut self) -> String {
+        std::mem::take(&mut self.held)
+    }
+
+    // Longest tail of `held` that is also a proper prefix of some stop string.
+    fn partial_suffix_len(&self) -> usize {
+        self.stops
+            .iter()
+            .filter_map(|stop| {
+                (1..stop.len()).rev().find(|&k| {
+                    stop.is_char_boundary(k) && self.held.ends_with(&stop[..k])
+                })
+            })
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// Byte offset of the earliest stop string in `text`, if any.
+pub fn find_stop(text: &str, stops: &[String]) -> Option<usize> {
+    stops
+        .iter()
+        .filter(|s| !s.is_empty())
+        .filter_map(|s| text.find(s.as_str()))
+        .min()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stops(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn passes_text_through_without_stops() {
+        let stops = stops(&[]);
+        let mut buf = StopBuffer::new(&stops);
+        assert_eq!(buf.push("hello "), "hello ");
+        assert_eq!(buf.push("world"), "world");
+        assert_eq!(buf.finish(), "");
+    }
+
+    #[test]
+    fn stop_split_across_deltas_is_caught() {
+        let stops = stops(&["\nUser:"]);
+        let mut buf = StopBuffer::new(&stops);
+        assert_eq!(buf.push("Sure.\nUs"), "Sure.");
+        assert!(!buf.is_stopped());
+        assert_eq!(buf.push("er: next"), "");
+        assert!(buf.is_stopped());
+        assert_eq!(buf.push("ignored"), "");
+    }
+
+    #[test]
+    fn false_alarm_is_released() {
+        let stops = stops(&["###"]);
+        let mut buf = StopBuffer::new(&stops);
+        assert_eq!(buf.push("C#"), "C");
+        assert_eq!(buf.push(" is fine"), "# is fine");
+        assert_eq!(buf.push("##"), "");
+        assert_eq!(buf.finish(), "##");
+    }
+
+    #[test]
+    fn earliest_stop_wins() {
+        let stops = stops(&["END", "\n\n"]);
+        assert_eq!(find_stop("a\n\nb END", &stops), Some(1));
+        assert_eq!(find_stop("nothing", &stops), None);
+    }
+}
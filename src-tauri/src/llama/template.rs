@@ -0,0 +1,346 @@
+//! Chat-template renderers.
+//!
+//! Each instruct family wraps turns in its own control tokens. The template
+//! is picked from the GGUF `tokenizer.chat_template` / `general.architecture`
+//! metadata at load time, or forced through `ATHENA_CHAT_TEMPLATE` (see
+//! `TemplateKind::from_env`) or `LLMEngine::with_template`.
+//! BOS is never emitted here - `llama_tokenize` adds it when the model wants one.
+
+use super::{ChatMessage, MessageRole};
+use std::sync::Arc;
+
+pub trait ChatTemplate: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Renders the conversation and opens the assistant turn for generation.
+    fn render(&self, messages: &[ChatMessage]) -> String;
+
+    /// Tokens of framing the template puts around each message, on top of
+    /// its content.
+    fn message_overhead(&self) -> usize;
+}
+
+/// Environment variable naming the template to use whatever the model's
+/// metadata says, e.g. `ATHENA_CHAT_TEMPLATE=llama-2`.
+pub const TEMPLATE_ENV: &str = "ATHENA_CHAT_TEMPLATE";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TemplateKind {
+    ChatMl,
+    Llama2,
+    Llama3,
+    Gemma,
+    MistralInstruct,
+    Phi,
+}
+
+impl TemplateKind {
+    /// Parses a configuration name such as `"chatml"` or `"llama-3"`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().replace(['-', '_', ' '], "").as_str() {
+            "chatml" | "qwen" | "qwen2" => Some(Self::ChatMl),
+            "llama2" => Some(Self::Llama2),
+            "llama3" => Some(Self::Llama3),
+            "gemma" | "gemma2" => Some(Self::Gemma),
+            "mistral" | "mistralinstruct" => Some(Self::MistralInstruct),
+            "phi" | "phi3" => Some(Self::Phi),
+            _ => None,
+        }
+    }
+
+    /// The template forced through `TEMPLATE_ENV`, if it names one.
+    pub fn from_env() -> Option<Self> {
+        std::env::var(TEMPLATE_ENV).ok().and_then(|name| Self::from_name(&name))
+    }
+
+    /// Best guess from GGUF metadata. The embedded Jinja template is the most
+    /// reliable signal; the architecture is the fallback; ChatML the default.
+    pub fn detect(chat_template: Option<&str>, architecture: Option<&str>) -> Self {
+        if let Some(tpl) = chat_template {
+            if tpl.contains("<|im_start|>") {
+                return Self::ChatMl;
+            }
+            if tpl.contains("<|start_header_id|>") {
+                return Self::Llama3;
+            }
+            if tpl.contains("<start_of_turn>") {
+                return Self::Gemma;
+            }
+            if tpl.contains("<|assistant|>") && tpl.contains("<|end|>") {
+                return Self::Phi;
+            }
+            if tpl.contains("<<SYS>>") {
+                return Self::Llama2;
+            }
+            if tpl.contains("[INST]") {
+                return Self::MistralInstruct;
+            }
+        }
+        // Llama-3 GGUFs always embed their template, so a bare "llama" is
+        // most likely Llama-2 or one of its fine-tunes.
+        match architecture.unwrap_or_default() {
+            "llama" => Self::Llama2,
+            "gemma" | "gemma2" => Self::Gemma,
+            "phi3" => Self::Phi,
+            "mistral" => Self::MistralInstruct,
+            _ => Self::ChatMl,
+        }
+    }
+
+    pub fn template(self) -> Arc<dyn ChatTemplate> {
+        match self {
+            Self::ChatMl => Arc::new(ChatMl),
+            Self::Llama2 => Arc::new(Llama2),
+            Self::Llama3 => Arc::new(Llama3),
+            Self::Gemma => Arc::new(Gemma),
+            Self::MistralInstruct => Arc::new(MistralInstruct),
+            Self::Phi => Arc::new(Phi),
+        }
+    }
+}
+
+fn role_name(role: &MessageRole) -> &'static str {
+    match role {
+        MessageRole::System => "system",
+        MessageRole::User => "user",
+        MessageRole::Assistant => "assistant",
+    }
+}
+
+/// Folds system messages into the first user turn, for formats without a system role.
+fn fold_system(messages: &[ChatMessage]) -> Vec<(MessageRole, String)> {
+    let system: Vec<&str> = messages
+        .iter()
+        .filter(|m| matches!(m.role, MessageRole::System))
+        .map(|m| m.content.as_str())
+        .collect();
+    let mut pending = (!system.is_empty()).then(|| system.join("\n\n"));
+    let mut turns = Vec::new();
+    for msg in messages.iter().filter(|m| !matches!(m.role, MessageRole::System)) {
+        let content = match (&msg.role, pending.take()) {
+            (MessageRole::User, Some(sys)) => format!("{}\n\n{}", sys, msg.content),
+            (_, leftover) => {
+                pending = leftover;
+                msg.content.clone()
+            }
+        };
+        turns.push((msg.role.clone(), content));
+    }
+    // System prompt with no user turn at all: still deliver it.
+    if let Some(sys) = pending {
+        turns.insert(0, (MessageRole::User, sys));
+    }
+    turns
+}
+
+/// Qwen, Hermes, Yi and friends.
+pub struct ChatMl;
+
+impl ChatTemplate for ChatMl {
+    fn name(&self) -> &'static str { "chatml" }
+
+    // `<|im_start|>role\n` + `<|im_end|>\n`.
+    fn message_overhead(&self) -> usize { 6 }
+
+    fn render(&self, messages: &[ChatMessage]) -> String {
+        let mut prompt = String::new();
+        for msg in messages {
+            prompt.push_str(&format!("<|im_start|>{}\n{}<|im_end|>\n", role_name(&msg.role), msg.content));
+        }
+        prompt.push_str("<|im_start|>assistant\n");
+        prompt
+    }
+}
+
+/// `[INST] ... [/INST]` pairs, with the system prompt in a `<<SYS>>` block
+/// at the top of the first instruction.
+pub struct Llama2;
+
+impl ChatTemplate for Llama2 {
+    fn name(&self) -> &'static str { "llama-2" }
+
+    // `[INST]`, `[/INST]` and `</s><s>` between turns are all plain text.
+    fn message_overhead(&self) -> usize { 10 }
+
+    fn render(&self, messages: &[ChatMessage]) -> String {
+        let system: Vec<&str> = messages
+            .iter()
+            .filter(|m| matches!(m.role, MessageRole::System))
+            .map(|m| m.content.as_str())
+            .collect();
+        let mut wrapped = Vec::new();
+        if !system.is_empty() {
+            wrapped.push(ChatMessage {
+                role: MessageRole::System,
+                content: format!("<<SYS>>\n{}\n<</SYS>>", system.join("\n\n")),
+            });
+        }
+        wrapped.extend(messages.iter().filter(|m| !matches!(m.role, MessageRole::System)).cloned());
+
+        let mut prompt = String::new();
+        for (role, content) in fold_system(&wrapped) {
+            match role {
+                MessageRole::Assistant => prompt.push_str(&format!(" {} </s>", content.trim())),
+                _ if prompt.is_empty() => prompt.push_str(&format!("[INST] {} [/INST]", content.trim())),
+                _ => prompt.push_str(&format!("<s>[INST] {} [/INST]", content.trim())),
+            }
+        }
+        prompt
+    }
+}
+
+pub struct Llama3;
+
+impl ChatTemplate for Llama3 {
+    fn name(&self) -> &'static str { "llama-3" }
+
+    // `<|start_header_id|>role<|end_header_id|>\n\n` + `<|eot_id|>`.
+    fn message_overhead(&self) -> usize { 5 }
+
+    fn render(&self, messages: &[ChatMessage]) -> String {
+        let mut prompt = String::new();
+        for msg in messages {
+            prompt.push_str(&format!(
+                "<|start_header_id|>{}<|end_header_id|>\n\n{}<|eot_id|>",
+                role_name(&msg.role),
+                msg.content.trim()
+            ));
+        }
+        prompt.push_str("<|start_header_id|>assistant<|end_header_id|>\n\n");
+        prompt
+    }
+}
+
+/// Gemma has no system role and calls the assistant "model".
+pub struct Gemma;
+
+impl ChatTemplate for Gemma {
+    fn name(&self) -> &'static str { "gemma" }
+
+    // `<start_of_turn>role\n` + `<end_of_turn>\n`.
+    fn message_overhead(&self) -> usize { 5 }
+
+    fn render(&self, messages: &[ChatMessage]) -> String {
+        let mut prompt = String::new();
+        for (role, content) in fold_system(messages) {
+            let role = match role {
+                MessageRole::Assistant => "model",
+                _ => "user",
+            };
+            prompt.push_str(&format!("<start_of_turn>{}\n{}<end_of_turn>\n", role, content.trim()));
+        }
+        prompt.push_str("<start_of_turn>model\n");
+        prompt
+    }
+}
+
+/// `[INST] ... [/INST]` pairs; the system prompt rides along in the first instruction.
+pub struct MistralInstruct;
+
+impl ChatTemplate for MistralInstruct {
+    fn name(&self) -> &'static str { "mistral-instruct" }
+
+    // `[INST]` and `[/INST]` are plain text in older vocabularies, several
+    // tokens each.
+    fn message_overhead(&self) -> usize { 9 }
+
+    fn render(&self, messages: &[ChatMessage]) -> String {
+        let mut prompt = String::new();
+        for (role, content) in fold_system(messages) {
+            match role {
+                MessageRole::Assistant => prompt.push_str(&format!(" {}</s>", content.trim())),
+                _ => prompt.push_str(&format!("[INST] {} [/INST]", content.trim())),
+            }
+        }
+        prompt
+    }
+}
+
+/// Phi-3 / Phi-3.5 instruct.
+pub struct Phi;
+
+impl ChatTemplate for Phi {
+    fn name(&self) -> &'static str { "phi" }
+
+    // `<|role|>\n` + `<|end|>\n`.
+    fn message_overhead(&self) -> usize { 4 }
+
+    fn render(&self, messages: &[ChatMessage]) -> String {
+        let mut prompt = String::new();
+        for msg in messages {
+            prompt.push_str(&format!("<|{}|>\n{}<|end|>\n", role_name(&msg.role), msg.content.trim()));
+        }
+        prompt.push_str("<|assistant|>\n");
+        prompt
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conversation() -> Vec<ChatMessage> {
+        vec![
+            ChatMessage { role: MessageRole::System, content: "sys".into() },
+            ChatMessage { role: MessageRole::User, content: "first".into() },
+            ChatMessage { role: MessageRole::Assistant, content: "reply".into() },
+            ChatMessage { role: MessageRole::User, content: "second".into() },
+        ]
+    }
+
+    #[test]
+    fn chatml_keeps_every_turn() {
+        let prompt = ChatMl.render(&conversation());
+        assert!(prompt.starts_with("<|im_start|>system\nsys<|im_end|>\n"));
+        assert!(prompt.contains("<|im_start|>assistant\nreply<|im_end|>"));
+        assert!(prompt.ends_with("<|im_start|>user\nsecond<|im_end|>\n<|im_start|>assistant\n"));
+    }
+
+    #[test]
+    fn llama2_wraps_system_prompt() {
+        let prompt = Llama2.render(&conversation());
+        assert_eq!(
+            prompt,
+            "[INST] <<SYS>>\nsys\n<</SYS>>\n\nfirst [/INST] reply </s><s>[INST] second [/INST]"
+        );
+    }
+
+    #[test]
+    fn llama3_uses_headers() {
+        let prompt = Llama3.render(&conversation());
+        assert!(prompt.starts_with("<|start_header_id|>system<|end_header_id|>\n\nsys<|eot_id|>"));
+        assert!(prompt.ends_with("<|start_header_id|>assistant<|end_header_id|>\n\n"));
+    }
+
+    #[test]
+    fn gemma_folds_system_into_first_user_turn() {
+        let prompt = Gemma.render(&conversation());
+        assert!(prompt.starts_with("<start_of_turn>user\nsys\n\nfirst<end_of_turn>\n"));
+        assert!(prompt.contains("<start_of_turn>model\nreply<end_of_turn>"));
+        assert!(prompt.ends_with("<start_of_turn>model\n"));
+    }
+
+    #[test]
+    fn mistral_wraps_instructions() {
+        let prompt = MistralInstruct.render(&conversation());
+        assert_eq!(prompt, "[INST] sys\n\nfirst [/INST] reply</s>[INST] second [/INST]");
+    }
+
+    #[test]
+    fn phi_uses_end_markers() {
+        let prompt = Phi.render(&conversation());
+        assert!(prompt.contains("<|user|>\nfirst<|end|>\n<|assistant|>\nreply<|end|>"));
+        assert!(prompt.ends_with("<|assistant|>\n"));
+    }
+
+    #[test]
+    fn detection_prefers_embedded_template() {
+        assert_eq!(TemplateKind::detect(Some("{{ '<|im_start|>' }}"), Some("llama")), TemplateKind::ChatMl);
+        assert_eq!(TemplateKind::detect(None, Some("gemma2")), TemplateKind::Gemma);
+        assert_eq!(TemplateKind::detect(Some("[INST] {{ m }} [/INST]"), Some("llama")), TemplateKind::MistralInstruct);
+        assert_eq!(TemplateKind::detect(Some("[INST] <<SYS>>"), None), TemplateKind::Llama2);
+        assert_eq!(TemplateKind::detect(None, Some("llama")), TemplateKind::Llama2);
+        assert_eq!(TemplateKind::detect(None, None), TemplateKind::ChatMl);
+        assert_eq!(TemplateKind::from_name("Llama-3"), Some(TemplateKind::Llama3));
+    }
+}
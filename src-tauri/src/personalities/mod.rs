@@ -1,6 +1,8 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::OnceLock;
 use toml;
+use crate::llama::GenerationConfig;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PersonaMetadata {
@@ -154,10 +156,54 @@ pub struct CollaborationProtocols {
 pub struct ModelSettings {
     pub temperature: f64,
     pub top_p: f64,
+    // Optional sampling overrides - anything left out keeps the engine default.
+    #[serde(default)]
+    pub top_k: Option<i32>,
+    #[serde(default)]
+    pub min_p: Option<f64>,
+    #[serde(default)]
+    pub repeat_penalty: Option<f64>,
+    #[serde(default)]
+    pub frequency_penalty: Option<f64>,
+    #[serde(default)]
+    pub presence_penalty: Option<f64>,
+    #[serde(default)]
+    pub seed: Option<u32>,
+    #[serde(default)]
+    pub stop: Vec<String>,
     pub operational_mode: String,
     pub reporting_frequency: String,
 }
 
+impl ModelSettings {
+    /// Engine defaults overlaid with whatever this persona pins down.
+    pub fn generation_config(&self) -> GenerationConfig {
+        let mut config = GenerationConfig {
+            temperature: self.temperature as f32,
+            top_p: self.top_p as f32,
+            seed: self.seed,
+            stop: self.stop.clone(),
+            ..Default::default()
+        };
+        if let Some(top_k) = self.top_k {
+            config.top_k = top_k;
+        }
+        if let Some(min_p) = self.min_p {
+            config.min_p = min_p as f32;
+        }
+        if let Some(penalty) = self.repeat_penalty {
+            config.repeat_penalty = penalty as f32;
+        }
+        if let Some(penalty) = self.frequency_penalty {
+            config.frequency_penalty = penalty as f32;
+        }
+        if let Some(penalty) = self.presence_penalty {
+            config.presence_penalty = penalty as f32;
+        }
+        config
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VisualProfile {
     pub symbol: String,
@@ -329,7 +375,16 @@ pub struct PersonaRegistry {
     pub viktor: PersonaConfiguration,
 }
 
+static REGISTRY: OnceLock<Option<PersonaRegistry>> = OnceLock::new();
+
 impl PersonaRegistry {
+    /// Process-wide registry, parsed once on first use.
+    pub fn shared() -> Option<&'static PersonaRegistry> {
+        REGISTRY
+            .get_or_init(|| Self::load_from_files().ok())
+            .as_ref()
+    }
+
     pub fn load_from_files() -> Result<Self, Box<dyn std::error::Error>> {
         let erika = toml::from_str::<PersonaConfiguration>(include_str!("Erika.toml"))?;
         let aurora = toml::from_str::<PersonaConfiguration>(include_str!("Aurora.toml"))?;
@@ -378,6 +433,10 @@ impl PersonaConfiguration {
         self.model_settings.top_p
     }
     
+    pub fn generation_config(&self) -> GenerationConfig {
+        self.model_settings.generation_config()
+    }
+    
     pub fn is_formal(&self) -> bool {
         self.interaction_style.formality_level >= 7
     }
@@ -438,4 +497,19 @@ mod tests {
         assert!(!viktor.supports_humor());
         assert!(viktor.is_direct());
     }
+    
+    #[test]
+    fn test_persona_sampling_defaults() {
+        let registry = PersonaRegistry::load_from_files().expect("Failed to load personas");
+        
+        let aurora = registry.aurora.generation_config();
+        assert_eq!(aurora.temperature, 0.3);
+        assert_eq!(aurora.top_p, 0.8);
+        
+        for (name, persona) in registry.get_all_personas() {
+            let config = persona.generation_config();
+            assert_eq!(config.top_p, persona.get_top_p() as f32, "{} top_p not applied", name);
+            assert!(config.top_k > 0, "{} should pin top_k", name);
+        }
+    }
 }
\ No newline at end of file
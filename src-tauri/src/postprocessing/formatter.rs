@@ -1,11 +1,21 @@
 //! Cleans and trims the raw LLM response.
 
+/// Fence tags a model puts on a reply it wrapped whole; a real language tag
+/// means the reply *is* code and keeps its fence.
+const WRAPPER_TAGS: &[&str] = &["", "markdown", "md", "text", "plaintext"];
+
 pub fn clean(raw: &str) -> String {
     let trimmed = raw.trim();
-    // Remove code-fence markers if LLM wrapped the answer.
-    trimmed
-        .trim_start_matches("```")
-        .trim_end_matches("```")
-        .trim()
-        .to_string()
-}
\ No newline at end of file
+    // Remove code-fence markers only if the LLM wrapped the whole answer.
+    unwrap_fence(trimmed).unwrap_or(trimmed).to_string()
+}
+
+/// The body of `text` when it is exactly one fence with a wrapper tag.
+fn unwrap_fence(text: &str) -> Option<&str> {
+    let body = text.strip_prefix("```")?.strip_suffix("```")?;
+    let (tag, body) = body.split_once('\n')?;
+    if body.contains("```") || !WRAPPER_TAGS.contains(&tag.trim().to_lowercase().as_str()) {
+        return None;
+    }
+    Some(body.trim())
+}
@@ -4,9 +4,95 @@ pub mod context;
 pub mod formatter;
 pub mod interpreter;
 pub mod persona;
+pub mod shaping;
 pub mod templates;
 pub mod traits;
 pub mod validator;
 
+pub use formatter::clean;
+pub use shaping::{shape, shape_question, QuizItem};
+pub use traits::PersonaFilter;
+pub use validator::{validate, MAX_RESPONSE_BYTES};
+
+use crate::llama::{ChatMessage, GenerationConfig, LlmBackend, MessageRole};
+use crate::preprocessing::{Mode, Personality, Proficiency};
+use persona::PersonaApplier;
+
+/// Runs a raw reply through interpretation, cleanup, validation, the
+/// mode's result shaping and the persona voice. The backend is only
+/// consulted to condense replies that blow past `MAX_RESPONSE_BYTES`.
+pub struct PostProcessor<'a> {
+    llm: &'a dyn LlmBackend,
+    asking: bool,
+}
+
+impl<'a> PostProcessor<'a> {
+    pub fn new(llm: &'a dyn LlmBackend) -> Self {
+        Self { llm, asking: false }
+    }
+
+    /// Marks the reply as a new quiz question, whose answer key must not
+    /// reach the learner even when the reply can't be parsed.
+    pub fn asking_quiz(mut self, asking: bool) -> Self {
+        self.asking = asking;
+        self
+    }
+
+    pub async fn process(
+        &self,
+        reply: String,
+        personality: Personality,
+        mode: Mode,
+        proficiency: Proficiency,
+    ) -> String {
+        let text = clean(&interpreter::interpret(&reply));
+        let text = match validate(&text) {
+            Ok(text) => text,
+            Err(_) if text.len() > MAX_RESPONSE_BYTES => self.condense(&text, &mode, &proficiency),
+            Err(_) => defuse(&text),
+        };
+        let text = if self.asking { shape_question(&text) } else { shape(mode, &text) };
+        PersonaApplier { name: format!("{:?}", personality) }.apply(&text)
+    }
+
+    // One attempt at a shorter rewrite; hard truncation if that doesn't land.
+    fn condense(&self, text: &str, mode: &Mode, proficiency: &Proficiency) -> String {
+        let messages = [
+            ChatMessage {
+                role: MessageRole::System,
+                content: format!(
+                    "Condense this {:?} answer for a {:?} learner to under 400 words. \
+                     Keep code blocks intact and add nothing new.",
+                    mode, proficiency
+                ),
+            },
+            ChatMessage { role: MessageRole::User, content: text.to_string() },
+        ];
+        let config = GenerationConfig { max_tokens: 768, temperature: 0.2, ..Default::default() };
+        self.llm
+            .chat(&messages, Some(config))
+            .ok()
+            .and_then(|short| validate(&clean(&short)).ok())
+            .unwrap_or_else(|| truncate(&defuse(text), MAX_RESPONSE_BYTES))
+    }
+}
+
+// Neutralise markup the validator refuses rather than dropping the reply.
+fn defuse(text: &str) -> String {
+    text.replace("<script>", "&lt;script&gt;")
+}
+
+fn truncate(text: &str, max_bytes: usize) -> String {
+    const ELLIPSIS: &str = "…";
+    if text.len() <= max_bytes {
+        return text.to_string();
+    }
+    let mut end = max_bytes - ELLIPSIS.len();
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}{}", &text[..end], ELLIPSIS)
+}
+
 #[cfg(test)]
-mod tests;
\ No newline at end of file
+mod tests;
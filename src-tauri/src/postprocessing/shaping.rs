@@ -0,0 +1,324 @@
+//! Mode-specific result shaping.
+//!
+//! Translator, Code Reviewer and Quiz prompts ask for a labelled layout (see
+//! `engine::output::strategies`). Small models follow it loosely, so labels
+//! match case-insensitively, with or without markdown bold, and a reply that
+//! can't be parsed is passed through untouched rather than lost; only a new
+//! quiz question still has its answer key cut out.
+
+use crate::preprocessing::Mode;
+
+/// Reshapes `text` for `mode`; Tutor and Assistant replies are left alone.
+pub fn shape(mode: Mode, text: &str) -> String {
+    let shaped = match mode {
+        Mode::Translator => parse_translation(text).map(|t| t.render()),
+        Mode::CodeReview => parse_review(text).map(|r| r.render()),
+        Mode::Quiz => parse_verdict(text)
+            .map(|g| g.render())
+            .or_else(|| parse_quiz_question(text).map(|q| q.render_question())),
+        Mode::Tutor | Mode::Assistant => None,
+    };
+    shaped.unwrap_or_else(|| text.to_string())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Translation {
+    pub text: String,
+    /// Each with a note on when to prefer it.
+    pub alternatives: Vec<String>,
+    pub register: Option<String>,
+}
+
+impl Translation {
+    fn render(&self) -> String {
+        let mut out = format!("**Translation:** {}", self.text);
+        if !self.alternatives.is_empty() {
+            out.push_str("\n\n**Alternatives:**");
+            for alternative in &self.alternatives {
+                out.push_str("\n- ");
+                out.push_str(alternative);
+            }
+        }
+        if let Some(register) = &self.register {
+            out.push_str("\n\n**Register:** ");
+            out.push_str(register);
+        }
+        out
+    }
+}
+
+pub fn parse_translation(raw: &str) -> Option<Translation> {
+    let sections = sections(raw, &["translation", "alternatives", "register"]);
+    let text = section(&sections, "translation")?;
+    Some(Translation {
+        text,
+        alternatives: section(&sections, "alternatives").map(|s| bullets(&s)).unwrap_or_default(),
+        register: section(&sections, "register"),
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Critical,
+    Warning,
+    Suggestion,
+}
+
+impl Severity {
+    fn parse(tag: &str) -> Option<Self> {
+        match tag.trim().to_lowercase().as_str() {
+            "critical" | "error" | "bug" => Some(Self::Critical),
+            "warning" | "warn" => Some(Self::Warning),
+            "suggestion" | "nit" | "style" | "info" => Some(Self::Suggestion),
+            _ => None,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::Critical => "Critical",
+            Self::Warning => "Warning",
+            Self::Suggestion => "Suggestion",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub severity: Severity,
+    /// `None` when the finding is about the snippet as a whole.
+    pub line: Option<u32>,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Review {
+    /// Most serious first.
+    pub findings: Vec<Finding>,
+    pub summary: Option<String>,
+}
+
+impl Review {
+    fn render(&self) -> String {
+        let mut out = if self.findings.is_empty() {
+            "No issues found.".to_string()
+        } else {
+            self.findings
+                .iter()
+                .map(|finding| match finding.line {
+                    Some(line) => format!("- **{}** (line {}): {}", finding.severity.label(), line, finding.message),
+                    None => format!("- **{}**: {}", finding.severity.label(), finding.message),
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        if let Some(summary) = &self.summary {
+            out.push_str("\n\n**Summary:** ");
+            out.push_str(summary);
+        }
+        out
+    }
+}
+
+/// `None` when the reply has neither a finding nor a summary, e.g. when the
+/// model asked for the snippet instead.
+pub fn parse_review(raw: &str) -> Option<Review> {
+    let mut findings: Vec<Finding> = raw.lines().filter_map(parse_finding).collect();
+    let summary = section(&sections(raw, &["summary"]), "summary");
+    if findings.is_empty() && summary.is_none() {
+        return None;
+    }
+    findings.sort_by_key(|finding| finding.severity);
+    Some(Review { findings, summary })
+}
+
+fn parse_finding(line: &str) -> Option<Finding> {
+    let line = strip_bullet(line).trim_start_matches('*');
+    let (tag, rest) = line.strip_prefix('[')?.split_once(']')?;
+    let severity = Severity::parse(tag)?;
+    let rest = rest.trim_start_matches('*').trim();
+
+    let mut line_number = None;
+    let mut message = rest;
+    if let Some(after) = strip_prefix_ignore_case(rest, "line ") {
+        let digits: String = after.chars().take_while(char::is_ascii_digit).collect();
+        if let Ok(n) = digits.parse() {
+            line_number = Some(n);
+            message = &after[digits.len()..];
+        }
+    }
+    let message = message.trim_start_matches([':', '-', ' ']).trim();
+    if message.is_empty() {
+        return None;
+    }
+    Some(Finding { severity, line: line_number, message: message.to_string() })
+}
+
+/// A question asked in quiz mode, kept until the learner answers it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuizItem {
+    /// The question, with its choices when it is multiple choice.
+    pub question: String,
+    pub answer: String,
+    pub explanation: Option<String>,
+}
+
+impl QuizItem {
+    /// What the learner gets to see: the answer key stays hidden.
+    fn render_question(&self) -> String {
+        format!("**Question:** {}", self.question)
+    }
+}
+
+/// Shapes the reply that asks a new quiz question. When it can't be parsed
+/// it still goes out, but without the answer key.
+pub fn shape_question(text: &str) -> String {
+    parse_quiz_question(text)
+        .map(|q| q.render_question())
+        .unwrap_or_else(|| hide_answer_key(text))
+}
+
+/// Drops `Answer:` and `Explanation:` lines and whatever follows them, up to
+/// the next `Question:`.
+fn hide_answer_key(raw: &str) -> String {
+    let mut hiding = false;
+    let mut kept = Vec::new();
+    for line in raw.lines() {
+        if ANSWER_KEY_LABELS.iter().any(|label| labelled(line, label).is_some()) {
+            hiding = true;
+        } else if labelled(line, "question").is_some() {
+            hiding = false;
+        }
+        if !hiding {
+            kept.push(line);
+        }
+    }
+    kept.join("\n").trim().to_string()
+}
+
+const ANSWER_KEY_LABELS: &[&str] = &["answer", "answer key", "correct answer", "explanation"];
+
+/// `None` unless the reply carries both a question and its answer.
+pub fn parse_quiz_question(raw: &str) -> Option<QuizItem> {
+    let sections = sections(raw, &["question", "answer", "explanation"]);
+    Some(QuizItem {
+        question: section(&sections, "question")?,
+        answer: section(&sections, "answer")?,
+        explanation: section(&sections, "explanation"),
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Correct,
+    PartiallyCorrect,
+    Incorrect,
+}
+
+impl Verdict {
+    fn parse(text: &str) -> Option<Self> {
+        let text = text.trim().to_lowercase();
+        // "partially correct" and "incorrect" both contain "correct".
+        if text.contains("partial") {
+            Some(Self::PartiallyCorrect)
+        } else if text.starts_with("incorrect") || text.starts_with("wrong") {
+            Some(Self::Incorrect)
+        } else if text.starts_with("correct") || text.starts_with("right") {
+            Some(Self::Correct)
+        } else {
+            None
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::Correct => "Correct",
+            Self::PartiallyCorrect => "Partially correct",
+            Self::Incorrect => "Incorrect",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Grade {
+    pub verdict: Verdict,
+    pub feedback: Option<String>,
+}
+
+impl Grade {
+    fn render(&self) -> String {
+        match &self.feedback {
+            Some(feedback) => format!("**{}.** {}", self.verdict.label(), feedback),
+            None => format!("**{}.**", self.verdict.label()),
+        }
+    }
+}
+
+pub fn parse_verdict(raw: &str) -> Option<Grade> {
+    let sections = sections(raw, &["verdict", "feedback"]);
+    Some(Grade {
+        verdict: Verdict::parse(&section(&sections, "verdict")?)?,
+        feedback: section(&sections, "feedback"),
+    })
+}
+
+/// Splits `raw` at lines starting with one of `labels`; lines that follow a
+/// label belong to it until the next one. Text before the first label is
+/// dropped.
+fn sections<'a>(raw: &str, labels: &[&'a str]) -> Vec<(&'a str, String)> {
+    let mut out: Vec<(&'a str, String)> = Vec::new();
+    for line in raw.lines() {
+        if let Some((label, value)) = labels.iter().find_map(|label| labelled(line, label).map(|v| (*label, v))) {
+            out.push((label, value.to_string()));
+        } else if let Some((_, value)) = out.last_mut() {
+            value.push('\n');
+            value.push_str(line);
+        }
+    }
+    out
+}
+
+/// The first non-empty section called `label`, trimmed.
+fn section(sections: &[(&str, String)], label: &str) -> Option<String> {
+    sections
+        .iter()
+        .filter(|(name, _)| *name == label)
+        .map(|(_, value)| value.trim())
+        .find(|value| !value.is_empty())
+        .map(str::to_string)
+}
+
+/// The text after `label:` on `line`; accepts `**Label:**`, `**Label**:`
+/// and markdown headings.
+fn labelled<'l>(line: &'l str, label: &str) -> Option<&'l str> {
+    let line = strip_bullet(line).trim_start_matches(['#', '*', ' ']);
+    let rest = strip_prefix_ignore_case(line, label)?;
+    let rest = rest.trim_start_matches('*').strip_prefix(':')?;
+    Some(rest.trim_start_matches('*').trim())
+}
+
+fn bullets(text: &str) -> Vec<String> {
+    text.lines()
+        .map(|line| strip_bullet(line).trim())
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Drops a leading `-`, `*` or `1.` list marker.
+fn strip_bullet(line: &str) -> &str {
+    let line = line.trim_start();
+    if let Some(rest) = line.strip_prefix("- ").or_else(|| line.strip_prefix("* ")) {
+        return rest;
+    }
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+    match line[digits..].strip_prefix(". ") {
+        Some(rest) if digits > 0 => rest,
+        _ => line,
+    }
+}
+
+fn strip_prefix_ignore_case<'s>(text: &'s str, prefix: &str) -> Option<&'s str> {
+    let head = text.get(..prefix.len())?;
+    head.eq_ignore_ascii_case(prefix).then(|| &text[prefix.len()..])
+}
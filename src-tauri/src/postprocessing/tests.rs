@@ -6,6 +6,19 @@ mod tests {
     fn clean_works() {
         let raw = "```\nhello\n```";
         assert_eq!(clean(raw), "hello");
+        assert_eq!(clean("```markdown\n**hi**\n```"), "**hi**");
+    }
+
+    #[test]
+    fn clean_keeps_code_fences() {
+        let ends_in_code = "Use a slice:\n```rust\nlet s = &v[..];\n```";
+        assert_eq!(clean(ends_in_code), ends_in_code);
+        let starts_with_code = "```rust\nlet s = &v[..];\n```\nThat borrows all of `v`.";
+        assert_eq!(clean(starts_with_code), starts_with_code);
+        let only_code = "```rust\nlet s = &v[..];\n```";
+        assert_eq!(clean(only_code), only_code);
+        let two_blocks = "```\na\n```\nand\n```\nb\n```";
+        assert_eq!(clean(two_blocks), two_blocks);
     }
 
     #[test]
@@ -25,4 +38,126 @@ mod tests {
     fn validate_rejects_script() {
         assert!(validate("<script>alert()</script>").is_err());
     }
+
+    mod shaping {
+        use crate::postprocessing::shaping::*;
+        use crate::preprocessing::Mode;
+
+        #[test]
+        fn translation_is_laid_out() {
+            let raw = "Sure!\n**Translation:** Je voudrais un café.\nAlternatives:\n- Un café, s'il vous plaît — shorter, just as polite\n* Je prendrais un café — when ordering at a table\nregister: neutral and polite";
+            let parsed = parse_translation(raw).unwrap();
+            assert_eq!(parsed.text, "Je voudrais un café.");
+            assert_eq!(parsed.alternatives.len(), 2);
+            assert_eq!(parsed.register.as_deref(), Some("neutral and polite"));
+
+            let shaped = shape(Mode::Translator, raw);
+            assert!(shaped.starts_with("**Translation:** Je voudrais un café."));
+            assert!(shaped.contains("\n- Je prendrais un café — when ordering at a table"));
+        }
+
+        #[test]
+        fn review_findings_sorted_by_severity() {
+            let raw = "1. [suggestion] line 4: name is vague — call it `total`\n\
+                       2. **[critical]** line 2: index out of bounds — use `get`\n\
+                       - [warning] unwrap on user input — return the error\n\
+                       Summary: works for the happy path only";
+            let review = parse_review(raw).unwrap();
+            let order: Vec<_> = review.findings.iter().map(|f| (f.severity, f.line)).collect();
+            assert_eq!(order, [
+                (Severity::Critical, Some(2)),
+                (Severity::Warning, None),
+                (Severity::Suggestion, Some(4)),
+            ]);
+            assert_eq!(review.findings[0].message, "index out of bounds — use `get`");
+
+            let clean = shape(Mode::CodeReview, "Summary: looks good");
+            assert_eq!(clean, "No issues found.\n\n**Summary:** looks good");
+        }
+
+        #[test]
+        fn quiz_question_hides_answer_key() {
+            let raw = "Question: Which keyword moves ownership into a closure?\nA) ref\nB) move\nAnswer: B) move\nExplanation: `move` captures by value.";
+            let item = parse_quiz_question(raw).unwrap();
+            assert_eq!(item.answer, "B) move");
+            assert_eq!(item.explanation.as_deref(), Some("`move` captures by value."));
+
+            let shaped = shape(Mode::Quiz, raw);
+            assert!(shaped.contains("B) move"));
+            assert!(!shaped.contains("Answer"));
+            assert!(!shaped.contains("captures by value"));
+        }
+
+        #[test]
+        fn unparsed_question_still_hides_answer_key() {
+            let raw = "Here's one: which keyword moves ownership into a closure?\nA) ref\nB) move\n**Answer:** B\nExplanation:\n`move` captures by value.";
+            let shaped = shape_question(raw);
+            assert!(shaped.contains("which keyword moves ownership"));
+            assert!(shaped.ends_with("B) move"));
+            assert!(!shaped.contains("Answer"));
+            assert!(!shaped.contains("captures by value"));
+        }
+
+        #[test]
+        fn quiz_grade_leads_with_verdict() {
+            let shaped = shape(Mode::Quiz, "Verdict: partially correct\nFeedback: right keyword, wrong reason.");
+            assert_eq!(shaped, "**Partially correct.** right keyword, wrong reason.");
+            assert_eq!(parse_verdict("**Verdict:** Incorrect").unwrap().verdict, Verdict::Incorrect);
+        }
+
+        #[test]
+        fn unparsable_replies_pass_through() {
+            let raw = "Could you paste the code you'd like me to review?";
+            assert_eq!(shape(Mode::CodeReview, raw), raw);
+            assert_eq!(shape(Mode::Translator, raw), raw);
+            assert_eq!(shape(Mode::Quiz, raw), raw);
+            assert_eq!(shape(Mode::Tutor, "Translation: x"), "Translation: x");
+        }
+    }
+
+    mod pipeline {
+        use crate::llama::MockBackend;
+        use crate::postprocessing::{PostProcessor, MAX_RESPONSE_BYTES};
+        use crate::preprocessing::{Mode, Personality, Proficiency};
+
+        #[tokio::test]
+        async fn short_reply_gets_persona_voice() {
+            let llm = MockBackend::echo();
+            let out = PostProcessor::new(&llm)
+                .process("```\nhello\n```".into(), Personality::Erika, Mode::Tutor, Proficiency::Beginner)
+                .await;
+            assert_eq!(out, "*giggles* hello");
+            assert!(llm.prompts().is_empty(), "Short replies should not hit the model");
+        }
+
+        #[tokio::test]
+        async fn long_reply_is_condensed_by_model() {
+            let llm = MockBackend::scripted(["short version"]);
+            let out = PostProcessor::new(&llm)
+                .process("word ".repeat(1000), Personality::Aurora, Mode::Assistant, Proficiency::Expert)
+                .await;
+            assert_eq!(out, "short version");
+            assert!(llm.prompts()[0].contains("Condense this Assistant answer for a Expert learner"));
+        }
+
+        #[tokio::test]
+        async fn long_reply_truncated_when_condensing_fails() {
+            // The echo backend hands the oversized text straight back.
+            let llm = MockBackend::echo();
+            let out = PostProcessor::new(&llm)
+                .process("é".repeat(3000), Personality::Aurora, Mode::Tutor, Proficiency::Beginner)
+                .await;
+            assert!(out.len() <= MAX_RESPONSE_BYTES);
+            assert!(out.ends_with('…'));
+        }
+
+        #[tokio::test]
+        async fn script_tags_are_defused() {
+            let llm = MockBackend::echo();
+            let out = PostProcessor::new(&llm)
+                .process("<script>x</script>".into(), Personality::Aurora, Mode::Tutor, Proficiency::Beginner)
+                .await;
+            assert!(!out.contains("<script>"));
+        }
+    }
 }
\ No newline at end of file
@@ -1,7 +1,10 @@
 //! Safety & policy checks.
 
+/// Upper bound on a reply, in bytes.
+pub const MAX_RESPONSE_BYTES: usize = 4096;
+
 pub fn validate(text: &str) -> Result<String, &'static str> {
-    if text.len() > 4096 {
+    if text.len() > MAX_RESPONSE_BYTES {
         return Err("response too long");
     }
     if text.contains("<script>") {
@@ -1,42 +1,164 @@
+//! Input normalisation ahead of analysis and tokenisation.
+//!
+//! Text is NFC-normalised so precomposed and combining forms compare equal,
+//! then stripped of control and invisible formatting characters. Whitespace
+//! is collapsed within lines but line breaks survive, and code blocks (as
+//! `Segmenter` finds them) are kept verbatim, since indentation is meaningful
+//! there.
+
+use super::segmenter::Segmenter;
 use thiserror::Error;
+use unicode_normalization::UnicodeNormalization;
 
 #[derive(Error, Debug)]
 pub enum CleanerError {
     #[error("Input is empty after cleaning")]
     EmptyInput,
-    #[error("Input exceeds maximum length: {0}")]
-    TooLong(usize),
+    #[error("Input is {count} characters long, the limit is {limit}")]
+    TooManyChars { count: usize, limit: usize },
+    #[error("Input is {count} bytes long, the limit is {limit}")]
+    TooManyBytes { count: usize, limit: usize },
 }
 
 pub struct Cleaner;
 
 impl Cleaner {
-    const MAX_LENGTH: usize = 10000;
-    
+    pub const MAX_CHARS: usize = 10_000;
+    /// Fits `MAX_CHARS` of CJK (3 bytes each) but not of emoji.
+    pub const MAX_BYTES: usize = 32 * 1024;
+
     pub fn clean(input: &str) -> Result<String, CleanerError> {
-        if input.trim().is_empty() {
-            return Err(CleanerError::EmptyInput);
+        // Cheap early exit before normalising something absurd.
+        if input.len() > Self::MAX_BYTES * 4 {
+            return Err(CleanerError::TooManyBytes { count: input.len(), limit: Self::MAX_BYTES });
         }
-        
-        let cleaned = input
-            .trim()
-            .lines()
-            .map(|line| line.trim())
-            .filter(|line| !line.is_empty())
+
+        let normalised: String = input.replace("\r\n", "\n").replace('\r', "\n").nfc().collect();
+
+        let stripped = normalised
+            .split('\n')
+            .map(|raw| raw.chars().filter(|&c| !is_invisible(c)).collect::<String>())
             .collect::<Vec<_>>()
-            .join(" ")
-            .chars()
-            .filter(|&c| c.is_ascii() || c.is_whitespace())
-            .collect::<String>();
-        
-        if cleaned.len() > Self::MAX_LENGTH {
-            return Err(CleanerError::TooLong(cleaned.len()));
+            .join("\n");
+        let code = Segmenter::code_mask(&stripped);
+
+        let mut lines: Vec<String> = Vec::new();
+        for (line, is_code) in stripped.split('\n').zip(code) {
+            if is_code {
+                lines.push(line.trim_end().to_string());
+            } else {
+                let collapsed = line.split_whitespace().collect::<Vec<_>>().join(" ");
+                // At most one blank line in a row outside code.
+                if collapsed.is_empty() && lines.last().is_none_or(|l| l.is_empty()) {
+                    continue;
+                }
+                lines.push(collapsed);
+            }
+        }
+        while lines.last().is_some_and(|l| l.is_empty()) {
+            lines.pop();
         }
-        
+        let cleaned = lines.join("\n");
+
         if cleaned.trim().is_empty() {
             return Err(CleanerError::EmptyInput);
         }
-        
+        let chars = cleaned.chars().count();
+        if chars > Self::MAX_CHARS {
+            return Err(CleanerError::TooManyChars { count: chars, limit: Self::MAX_CHARS });
+        }
+        if cleaned.len() > Self::MAX_BYTES {
+            return Err(CleanerError::TooManyBytes { count: cleaned.len(), limit: Self::MAX_BYTES });
+        }
+
         Ok(cleaned)
     }
-}
\ No newline at end of file
+}
+
+/// Characters that render as nothing but can change how text is read or
+/// matched. Tabs survive (code needs them); ZWJ and ZWNJ survive too, since
+/// emoji sequences and Persian or Indic spelling depend on them.
+fn is_invisible(c: char) -> bool {
+    match c {
+        '\t' => false,
+        '\u{200C}' | '\u{200D}' => false,
+        '\u{200B}' | '\u{2060}' | '\u{FEFF}' | '\u{00AD}' => true,
+        // Bidi marks, embeddings, overrides and isolates.
+        '\u{200E}' | '\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}' => true,
+        c => c.is_control(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_kept(text: &str) {
+        assert_eq!(Cleaner::clean(text).unwrap(), text);
+    }
+
+    #[test]
+    fn test_preserves_every_natural_language() {
+        // One sample per natural language in `categorize_domain`.
+        assert_kept("How do I use the present perfect?");
+        assert_kept("Où est la bibliothèque ? Ça dépend, garçon.");
+        assert_kept("Não sei se você já viu o coração da ação.");
+        assert_kept("Привет! Как дела? Я изучаю русский язык.");
+        assert_kept("¿Dónde está la estación? Mañana, señor.");
+        assert_kept("Größe, Übung und Straße sind schwierig.");
+        assert_kept("Perché è così difficile? Più tardi, città.");
+        assert_kept("日本語を勉強しています。カタカナも難しいです。");
+        assert_kept("我在学习中文，你好吗？");
+        assert_kept("مرحبا، كيف حالك؟ أنا أتعلم العربية.");
+        assert_kept("안녕하세요? 한국어를 배우고 있어요.");
+        assert_kept("שלום, מה שלומך? אני לומד עברית.");
+    }
+
+    #[test]
+    fn test_nfc_normalisation() {
+        // "e" + combining acute becomes the precomposed "é".
+        assert_eq!(Cleaner::clean("cafe\u{301}").unwrap(), "caf\u{e9}");
+        // Hangul jamo compose into syllables.
+        assert_eq!(Cleaner::clean("\u{1100}\u{1161}").unwrap(), "가");
+    }
+
+    #[test]
+    fn test_strips_controls_and_invisibles() {
+        assert_eq!(Cleaner::clean("a\u{200B}b\u{FEFF}c\u{7}d").unwrap(), "abcd");
+        assert_eq!(Cleaner::clean("abc\u{202E}def").unwrap(), "abcdef");
+        // Emoji ZWJ sequences stay intact.
+        assert_kept("👩\u{200D}💻 writes Rust");
+    }
+
+    #[test]
+    fn test_collapses_whitespace_but_keeps_lines() {
+        let input = "  first   line\t here  \r\n\r\n\r\n\nsecond\u{3000}line\n\n";
+        assert_eq!(Cleaner::clean(input).unwrap(), "first line here\n\nsecond line");
+    }
+
+    #[test]
+    fn test_code_blocks_are_verbatim() {
+        let input = "Why   does this fail?\n```rust\nfn main() {\n    let  x =\t1;\n\n\n}\n```\nThanks  !";
+        let expected = "Why does this fail?\n```rust\nfn main() {\n    let  x =\t1;\n\n\n}\n```\nThanks !";
+        assert_eq!(Cleaner::clean(input).unwrap(), expected);
+
+        let indented = "Why is this slow?\n\n    for i in 0..n {\n        total  +=  i;\n    }\n\nAny   ideas?";
+        let expected = "Why is this slow?\n\n    for i in 0..n {\n        total  +=  i;\n    }\n\nAny ideas?";
+        assert_eq!(Cleaner::clean(indented).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_limits() {
+        assert!(matches!(Cleaner::clean(" \u{200B}\n\t"), Err(CleanerError::EmptyInput)));
+
+        let long = "a".repeat(Cleaner::MAX_CHARS + 1);
+        assert!(matches!(Cleaner::clean(&long), Err(CleanerError::TooManyChars { .. })));
+
+        // Under the char limit, over the byte limit: 4 bytes per emoji.
+        let wide = "😀".repeat(Cleaner::MAX_BYTES / 4 + 1);
+        assert!(matches!(Cleaner::clean(&wide), Err(CleanerError::TooManyBytes { .. })));
+
+        let cjk = "学".repeat(Cleaner::MAX_CHARS);
+        assert!(Cleaner::clean(&cjk).is_ok());
+    }
+}
@@ -1,5 +1,8 @@
 use serde::{Serialize, Deserialize};
-use crate::llama::{LLMEngine, GenerationConfig};
+use super::heuristics::{self, AnalysisSource, HeuristicAnalyzer};
+use super::langid::{LanguageDetector, LanguageGuess};
+use crate::llama::{ChatMessage, GenerationConfig, LLMError, LlmBackend, MessageRole};
+use serde_json::json;
 use thiserror::Error;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -8,6 +11,20 @@ pub struct Context {
     pub domain: String,
     pub topic: String,
     pub raw_input: String,
+    /// What the learner actually typed in; `None` without any letters.
+    #[serde(default)]
+    pub detected_language: Option<LanguageGuess>,
+    /// Studying a natural language (`domain`) but confidently writing in
+    /// another, e.g. asking in English during a French lesson.
+    #[serde(default)]
+    pub language_mismatch: bool,
+    /// Whether action, domain and topic came from the model, the heuristic
+    /// analyzer or both.
+    #[serde(default)]
+    pub analysis_source: AnalysisSource,
+    /// Mean per-field confidence of the analysis that was kept.
+    #[serde(default)]
+    pub analysis_confidence: f32,
 }
 
 #[derive(Error, Debug)]
@@ -19,39 +36,65 @@ pub enum ContextError {
 }
 
 impl Context {
-    const ANALYSIS_PROMPT: &'static str = r#"<|im_start|>system
-You are an input analyzer. Extract exactly these REQUIRED elements:
+    const ANALYSIS_PROMPT: &'static str = r#"You are an input analyzer. Extract exactly these REQUIRED elements:
 1. Action (verb: explain, write, debug, compare, etc.)
 2. Domain (subject: Rust, Python, math, etc.)
 3. Topic (specific concept: lifetimes, vectors, etc.)
 
 Respond ONLY with this JSON format:
 {"action":"...","domain":"...","topic":"..."}
-No commentary or additional text!<|im_end|>
-"#;
+No commentary or additional text!"#;
 
-    pub async fn analyze(input: String, llm: &LLMEngine) -> Result<Self, ContextError> {
-        let prompt = format!(
-            "{}<|im_start|>user\n{}\n<|im_end|>\n<|im_start|>assistant\n",
-            Self::ANALYSIS_PROMPT,
-            input
-        );
+    /// Asks the model for action, domain and topic, then cross-checks its
+    /// answer with `HeuristicAnalyzer`. If the model fails or is unsure, the
+    /// heuristic reading stands in for it, wholly or per field; an error is
+    /// only returned when neither has anything to offer.
+    pub async fn analyze(input: String, llm: &dyn LlmBackend) -> Result<Self, ContextError> {
+        let messages = [
+            ChatMessage { role: MessageRole::System, content: Self::ANALYSIS_PROMPT.to_string() },
+            ChatMessage { role: MessageRole::User, content: input.clone() },
+        ];
 
-        let response = llm.generate(&prompt, Some(GenerationConfig {
+        let config = GenerationConfig {
             max_tokens: 100,
             temperature: 0.1,
             ..Default::default()
-        })).map_err(|e| ContextError::AnalysisFailed(e.to_string()))?;
+        };
 
-        let clean_response = response.split('<').next().unwrap_or(&response).trim();
-        let analysis: ContentAnalysis = serde_json::from_str(clean_response)
-            .map_err(|e| ContextError::InvalidFormat(e.to_string()))?;
+        let heuristic = HeuristicAnalyzer::analyze(&input);
+        let model = match llm.chat_json::<ContentAnalysis>(&messages, &ContentAnalysis::schema(), Some(config)) {
+            Ok(analysis) => Some(HeuristicAnalyzer::judge(
+                analysis.action,
+                analysis.domain,
+                analysis.topic,
+                &input,
+                heuristic.as_ref(),
+            )),
+            Err(e) if heuristic.is_none() => {
+                return Err(match e {
+                    LLMError::InvalidJson { .. } => ContextError::InvalidFormat(e.to_string()),
+                    _ => ContextError::AnalysisFailed(e.to_string()),
+                });
+            }
+            Err(_) => None,
+        };
+        let (analysis, analysis_source) = heuristics::reconcile(model, heuristic)
+            .ok_or_else(|| ContextError::AnalysisFailed("No analysis available".to_string()))?;
+
+        let detected_language = LanguageDetector::detect(&input);
+        let language_mismatch = detected_language
+            .as_ref()
+            .is_some_and(|guess| LanguageDetector::is_mismatch(guess, &analysis.domain));
 
         Ok(Self {
             action: analysis.action,
             domain: analysis.domain,
             topic: analysis.topic,
             raw_input: input,
+            detected_language,
+            language_mismatch,
+            analysis_source,
+            analysis_confidence: analysis.confidence.overall(),
         })
     }
 }
@@ -61,4 +104,84 @@ struct ContentAnalysis {
     action: String,
     domain: String,
     topic: String,
+}
+
+impl ContentAnalysis {
+    // Decoding is constrained to this shape, so the model cannot wander off
+    // into commentary or ChatML markers.
+    fn schema() -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "action": { "type": "string" },
+                "domain": { "type": "string" },
+                "topic": { "type": "string" }
+            },
+            "required": ["action", "domain", "topic"]
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llama::MockBackend;
+    use crate::preprocessing::router::Language;
+
+    #[tokio::test]
+    async fn test_analyze_parses_model_json() {
+        let llm = MockBackend::scripted([r#"{"action":"explain","domain":"Rust","topic":"lifetimes"}"#]);
+        let context = Context::analyze("Explain Rust lifetimes".to_string(), &llm).await.unwrap();
+        assert_eq!(context.action, "explain");
+        assert_eq!(context.domain, "Rust");
+        assert_eq!(context.topic, "lifetimes");
+        assert_eq!(context.raw_input, "Explain Rust lifetimes");
+        assert_eq!(context.analysis_source, AnalysisSource::Model);
+        assert!(llm.prompts()[0].contains("input analyzer"));
+    }
+
+    #[tokio::test]
+    async fn test_analyze_falls_back_on_malformed_json() {
+        let llm = MockBackend::scripted(["{\"action\": explain", "not json either"]);
+        let context = Context::analyze("Explain Rust lifetimes".to_string(), &llm).await.unwrap();
+        assert_eq!(context.analysis_source, AnalysisSource::Heuristic);
+        assert_eq!(context.action, "explain");
+        assert_eq!(context.domain, "rust");
+        assert_eq!(context.topic, "lifetimes");
+        assert!(context.analysis_confidence > 0.5);
+    }
+
+    #[tokio::test]
+    async fn test_analyze_overrides_an_unsupported_domain() {
+        let llm = MockBackend::scripted([r#"{"action":"debug","domain":"Cooking","topic":"borrow checker"}"#]);
+        let input = "How do I fix this borrow checker error in Rust?";
+        let context = Context::analyze(input.to_string(), &llm).await.unwrap();
+        assert_eq!(context.analysis_source, AnalysisSource::Merged);
+        assert_eq!(context.action, "debug");
+        assert_eq!(context.domain, "rust");
+        assert_eq!(context.topic, "borrow checker");
+    }
+
+    #[tokio::test]
+    async fn test_analyze_detects_language_mismatch() {
+        let llm = MockBackend::scripted([r#"{"action":"explain","domain":"French","topic":"greetings"}"#]);
+        let input = "How do people usually greet each other in the morning in France?";
+        let context = Context::analyze(input.to_string(), &llm).await.unwrap();
+        let guess = context.detected_language.as_ref().unwrap();
+        assert_eq!(guess.language, Language::English);
+        assert!(context.language_mismatch);
+
+        let llm = MockBackend::scripted([r#"{"action":"explain","domain":"French","topic":"greetings"}"#]);
+        let input = "Comment est-ce qu'on se salue le matin en France?";
+        let context = Context::analyze(input.to_string(), &llm).await.unwrap();
+        assert_eq!(context.detected_language.unwrap().language, Language::French);
+        assert!(!context.language_mismatch);
+    }
+
+    #[tokio::test]
+    async fn test_analyze_reports_unparseable_output() {
+        let llm = MockBackend::scripted(["no idea", "still no idea"]);
+        let result = Context::analyze("???".to_string(), &llm).await;
+        assert!(matches!(result, Err(ContextError::InvalidFormat(_))));
+    }
 }
\ No newline at end of file
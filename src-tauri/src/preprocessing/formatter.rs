@@ -2,9 +2,11 @@ use serde::{Serialize, Deserialize};
 use thiserror::Error;
 use std::collections::HashMap;
 use uuid::Uuid;
-use crate::context::Context;
-use crate::tokenizer::TokenInfo;
-use crate::router::{Mode, Proficiency, Personality};
+use super::context::Context;
+use super::tokenizer::TokenInfo;
+use super::router::{Mode, Proficiency, Personality, Language};
+use super::segmenter::{Segment, Segmenter};
+use super::redactor::{Redactor, Sink};
 
 #[derive(Error, Debug)]
 pub enum FormatterError {
@@ -14,6 +16,28 @@ pub enum FormatterError {
     InvalidPayload(String),
 }
 
+/// Every domain the formatter knows, by category. `heuristics` scans input
+/// for these same keywords when the model's analysis can't be trusted.
+pub(crate) const DOMAIN_CATEGORIES: &[(&str, &[&str])] = &[
+    // Programming languages
+    ("programming_language", &["rust", "elixir", "julia", "c++", "c", "sql", "go", "typescript", "python", "javascript", "scala", "cobol"]),
+    ("markup_styling", &["html", "css"]),
+    ("framework", &["tauri"]),
+
+    // Natural languages
+    ("natural_language", &["english", "french", "portuguese", "russian", "spanish", "german", "italian", "japanese", "mandarin", "arabic", "korean", "hebrew"]),
+
+    // Language mechanics and theory
+    ("language_mechanics", &["grammar", "syntax", "pronunciation", "vocabulary", "translation", "linguistics", "phonetics", "semantics", "pragmatics", "sociolinguistics", "discourse_analysis", "stylistics", "morphology", "phonology", "syntax_tree", "language_acquisition", "general_linguistics"]),
+
+    // Programming concepts
+    ("programming_concepts", &["algorithms", "data_structures", "design_patterns", "debugging", "testing", "optimization", "concurrency", "distributed_systems", "functional_programming", "object_oriented_programming", "reactive_programming", "metaprogramming", "type_systems", "memory_management", "networking", "security", "performance_tuning", "software_architecture", "agile_development", "general_programming"]),
+];
+
+/// Layout version of `SqliteVecRecord` as stored by `records`. Bump it with
+/// every field change, together with a migration in `records::schema`.
+pub const RECORD_VERSION: u32 = 1;
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SqliteVecRecord {
     pub id: String,
@@ -26,6 +50,8 @@ pub struct SqliteVecRecord {
     pub mode: String,
     pub proficiency: String,
     pub personality: String,
+    /// ISO 639-1 code of the language replies are written in.
+    pub language: String,
     pub word_count: i64,
     pub sentence_count: i64,
     pub token_preview: String,
@@ -35,18 +61,35 @@ pub struct SqliteVecRecord {
     pub domain_category: String,
     pub complexity_tier: String,
     pub proficiency_level: String,
+    /// ISO 639-1 code of the input's language, empty if undetected.
+    pub detected_language: String,
+    pub language_confidence: f32,
+    pub language_mismatch: bool,
     pub created_at: i64,
     pub updated_at: i64,
 }
 
+impl SqliteVecRecord {
+    /// Scrubs the free-text fields for the memory sink. The record is what
+    /// gets stored; `FormattedInput::context` keeps the original for the model.
+    pub fn redact(&mut self, redactor: &Redactor) {
+        for field in [&mut self.raw_input, &mut self.cleaned_input, &mut self.topic, &mut self.token_preview] {
+            *field = redactor.scrub(Sink::Memory, field);
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct FormattedInput {
     pub sqlite_vec_record: SqliteVecRecord,
     pub context: Context,
     pub tokens: TokenInfo,
+    /// The input split into prose and code, in order.
+    pub segments: Vec<Segment>,
     pub mode: Mode,
     pub proficiency: Proficiency,
     pub personality: Personality,
+    pub language: Language,
     pub metadata: InputMetadata,
 }
 
@@ -76,14 +119,17 @@ impl FormattedInput {
         vector: Vec<f32>,
     ) -> Result<Self, FormatterError> {
         let metadata = Self::calculate_metadata(&context, &tokens, &proficiency);
-        let sqlite_vec_record = Self::build_sqlite_vec_record(&context, &tokens, &mode, &proficiency, &personality, &metadata, vector)?;
+        let sqlite_vec_record = Self::build_sqlite_vec_record(&context, &tokens, &mode, &proficiency, &personality, language, &metadata, vector)?;
+        let segments = Segmenter::segment(&context.raw_input);
         Ok(Self {
             sqlite_vec_record,
             context,
             tokens,
+            segments,
             mode,
             proficiency,
             personality,
+            language,
             metadata,
         })
     }
@@ -94,6 +140,7 @@ impl FormattedInput {
         mode: &Mode,
         proficiency: &Proficiency,
         personality: &Personality,
+        language: Language,
         metadata: &InputMetadata,
         vector: Vec<f32>,
     ) -> Result<SqliteVecRecord, FormatterError> {
@@ -120,6 +167,7 @@ impl FormattedInput {
             mode: format!("{:?}", mode),
             proficiency: format!("{:?}", proficiency),
             personality: format!("{:?}", personality),
+            language: language.code().to_string(),
             word_count: tokens.word_count as i64,
             sentence_count: tokens.sentence_count as i64,
             token_preview,
@@ -129,29 +177,25 @@ impl FormattedInput {
             domain_category: Self::categorize_domain(&context.domain),
             complexity_tier: Self::categorize_complexity(metadata.complexity_score),
             proficiency_level: Self::map_proficiency_level(proficiency),
+            detected_language: context
+                .detected_language
+                .as_ref()
+                .map(|guess| guess.language.code().to_string())
+                .unwrap_or_default(),
+            language_confidence: context.detected_language.as_ref().map_or(0.0, |guess| guess.confidence),
+            language_mismatch: context.language_mismatch,
             created_at: now,
             updated_at: now,
         })
     }
     
     fn categorize_domain(domain: &str) -> String {
-        match domain.to_lowercase().as_str() {
-            // Programming languages
-            "rust" | "elixir" | "julia" | "c++" | "c" | "sql" | "go" | "typescript" | "python" | "javascript" | "scala" | "cobol" => "programming_language".to_string(),
-            "html" | "css" => "markup_styling".to_string(),
-            "tauri" => "framework".to_string(),
-
-            // Natural languages
-            "english" | "french" | "portuguese" | "russian" | "spanish" | "german" | "italian" | "japanese" | "mandarin" | "arabic" | "korean" | "hebrew" => "natural_language".to_string(),
-
-            // Language mechanics and theory
-            "grammar" | "syntax" | "pronunciation" | "vocabulary" | "translation" | "linguistics" | "phonetics" | "semantics" | "pragmatics" | "sociolinguistics" | "discourse_analysis" | "stylistics" | "morphology" | "phonology" | "syntax_tree" | "language_acquisition" | "general_linguistics" => "language_mechanics".to_string(),
-
-            // Programming concepts
-            "algorithms" | "data_structures" | "design_patterns" | "debugging" | "testing" | "optimization" | "concurrency" | "distributed_systems" | "functional_programming" | "object_oriented_programming" | "reactive_programming" | "metaprogramming" | "type_systems" | "memory_management" | "networking" | "security" | "performance_tuning" | "software_architecture" | "agile_development" | "general_programming" => "programming_concepts".to_string(),
-
-            _ => "general".to_string(),
-        }
+        let domain = domain.to_lowercase();
+        DOMAIN_CATEGORIES
+            .iter()
+            .find(|(_, domains)| domains.contains(&domain.as_str()))
+            .map_or("general", |(category, _)| category)
+            .to_string()
     }
     
     fn categorize_complexity(score: f32) -> String {
@@ -304,6 +348,11 @@ impl FormattedInput {
         filters
     }
 
+    /// Pasted code, each snippet exactly as typed.
+    pub fn code_segments(&self) -> impl Iterator<Item = &Segment> {
+        self.segments.iter().filter(|segment| segment.is_code())
+    }
+
     pub fn get_id(&self) -> &str {
         &self.sqlite_vec_record.id
     }
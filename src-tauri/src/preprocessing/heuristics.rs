@@ -0,0 +1,440 @@
+//! Rule-based reading of an input, as a fallback and cross-check for the model.
+//!
+//! The model's analysis is usually the better one, but it can come back as
+//! malformed JSON or latch onto the wrong subject. `HeuristicAnalyzer` gives a
+//! second opinion from a verb lexicon and the formatter's domain tables, and
+//! `reconcile` keeps, field by field, whichever reading is more credible.
+
+use super::formatter::DOMAIN_CATEGORIES;
+use super::segmenter::Segmenter;
+use serde::{Deserialize, Serialize};
+
+/// A model analysis at least this credible overall is taken as is.
+pub const TRUST_MODEL: f32 = 0.75;
+
+/// Canonical action, then the words that signal it.
+const VERBS: &[(&str, &[&str])] = &[
+    ("explain", &["explain", "describe", "clarify", "understand", "mean", "means"]),
+    ("write", &["write", "create", "generate", "build", "make", "implement", "compose", "draft"]),
+    ("debug", &["debug", "fix", "troubleshoot", "solve", "crash", "crashes", "error", "failing", "broken"]),
+    ("compare", &["compare", "difference", "differences", "versus", "vs"]),
+    ("translate", &["translate", "say"]),
+    ("review", &["review", "check", "correct", "proofread", "critique", "improve"]),
+    ("optimize", &["optimize", "optimise", "speed", "faster", "refactor"]),
+    ("summarize", &["summarize", "summarise", "summary", "tldr"]),
+    ("define", &["define", "definition"]),
+    ("practice", &["practice", "practise", "quiz", "exercise", "drill"]),
+];
+
+/// Questions without a verb from the lexicon are asking for an explanation.
+const QUESTION_WORDS: &[&str] = &["what", "why", "how", "when", "which", "where", "who"];
+
+/// Politeness and framing that come before the verb that matters.
+const LEAD_INS: &[&str] = &[
+    "please", "can", "could", "would", "will", "you", "help", "me", "i", "i'd", "want", "need",
+    "like", "to", "let's", "lets", "us", "hey", "hi",
+];
+
+const ALIASES: &[(&str, &str)] = &[
+    ("js", "javascript"),
+    ("ts", "typescript"),
+    ("py", "python"),
+    ("golang", "go"),
+    ("cpp", "c++"),
+    ("rustlang", "rust"),
+    ("postgres", "sql"),
+    ("sqlite", "sql"),
+    ("mysql", "sql"),
+    ("chinese", "mandarin"),
+];
+
+/// Domains that are also everyday words; they only count as written
+/// ("C", "Go") and never as the first word of a sentence.
+const AMBIGUOUS: &[(&str, &str)] = &[("c", "C"), ("go", "Go")];
+
+const STOPWORDS: &[&str] = &[
+    "a", "an", "the", "is", "are", "was", "were", "be", "been", "do", "does", "did", "don't",
+    "doesn't", "my", "your", "it", "its", "it's", "this", "that", "these", "those", "in", "on",
+    "of", "for", "with", "and", "or", "but", "not", "about", "from", "into", "by", "at", "as",
+    "so", "if", "there", "here", "some", "any", "use", "using", "get", "work", "works", "between",
+    "should", "way", "thing", "things", "really", "just", "also", "code",
+];
+
+const MAX_TOPIC_WORDS: usize = 4;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Analysis {
+    pub action: String,
+    pub domain: String,
+    pub topic: String,
+    pub confidence: FieldConfidence,
+}
+
+/// 0.0 to 1.0 per field; 0.0 means the field is a placeholder.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FieldConfidence {
+    pub action: f32,
+    pub domain: f32,
+    pub topic: f32,
+}
+
+impl FieldConfidence {
+    pub fn overall(&self) -> f32 {
+        (self.action + self.domain + self.topic) / 3.0
+    }
+}
+
+/// Whose reading of the input `Context` ended up with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AnalysisSource {
+    #[default]
+    Model,
+    Heuristic,
+    /// Some fields from each.
+    Merged,
+}
+
+pub struct HeuristicAnalyzer;
+
+impl HeuristicAnalyzer {
+    /// `None` when the prose has no words to go on.
+    pub fn analyze(input: &str) -> Option<Analysis> {
+        let segments = Segmenter::segment(input);
+        let prose = Segmenter::prose(input);
+        let code_languages: Vec<&str> = segments.iter().filter_map(|s| s.language()).collect();
+        let words = words(&prose);
+        if !words.iter().any(|w| w.chars().any(char::is_alphabetic)) {
+            return None;
+        }
+        let lower: Vec<String> = words.iter().map(|w| w.to_lowercase()).collect();
+
+        let (action, action_confidence) = Self::action(&lower);
+        let (domain, domain_confidence, domain_words) = Self::domain(&words, &lower, &code_languages);
+        let (topic, topic_confidence) = Self::topic(&lower, &domain_words);
+
+        Some(Analysis {
+            action,
+            domain,
+            topic,
+            confidence: FieldConfidence {
+                action: action_confidence,
+                domain: domain_confidence,
+                topic: topic_confidence,
+            },
+        })
+    }
+
+    /// Scores the model's fields against the input and, where it found
+    /// something definite, the heuristic reading.
+    pub fn judge(
+        action: String,
+        domain: String,
+        topic: String,
+        input: &str,
+        heuristic: Option<&Analysis>,
+    ) -> Analysis {
+        let text = input.to_lowercase();
+
+        let action_confidence = match action.trim().to_lowercase() {
+            a if a.is_empty() => 0.0,
+            a if verb_for(&a).is_some() => 0.9,
+            a if !a.contains(char::is_whitespace) => 0.7,
+            _ => 0.4,
+        };
+
+        let known = canonical_domain(&domain);
+        let mentioned = !domain.trim().is_empty() && text.contains(&domain.trim().to_lowercase());
+        let mut domain_confidence = if domain.trim().is_empty() {
+            0.0
+        } else if is_known_domain(&known) {
+            0.9
+        } else if mentioned {
+            0.7
+        } else {
+            0.5
+        };
+        // The input names a domain outright and the model picked another it
+        // can't point to: most likely the model wandered off.
+        if let Some(h) = heuristic {
+            if h.confidence.domain >= 0.9 && h.domain != known && !mentioned {
+                domain_confidence = f32::min(domain_confidence, 0.4);
+            }
+        }
+
+        let topic_confidence = if topic.trim().is_empty() {
+            0.0
+        } else if words(&topic.to_lowercase()).iter().any(|w| text.contains(stem(w))) {
+            0.8
+        } else {
+            0.5
+        };
+
+        Analysis {
+            action,
+            domain,
+            topic,
+            confidence: FieldConfidence {
+                action: action_confidence,
+                domain: domain_confidence,
+                topic: topic_confidence,
+            },
+        }
+    }
+
+    fn action(lower: &[String]) -> (String, f32) {
+        let first = lower.iter().find(|w| !LEAD_INS.contains(&w.as_str()));
+        if let Some(verb) = first.and_then(|w| verb_for(w)) {
+            return (verb.to_string(), 0.9);
+        }
+        if let Some(verb) = lower.iter().find_map(|w| verb_for(w)) {
+            return (verb.to_string(), 0.7);
+        }
+        if lower.first().is_some_and(|w| QUESTION_WORDS.contains(&w.as_str())) {
+            return ("explain".to_string(), 0.55);
+        }
+        ("explain".to_string(), 0.2)
+    }
+
+    /// Most frequent domain keyword, the words that named it, and how sure
+    /// we are: an unambiguous keyword or the language of pasted code is
+    /// strong evidence, "C" or "Go" much less so.
+    fn domain(words: &[&str], lower: &[String], code_languages: &[&str]) -> (String, f32, Vec<String>) {
+        let mut hits: Vec<(String, usize, bool)> = Vec::new();
+        let mut consumed = Vec::new();
+        let mut record = |domain: String, strong: bool| match hits.iter_mut().find(|(d, _, _)| *d == domain) {
+            Some(hit) => {
+                hit.1 += 1;
+                hit.2 |= strong;
+            }
+            None => hits.push((domain, 1, strong)),
+        };
+
+        for language in code_languages {
+            let domain = canonical_domain(language);
+            if is_known_domain(&domain) {
+                record(domain, true);
+            }
+        }
+
+        let mut i = 0;
+        while i < lower.len() {
+            // Longest phrase first: "object oriented programming" before "programming".
+            let matched = (1..=3).rev().find_map(|n| {
+                let phrase = lower.get(i..i + n)?.join("_");
+                let domain = [phrase.clone(), format!("{}s", phrase)]
+                    .into_iter()
+                    .map(|p| canonical_domain(&p))
+                    .find(|d| is_known_domain(d))?;
+                Some((n, domain))
+            });
+            match matched {
+                Some((n, domain)) => {
+                    let ambiguous = AMBIGUOUS.iter().find(|(d, _)| *d == domain && n == 1 && lower[i] == *d);
+                    let counts = match ambiguous {
+                        Some((_, written)) => i > 0 && words[i] == *written,
+                        None => true,
+                    };
+                    if counts {
+                        consumed.extend(lower[i..i + n].iter().cloned());
+                        record(domain, ambiguous.is_none());
+                    }
+                    i += n;
+                }
+                None => i += 1,
+            }
+        }
+
+        let distinct_strong = hits.iter().filter(|(_, _, strong)| *strong).count();
+        // Ties go to whichever was named first.
+        let best = hits
+            .iter()
+            .enumerate()
+            .max_by_key(|(index, (_, count, strong))| (*strong, *count, usize::MAX - index))
+            .map(|(_, hit)| hit.clone());
+        match best {
+            Some((domain, _, true)) if distinct_strong == 1 => (domain, 0.9, consumed),
+            Some((domain, _, true)) => (domain, 0.7, consumed),
+            Some((domain, _, false)) => (domain, 0.5, consumed),
+            None => ("general".to_string(), 0.0, consumed),
+        }
+    }
+
+    /// The first few words left once framing, verbs and the domain are gone.
+    fn topic(lower: &[String], domain_words: &[String]) -> (String, f32) {
+        let content: Vec<&str> = lower
+            .iter()
+            .map(String::as_str)
+            .filter(|w| {
+                !STOPWORDS.contains(w)
+                    && !LEAD_INS.contains(w)
+                    && !QUESTION_WORDS.contains(w)
+                    && verb_for(w).is_none()
+                    && !domain_words.iter().any(|d| d == w)
+                    && w.chars().any(char::is_alphabetic)
+            })
+            .collect();
+        match content.len() {
+            0 => ("general".to_string(), 0.0),
+            n if n <= MAX_TOPIC_WORDS => (content.join(" "), 0.6),
+            _ => (content[..MAX_TOPIC_WORDS].join(" "), 0.4),
+        }
+    }
+}
+
+/// Picks between the two readings. A credible model analysis wins outright;
+/// otherwise each field goes to whichever side is surer of it, the model on
+/// a tie.
+pub fn reconcile(model: Option<Analysis>, heuristic: Option<Analysis>) -> Option<(Analysis, AnalysisSource)> {
+    match (model, heuristic) {
+        (None, None) => None,
+        (Some(model), None) => Some((model, AnalysisSource::Model)),
+        (None, Some(heuristic)) => Some((heuristic, AnalysisSource::Heuristic)),
+        (Some(model), Some(_)) if model.confidence.overall() >= TRUST_MODEL => {
+            Some((model, AnalysisSource::Model))
+        }
+        (Some(model), Some(heuristic)) => {
+            let (m, h) = (model.confidence, heuristic.confidence);
+            let pick = |model_field: String, model_score: f32, heuristic_field: String, heuristic_score: f32| {
+                if model_score >= heuristic_score {
+                    (model_field, model_score, true)
+                } else {
+                    (heuristic_field, heuristic_score, false)
+                }
+            };
+            let (action, action_score, a) = pick(model.action, m.action, heuristic.action, h.action);
+            let (domain, domain_score, d) = pick(model.domain, m.domain, heuristic.domain, h.domain);
+            let (topic, topic_score, t) = pick(model.topic, m.topic, heuristic.topic, h.topic);
+
+            let source = match (a, d, t) {
+                (true, true, true) => AnalysisSource::Model,
+                (false, false, false) => AnalysisSource::Heuristic,
+                _ => AnalysisSource::Merged,
+            };
+            let confidence = FieldConfidence {
+                action: action_score,
+                domain: domain_score,
+                topic: topic_score,
+            };
+            Some((Analysis { action, domain, topic, confidence }, source))
+        }
+    }
+}
+
+/// Words of `text`, keeping the `+` and `#` of "c++" and "c#" and inner apostrophes.
+fn words(text: &str) -> Vec<&str> {
+    text.split(|c: char| !(c.is_alphanumeric() || c == '+' || c == '#' || c == '\''))
+        .map(|w| w.trim_matches('\''))
+        .filter(|w| !w.is_empty())
+        .collect()
+}
+
+fn verb_for(word: &str) -> Option<&'static str> {
+    VERBS
+        .iter()
+        .find(|(action, signals)| *action == word || signals.contains(&word))
+        .map(|(action, _)| *action)
+}
+
+/// Lowercase domain name with aliases resolved: "JS" becomes "javascript".
+fn canonical_domain(name: &str) -> String {
+    let name = name.trim().to_lowercase().replace([' ', '-'], "_");
+    ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == name)
+        .map_or(name, |(_, domain)| domain.to_string())
+}
+
+fn is_known_domain(domain: &str) -> bool {
+    DOMAIN_CATEGORIES.iter().any(|(_, domains)| domains.contains(&domain))
+}
+
+/// Crude stem so "greetings" in a topic matches "greet" in the input.
+fn stem(word: &str) -> &str {
+    match word.char_indices().nth(4) {
+        Some((end, _)) => &word[..end],
+        None => word,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn analyze(input: &str) -> Analysis {
+        HeuristicAnalyzer::analyze(input).unwrap()
+    }
+
+    #[test]
+    fn test_action_from_verb_lexicon() {
+        assert_eq!(analyze("Explain Rust lifetimes").action, "explain");
+        assert_eq!(analyze("Can you please fix this Python script?").action, "debug");
+        assert_eq!(analyze("What's the difference between a Vec and a slice?").action, "compare");
+        assert_eq!(analyze("How do you say good morning in Japanese?").action, "translate");
+
+        let question = analyze("Why is the sky blue?");
+        assert_eq!(question.action, "explain");
+        assert!(question.confidence.action < 0.9);
+        assert!(analyze("Explain closures").confidence.action > question.confidence.action);
+    }
+
+    #[test]
+    fn test_domain_from_formatter_tables() {
+        let rust = analyze("Explain Rust lifetimes");
+        assert_eq!((rust.domain.as_str(), rust.topic.as_str()), ("rust", "lifetimes"));
+        assert!(rust.confidence.domain >= 0.9);
+
+        assert_eq!(analyze("Which data structures suit a priority queue?").domain, "data_structures");
+        assert_eq!(analyze("Write a small JS debounce helper").domain, "javascript");
+        assert_eq!(analyze("Why won't this compile?\n```rust\nfn main() {}\n```").domain, "rust");
+        assert_eq!(analyze("Why does this panic?\n\n    let v: Vec<i32> = vec![];\n    println!(\"{}\", v[0]);").domain, "rust");
+
+        // "go" the verb is not Go the language.
+        assert_eq!(analyze("Go ahead and explain recursion").domain, "general");
+        let go = analyze("How do channels work in Go?");
+        assert_eq!(go.domain, "go");
+        assert!(go.confidence.domain < 0.9);
+
+        assert_eq!(analyze("Tell me a story").confidence.domain, 0.0);
+        assert!(HeuristicAnalyzer::analyze("??? 123").is_none());
+    }
+
+    #[test]
+    fn test_judge_distrusts_unsupported_domain() {
+        let input = "How do I fix this borrow checker error in Rust?";
+        let heuristic = analyze(input);
+        let wrong = HeuristicAnalyzer::judge(
+            "debug".into(),
+            "Cooking".into(),
+            "borrow checker".into(),
+            input,
+            Some(&heuristic),
+        );
+        assert!(wrong.confidence.domain < heuristic.confidence.domain);
+
+        let right = HeuristicAnalyzer::judge("debug".into(), "Rust".into(), "borrow checker".into(), input, Some(&heuristic));
+        assert!(right.confidence.overall() >= TRUST_MODEL);
+    }
+
+    #[test]
+    fn test_reconcile_picks_per_field() {
+        let input = "How do I fix this borrow checker error in Rust?";
+        let heuristic = analyze(input);
+        let model = HeuristicAnalyzer::judge(
+            "debug".into(),
+            "Cooking".into(),
+            "borrow checker".into(),
+            input,
+            Some(&heuristic),
+        );
+        let (merged, source) = reconcile(Some(model), Some(heuristic.clone())).unwrap();
+        assert_eq!(source, AnalysisSource::Merged);
+        assert_eq!((merged.action.as_str(), merged.domain.as_str()), ("debug", "rust"));
+        assert_eq!(merged.topic, "borrow checker");
+
+        let (alone, source) = reconcile(None, Some(heuristic)).unwrap();
+        assert_eq!(source, AnalysisSource::Heuristic);
+        assert_eq!(alone.domain, "rust");
+        assert!(reconcile(None, None).is_none());
+    }
+}
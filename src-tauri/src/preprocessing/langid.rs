@@ -0,0 +1,242 @@
+//! Identifies which language the learner actually typed.
+//!
+//! Non-Latin scripts give the answer away: Cyrillic is Russian, Hangul is
+//! Korean, kana is Japanese and so on. Latin-script text is compared against
+//! character-trigram profiles built from short reference samples, which is
+//! enough to tell the six supported Latin languages apart from a sentence or
+//! two. Code is ignored; it would otherwise read as English.
+
+use super::router::Language;
+use super::segmenter::Segmenter;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// Below this confidence a guess is recorded but never flagged as a mismatch.
+pub const MISMATCH_CONFIDENCE: f32 = 0.5;
+
+const SAMPLES: &[(Language, &str)] = &[
+    (Language::English, "the quick brown fox jumps over the lazy dog. i would like to know how this works and why \
+            it is not what i expected. could you please explain the difference between these two \
+            things? what does that mean in practice, and where should i start learning? they have \
+            been there with their friends all of the time, which is nice."),
+    (Language::French, "je voudrais savoir comment cela fonctionne et pourquoi ce n'est pas ce que j'attendais. \
+            est-ce que tu peux m'expliquer la différence entre les deux? qu'est-ce que cela veut \
+            dire dans la pratique, et où dois-je commencer? nous avons été très contents de notre \
+            voyage avec les enfants, mais il faisait froid à la plage."),
+    (Language::Portuguese, "eu gostaria de saber como isso funciona e por que não é o que eu esperava. você pode \
+            me explicar a diferença entre os dois? o que isso significa na prática, e onde devo \
+            começar a aprender? nós estávamos muito felizes com a viagem, mas não havia ninguém \
+            na praia e as crianças ficaram em casa com a avó."),
+    (Language::Spanish, "me gustaría saber cómo funciona esto y por qué no es lo que esperaba. ¿puedes \
+            explicarme la diferencia entre los dos? ¿qué significa eso en la práctica, y dónde \
+            debo empezar a aprender? estábamos muy contentos con el viaje, pero no había nadie en \
+            la playa y los niños se quedaron en casa con la abuela."),
+    (Language::German, "ich möchte wissen, wie das funktioniert und warum es nicht das ist, was ich erwartet \
+            habe. kannst du mir den unterschied zwischen den beiden erklären? was bedeutet das in \
+            der praxis, und wo sollte ich anfangen zu lernen? wir waren sehr zufrieden mit der \
+            reise, aber es war niemand am strand und die kinder sind zu hause geblieben."),
+    (Language::Italian, "vorrei sapere come funziona e perché non è quello che mi aspettavo. puoi spiegarmi la \
+            differenza tra i due? che cosa significa nella pratica, e da dove dovrei cominciare a \
+            imparare? eravamo molto contenti del viaggio, ma non c'era nessuno sulla spiaggia e i \
+            bambini sono rimasti a casa con la nonna."),
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LanguageGuess {
+    pub language: Language,
+    /// 0.0 to 1.0; short or mixed input scores low.
+    pub confidence: f32,
+}
+
+pub struct LanguageDetector;
+
+impl LanguageDetector {
+    /// `None` when the text has no letters to go on.
+    pub fn detect(text: &str) -> Option<LanguageGuess> {
+        let prose = Segmenter::prose(text);
+
+        let mut scripts: HashMap<&'static str, usize> = HashMap::new();
+        let mut letters = 0usize;
+        for c in prose.chars().filter(|c| c.is_alphabetic()) {
+            letters += 1;
+            *scripts.entry(script_of(c)).or_default() += 1;
+        }
+        if letters == 0 {
+            return None;
+        }
+
+        let latin = scripts.get("latin").copied().unwrap_or(0);
+        let (script, count) = scripts
+            .iter()
+            .filter(|(script, _)| **script != "latin")
+            .max_by_key(|(_, count)| **count)
+            .map(|(script, count)| (*script, *count))
+            .unwrap_or(("latin", latin));
+
+        if count > latin {
+            // Kanji appear in Japanese too; any kana settles it.
+            let language = match script {
+                "han" if scripts.contains_key("kana") => Language::Japanese,
+                "han" => Language::Mandarin,
+                "kana" => Language::Japanese,
+                "cyrillic" => Language::Russian,
+                "hangul" => Language::Korean,
+                "arabic" => Language::Arabic,
+                "hebrew" => Language::Hebrew,
+                _ => return None,
+            };
+            let native = match language {
+                Language::Japanese => count + scripts.get("han").copied().unwrap_or(0),
+                _ => count,
+            };
+            return Some(LanguageGuess {
+                language,
+                confidence: (native as f32 / letters as f32).min(1.0),
+            });
+        }
+
+        let (language, confidence) = Self::classify_latin(&prose)?;
+        Some(LanguageGuess {
+            language,
+            confidence: confidence * latin as f32 / letters as f32,
+        })
+    }
+
+    /// True when the learner is studying a natural language (per `domain`)
+    /// and confidently wrote in a different one.
+    pub fn is_mismatch(guess: &LanguageGuess, domain: &str) -> bool {
+        match Language::from_name(domain) {
+            Some(studied) => guess.language != studied && guess.confidence >= MISMATCH_CONFIDENCE,
+            None => false,
+        }
+    }
+
+    fn classify_latin(text: &str) -> Option<(Language, f32)> {
+        let input = trigrams(text);
+        let total: usize = input.values().sum();
+        if total == 0 {
+            return None;
+        }
+
+        let mut scores: Vec<(Language, f32)> = profiles()
+            .iter()
+            .map(|(language, profile)| (*language, cosine(&input, profile)))
+            .collect();
+        scores.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        let (best, best_score) = scores[0];
+        let runner_up = scores.get(1).map_or(0.0, |s| s.1);
+        if best_score <= 0.0 {
+            return None;
+        }
+
+        // The margin over the runner-up says how distinct the guess is; a
+        // handful of trigrams can't be trusted however distinct they look.
+        let margin = ((best_score - runner_up) / best_score * 4.0).min(1.0);
+        let length = (total as f32 / 40.0).min(1.0);
+        Some((best, margin * (0.4 + 0.6 * length)))
+    }
+}
+
+fn profiles() -> &'static [(Language, HashMap<String, usize>)] {
+    static PROFILES: OnceLock<Vec<(Language, HashMap<String, usize>)>> = OnceLock::new();
+    PROFILES.get_or_init(|| SAMPLES.iter().map(|(language, text)| (*language, trigrams(text))).collect())
+}
+
+/// Trigrams of each lowercase word, padded with a space on both sides.
+fn trigrams(text: &str) -> HashMap<String, usize> {
+    let mut grams = HashMap::new();
+    for word in text
+        .split(|c: char| !c.is_alphabetic() && c != '\'')
+        .filter(|w| !w.is_empty())
+    {
+        let padded: Vec<char> = std::iter::once(' ')
+            .chain(word.chars().flat_map(char::to_lowercase))
+            .chain(std::iter::once(' '))
+            .collect();
+        for window in padded.windows(3) {
+            *grams.entry(window.iter().collect::<String>()).or_default() += 1;
+        }
+    }
+    grams
+}
+
+fn cosine(a: &HashMap<String, usize>, b: &HashMap<String, usize>) -> f32 {
+    let dot: usize = a.iter().filter_map(|(g, n)| b.get(g).map(|m| n * m)).sum();
+    let norm = |m: &HashMap<String, usize>| (m.values().map(|v| v * v).sum::<usize>() as f32).sqrt();
+    let denom = norm(a) * norm(b);
+    if denom == 0.0 {
+        return 0.0;
+    }
+    dot as f32 / denom
+}
+
+fn script_of(c: char) -> &'static str {
+    match c as u32 {
+        0x0400..=0x052F => "cyrillic",
+        0x0590..=0x05FF => "hebrew",
+        0x0600..=0x06FF | 0x0750..=0x077F => "arabic",
+        0x3040..=0x30FF | 0x31F0..=0x31FF | 0xFF66..=0xFF9F => "kana",
+        0x1100..=0x11FF | 0x3130..=0x318F | 0xAC00..=0xD7AF => "hangul",
+        0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xF900..=0xFAFF => "han",
+        _ => "latin",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn code(text: &str) -> &'static str {
+        LanguageDetector::detect(text).unwrap().language.code()
+    }
+
+    #[test]
+    fn test_detects_every_supported_language() {
+        assert_eq!(code("How do I use the present perfect tense in a question?"), "en");
+        assert_eq!(code("Je ne comprends pas pourquoi on utilise le subjonctif ici."), "fr");
+        assert_eq!(code("Não entendo quando devo usar o pretérito imperfeito."), "pt");
+        assert_eq!(code("Я не понимаю, когда использовать совершенный вид."), "ru");
+        assert_eq!(code("No entiendo cuándo tengo que usar el subjuntivo."), "es");
+        assert_eq!(code("Ich verstehe nicht, wann man den Konjunktiv benutzt."), "de");
+        assert_eq!(code("Non capisco quando si usa il congiuntivo."), "it");
+        assert_eq!(code("日本語の敬語が難しいです。"), "ja");
+        assert_eq!(code("我不明白这个语法。"), "zh");
+        assert_eq!(code("لا أفهم هذه القاعدة."), "ar");
+        assert_eq!(code("이 문법을 이해하지 못해요."), "ko");
+        assert_eq!(code("אני לא מבין את הדקדוק הזה."), "he");
+    }
+
+    #[test]
+    fn test_confidence_tracks_length_and_mixing() {
+        let short = LanguageDetector::detect("hola").unwrap();
+        let long = LanguageDetector::detect(
+            "Hola, ¿cómo estás? Me gustaría practicar mi español contigo esta tarde.",
+        )
+        .unwrap();
+        assert!(long.confidence > short.confidence);
+        assert!(long.confidence >= MISMATCH_CONFIDENCE);
+
+        let mixed = LanguageDetector::detect("What does привет mean?").unwrap();
+        assert_eq!(mixed.language, Language::English);
+        assert!(mixed.confidence < 1.0);
+    }
+
+    #[test]
+    fn test_ignores_code_and_empty_input() {
+        let text = "Pourquoi est-ce que ce code ne compile pas?\n```rust\nfn main() { let value = vec![1, 2]; }\n```";
+        assert_eq!(code(text), "fr");
+        assert_eq!(LanguageDetector::detect("1234 !?"), None);
+    }
+
+    #[test]
+    fn test_mismatch_when_studying_another_language() {
+        let english = LanguageDetector::detect(
+            "Can you tell me how to order a coffee politely when I visit Paris next week?",
+        )
+        .unwrap();
+        assert!(LanguageDetector::is_mismatch(&english, "French"));
+        assert!(!LanguageDetector::is_mismatch(&english, "English"));
+        assert!(!LanguageDetector::is_mismatch(&english, "Rust"));
+    }
+}
@@ -3,12 +3,20 @@ pub mod context;
 pub mod cleaner;
 pub mod tokenizer;
 pub mod formatter;
+pub mod langid;
+pub mod heuristics;
+pub mod segmenter;
+pub mod redactor;
 
 pub use router::{Mode, Proficiency, Personality, Language};
 pub use context::{Context, ContextError};
 pub use cleaner::{Cleaner, CleanerError};
 pub use tokenizer::{Tokenizer, TokenizerError};
 pub use formatter::{FormattedInput, FormatterError};
+pub use langid::{LanguageDetector, LanguageGuess};
+pub use heuristics::{AnalysisSource, HeuristicAnalyzer};
+pub use segmenter::{Segment, Segmenter};
+pub use redactor::{Redacted, RedactionConfig, Redactor, SecretKind, Sink};
 
 use thiserror::Error;
 
@@ -33,12 +41,20 @@ impl Preprocessor {
         proficiency: Proficiency,
         personality: Personality,
         language: Language,
-        llm: &crate::llama::LLMEngine,
+        learner: &crate::learner::LearnerModel,
+        redactor: &Redactor,
+        llm: &dyn crate::llama::LlmBackend,
     ) -> Result<FormattedInput, PreprocessorError> {
         let cleaned = Cleaner::clean(&input)?;
         let context = Context::analyze(cleaned.clone(), llm).await?;
-        let tokens = Tokenizer::tokenize(&cleaned)?;
-        let formatted = FormattedInput::new(context, tokens, mode, proficiency, personality, language)?;
+        // The welcome-screen pick holds until the learner model knows better
+        // for this domain, and only if the learner let it adjust.
+        let proficiency = learner.adjust(&context.domain, proficiency);
+        let tokens = Tokenizer::tokenize(&cleaned, llm)?;
+        // `send_output` fills the vector in once the redacted input is embedded.
+        let mut formatted = FormattedInput::new(context, tokens, mode, proficiency, personality, language, Vec::new())?;
+        // The model sees what was typed; the stored record never holds secrets.
+        formatted.sqlite_vec_record.redact(redactor);
         
         Ok(formatted)
     }
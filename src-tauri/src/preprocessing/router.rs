@@ -1,13 +1,21 @@
 use serde::{Deserialize, Serialize};
 use serde_json;
+use std::fmt;
+use std::str::FromStr;
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Mode{
     Tutor,
-    Assistant
+    Assistant,
+    /// Source → target translation with alternatives and register notes.
+    Translator,
+    /// Structured findings on a pasted snippet.
+    CodeReview,
+    /// Asks a question, then grades the answer to it.
+    Quiz
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Proficiency{
     Beginner,
     Intermediate,
@@ -23,11 +31,45 @@ pub enum Personality{
     Viktor
 }
 
+/// Languages Athena teaches and answers in. Serialised as ISO 639-1 codes,
+/// the same ones `src/translation` uses for the UI language.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Language{
+    #[default]
+    #[serde(rename = "en")]
+    English,
+    #[serde(rename = "fr")]
+    French,
+    #[serde(rename = "pt")]
+    Portuguese,
+    #[serde(rename = "es")]
+    Spanish,
+    #[serde(rename = "de")]
+    German,
+    #[serde(rename = "it")]
+    Italian,
+    #[serde(rename = "ru")]
+    Russian,
+    #[serde(rename = "ja")]
+    Japanese,
+    #[serde(rename = "zh")]
+    Mandarin,
+    #[serde(rename = "ar")]
+    Arabic,
+    #[serde(rename = "ko")]
+    Korean,
+    #[serde(rename = "he")]
+    Hebrew
+}
+
 impl Mode{
     pub async fn select_mode(mode: u8) -> Result<Self, String> {
         match mode {
             0 => Ok(Mode::Tutor),
             1 => Ok(Mode::Assistant),
+            2 => Ok(Mode::Translator),
+            3 => Ok(Mode::CodeReview),
+            4 => Ok(Mode::Quiz),
             _ => Err("Invalid mode selected!".to_string())
         }
     }
@@ -43,6 +85,27 @@ impl Proficiency{
             _ => Err("Invalid proficiency selected!".to_string())
         }
     }
+
+    /// 0 for `Beginner` up to 3 for `Expert`, the same numbering the
+    /// frontend sends.
+    pub fn rank(self) -> u8 {
+        match self {
+            Proficiency::Beginner => 0,
+            Proficiency::Intermediate => 1,
+            Proficiency::Advanced => 2,
+            Proficiency::Expert => 3,
+        }
+    }
+
+    /// Inverse of `rank`; anything above 3 is `Expert`.
+    pub fn from_rank(rank: u8) -> Self {
+        match rank {
+            0 => Proficiency::Beginner,
+            1 => Proficiency::Intermediate,
+            2 => Proficiency::Advanced,
+            _ => Proficiency::Expert,
+        }
+    }
 }
 
 impl Personality{
@@ -56,3 +119,121 @@ impl Personality{
         }
     }
 }
+
+impl Language{
+    pub const ALL: [Language; 12] = [
+        Language::English,
+        Language::French,
+        Language::Portuguese,
+        Language::Spanish,
+        Language::German,
+        Language::Italian,
+        Language::Russian,
+        Language::Japanese,
+        Language::Mandarin,
+        Language::Arabic,
+        Language::Korean,
+        Language::Hebrew,
+    ];
+
+    /// ISO 639-1 code.
+    pub fn code(self) -> &'static str {
+        match self {
+            Language::English => "en",
+            Language::French => "fr",
+            Language::Portuguese => "pt",
+            Language::Spanish => "es",
+            Language::German => "de",
+            Language::Italian => "it",
+            Language::Russian => "ru",
+            Language::Japanese => "ja",
+            Language::Mandarin => "zh",
+            Language::Arabic => "ar",
+            Language::Korean => "ko",
+            Language::Hebrew => "he",
+        }
+    }
+
+    /// English name, as the model should read it in instructions.
+    pub fn name(self) -> &'static str {
+        match self {
+            Language::English => "English",
+            Language::French => "French",
+            Language::Portuguese => "Portuguese",
+            Language::Spanish => "Spanish",
+            Language::German => "German",
+            Language::Italian => "Italian",
+            Language::Russian => "Russian",
+            Language::Japanese => "Japanese",
+            Language::Mandarin => "Mandarin",
+            Language::Arabic => "Arabic",
+            Language::Korean => "Korean",
+            Language::Hebrew => "Hebrew",
+        }
+    }
+
+    /// Accepts region-tagged codes too: `pt-BR` and `zh_TW` resolve.
+    pub fn from_code(code: &str) -> Option<Self> {
+        let base = code.split(['-', '_']).next()?.to_ascii_lowercase();
+        Self::ALL.into_iter().find(|lang| lang.code() == base)
+    }
+
+    /// Case-insensitive English name, e.g. a `Context::domain` like "French".
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.trim();
+        if name.eq_ignore_ascii_case("chinese") {
+            return Some(Language::Mandarin);
+        }
+        Self::ALL.into_iter().find(|lang| lang.name().eq_ignore_ascii_case(name))
+    }
+
+    /// System-prompt line that keeps replies in this language.
+    pub fn reply_instruction(self) -> String {
+        format!(
+            "Always write your replies in {}, whatever language the user writes in. \
+             Examples in the language being studied may stay in that language.",
+            self.name()
+        )
+    }
+}
+
+impl fmt::Display for Language{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+impl FromStr for Language{
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_code(s).ok_or_else(|| format!("Unsupported language: {}", s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_language_serde_uses_iso_codes() {
+        assert_eq!(serde_json::to_string(&Language::Mandarin).unwrap(), "\"zh\"");
+        assert_eq!(serde_json::from_str::<Language>("\"pt\"").unwrap(), Language::Portuguese);
+        assert!(serde_json::from_str::<Language>("\"xx\"").is_err());
+        for lang in Language::ALL {
+            assert_eq!(lang.code().parse::<Language>().unwrap(), lang);
+            assert_eq!(Language::from_name(lang.name()), Some(lang));
+        }
+    }
+
+    #[test]
+    fn test_language_lookups() {
+        assert_eq!(Language::from_code("pt-BR"), Some(Language::Portuguese));
+        assert_eq!(Language::from_code("ZH_tw"), Some(Language::Mandarin));
+        assert_eq!(Language::from_name(" french "), Some(Language::French));
+        assert_eq!(Language::from_name("Chinese"), Some(Language::Mandarin));
+        assert_eq!(Language::from_name("Rust"), None);
+        assert_eq!(Language::default().to_string(), "en");
+        assert!(Language::German.reply_instruction().contains("German"));
+    }
+}
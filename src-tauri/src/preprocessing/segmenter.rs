@@ -0,0 +1,379 @@
+//! Splits input into prose and code.
+//!
+//! Learners paste snippets next to their questions. Code is recognised the
+//! way Markdown does it: a ``` or ~~~ fence, or a run of lines indented four
+//! spaces (or a tab) after a blank line. Indented runs must also look like
+//! code, so an indented paragraph stays prose. Each code segment gets a
+//! language, from the fence tag when there is one, otherwise guessed from
+//! its syntax.
+
+use serde::{Deserialize, Serialize};
+
+/// Fence tags that say nothing about the language.
+const PLAIN_TAGS: &[&str] = &["text", "txt", "plain", "plaintext"];
+
+const TAG_ALIASES: &[(&str, &str)] = &[
+    ("rs", "rust"),
+    ("py", "python"),
+    ("python3", "python"),
+    ("js", "javascript"),
+    ("jsx", "javascript"),
+    ("mjs", "javascript"),
+    ("node", "javascript"),
+    ("ts", "typescript"),
+    ("tsx", "typescript"),
+    ("golang", "go"),
+    ("cpp", "c++"),
+    ("cc", "c++"),
+    ("cxx", "c++"),
+    ("hpp", "c++"),
+    ("h", "c"),
+    ("ex", "elixir"),
+    ("exs", "elixir"),
+    ("jl", "julia"),
+    ("sc", "scala"),
+    ("cbl", "cobol"),
+    ("cob", "cobol"),
+    ("htm", "html"),
+    ("postgres", "sql"),
+    ("postgresql", "sql"),
+    ("sqlite", "sql"),
+    ("mysql", "sql"),
+];
+
+/// Telltale fragments per language, matched case-sensitively unless the
+/// language is case-insensitive itself. Names match the formatter's domains.
+const SIGNATURES: &[(&str, bool, &[&str])] = &[
+    ("rust", false, &["fn ", "let mut ", "println!", "vec!", "impl ", "pub fn", "use std::", "&mut ", "::new(", "Option<", "Result<", "Vec<", "&str", "#[derive", "match "]),
+    ("python", false, &["def ", "import ", "elif ", "self.", "print(", "None", "True", "False", "__init__", "lambda ", "range("]),
+    ("javascript", false, &["function ", "const ", "=>", "console.log", "let ", "var ", "===", "document.", "require(", "undefined"]),
+    ("go", false, &["func ", "package ", ":=", "fmt.", "go func", "chan ", "defer "]),
+    ("c++", false, &["#include", "std::", "cout", "cin >>", "template<", "template <", "nullptr", "namespace "]),
+    ("c", false, &["#include", "printf(", "malloc(", "int main", "sizeof(", "char *", "NULL"]),
+    ("elixir", false, &["defmodule", "|>", "do:", "defp ", "%{", "IO.puts", "end\n"]),
+    ("scala", false, &["object ", "val ", "case class", "extends App", "def main"]),
+    ("julia", false, &["using ", "::Int", "::Float64", ".=", "@time", "end\n"]),
+    ("sql", true, &["select ", "from ", "where ", "insert into", "create table", "join ", "group by", "order by"]),
+    ("html", true, &["<div", "</", "<html", "<body", "<p>", "<span", "class=\"", "<!doctype"]),
+    ("css", true, &["color:", "margin:", "padding:", "display:", "font-size:", "px;", "@media", "border:"]),
+    ("cobol", true, &["identification division", "procedure division", "working-storage", " pic ", "display "]),
+];
+
+/// TypeScript is JavaScript plus these; they only count on top of a JavaScript match.
+const TYPESCRIPT_SIGNATURES: &[&str] = &[": string", ": number", ": boolean", "interface ", ": void", "<T>", "export type", "as const"];
+
+/// A guess needs at least this many distinct signatures.
+const MIN_SIGNATURES: usize = 2;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Segment {
+    Prose {
+        text: String,
+    },
+    Code {
+        /// Exactly as typed, minus the fences or the block's indentation.
+        text: String,
+        /// Lowercase, named as in the formatter's domain tables; `None` if
+        /// neither a fence tag nor the syntax gave it away.
+        language: Option<String>,
+        fenced: bool,
+    },
+}
+
+impl Segment {
+    pub fn text(&self) -> &str {
+        match self {
+            Segment::Prose { text } | Segment::Code { text, .. } => text,
+        }
+    }
+
+    pub fn is_code(&self) -> bool {
+        matches!(self, Segment::Code { .. })
+    }
+
+    pub fn language(&self) -> Option<&str> {
+        match self {
+            Segment::Code { language, .. } => language.as_deref(),
+            Segment::Prose { .. } => None,
+        }
+    }
+}
+
+/// Lines `start..end` of the input and what they hold. Fenced blocks
+/// include both fence lines.
+#[derive(Debug, PartialEq)]
+enum Block {
+    Prose { start: usize, end: usize },
+    Fenced { start: usize, end: usize, closed: bool, tag: String },
+    Indented { start: usize, end: usize },
+}
+
+pub struct Segmenter;
+
+impl Segmenter {
+    pub fn segment(text: &str) -> Vec<Segment> {
+        let lines: Vec<&str> = text.split('\n').collect();
+        Self::blocks(&lines)
+            .into_iter()
+            .filter_map(|block| match block {
+                Block::Prose { start, end } => {
+                    let text = lines[start..end].join("\n").trim_matches('\n').to_string();
+                    (!text.trim().is_empty()).then_some(Segment::Prose { text })
+                }
+                Block::Fenced { start, end, closed, tag } => {
+                    let body_end = if closed { end - 1 } else { end };
+                    let text = lines[start + 1..body_end].join("\n");
+                    let language = match tag.as_str() {
+                        t if t.is_empty() || PLAIN_TAGS.contains(&t) => detect_language(&text),
+                        t => Some(canonical_tag(t)),
+                    };
+                    Some(Segment::Code { text, language, fenced: true })
+                }
+                Block::Indented { start, end } => {
+                    let text = dedent(&lines[start..end]);
+                    let language = detect_language(&text);
+                    Some(Segment::Code { text, language, fenced: false })
+                }
+            })
+            .collect()
+    }
+
+    /// The prose alone, for analyses that code would only confuse.
+    pub fn prose(text: &str) -> String {
+        Self::segment(text)
+            .iter()
+            .filter(|segment| !segment.is_code())
+            .map(Segment::text)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// For each line of `text` (split on `\n`), whether it belongs to a code
+    /// block, fences included.
+    pub(crate) fn code_mask(text: &str) -> Vec<bool> {
+        let lines: Vec<&str> = text.split('\n').collect();
+        let mut mask = vec![false; lines.len()];
+        for block in Self::blocks(&lines) {
+            if let Block::Fenced { start, end, .. } | Block::Indented { start, end } = block {
+                mask[start..end].iter_mut().for_each(|line| *line = true);
+            }
+        }
+        mask
+    }
+
+    fn blocks(lines: &[&str]) -> Vec<Block> {
+        let mut blocks = Vec::new();
+        let mut prose_start = 0;
+        let mut i = 0;
+        while i < lines.len() {
+            if let Some((fence, width, tag)) = fence_open(lines[i]) {
+                let close = (i + 1..lines.len()).find(|&j| is_fence_close(lines[j], fence, width));
+                let end = close.map_or(lines.len(), |j| j + 1);
+                push_prose(&mut blocks, prose_start, i);
+                blocks.push(Block::Fenced { start: i, end, closed: close.is_some(), tag });
+                i = end;
+                prose_start = i;
+                continue;
+            }
+
+            if is_indented(lines[i]) && (i == 0 || lines[i - 1].trim().is_empty()) {
+                let end = indented_run_end(lines, i);
+                if looks_like_code(&dedent(&lines[i..end])) {
+                    push_prose(&mut blocks, prose_start, i);
+                    blocks.push(Block::Indented { start: i, end });
+                    prose_start = end;
+                }
+                i = end;
+                continue;
+            }
+            i += 1;
+        }
+        push_prose(&mut blocks, prose_start, lines.len());
+        blocks
+    }
+}
+
+/// Best guess at the language of a code snippet.
+pub fn detect_language(code: &str) -> Option<String> {
+    let lower = code.to_lowercase();
+    let score = |case_insensitive: bool, signatures: &[&str]| {
+        let haystack = if case_insensitive { &lower } else { code };
+        signatures.iter().filter(|s| haystack.contains(*s)).count()
+    };
+
+    let (mut best, mut best_score) = (None, 0);
+    for (language, case_insensitive, signatures) in SIGNATURES {
+        let mut points = score(*case_insensitive, signatures);
+        let mut name = *language;
+        if name == "javascript" && points > 0 {
+            let typed = score(false, TYPESCRIPT_SIGNATURES);
+            if typed > 0 {
+                points += typed;
+                name = "typescript";
+            }
+        }
+        // Ties go to the earlier, more common language.
+        if points > best_score {
+            best = Some(name);
+            best_score = points;
+        }
+    }
+    best.filter(|_| best_score >= MIN_SIGNATURES).map(str::to_string)
+}
+
+fn canonical_tag(tag: &str) -> String {
+    let tag = tag.to_lowercase();
+    TAG_ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == tag)
+        .map_or(tag, |(_, language)| language.to_string())
+}
+
+fn push_prose(blocks: &mut Vec<Block>, start: usize, end: usize) {
+    if start < end {
+        blocks.push(Block::Prose { start, end });
+    }
+}
+
+/// Fence character, run length and info-string tag of an opening fence:
+/// at most three spaces of indent, then three or more backticks or tildes.
+fn fence_open(line: &str) -> Option<(char, usize, String)> {
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    if indent > 3 {
+        return None;
+    }
+    let rest = &line[indent..];
+    let fence = rest.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let width = rest.chars().take_while(|&c| c == fence).count();
+    if width < 3 {
+        return None;
+    }
+    let info = rest[width..].trim();
+    // A backtick fence's info string can't contain backticks.
+    if fence == '`' && info.contains('`') {
+        return None;
+    }
+    let tag = info.split_whitespace().next().unwrap_or("").trim_matches(['{', '}', '.']);
+    Some((fence, width, tag.to_string()))
+}
+
+fn is_fence_close(line: &str, fence: char, width: usize) -> bool {
+    let trimmed = line.trim();
+    trimmed.chars().count() >= width && trimmed.chars().all(|c| c == fence) && line.len() - line.trim_start().len() <= 3
+}
+
+fn is_indented(line: &str) -> bool {
+    (line.starts_with("    ") || line.starts_with('\t')) && !line.trim().is_empty()
+}
+
+/// End of the indented run starting at `start`. Blank lines inside the run
+/// belong to it; trailing ones don't.
+fn indented_run_end(lines: &[&str], start: usize) -> usize {
+    let mut end = start;
+    let mut i = start;
+    while i < lines.len() && (is_indented(lines[i]) || lines[i].trim().is_empty()) {
+        if is_indented(lines[i]) {
+            end = i + 1;
+        }
+        i += 1;
+    }
+    end
+}
+
+/// Strips one level of block indentation: a tab or up to four spaces.
+fn dedent(lines: &[&str]) -> String {
+    lines
+        .iter()
+        .map(|line| {
+            line.strip_prefix('\t')
+                .or_else(|| line.strip_prefix("    "))
+                .unwrap_or_else(|| line.trim_start_matches(' '))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Indented prose is common enough (quoted text, pasted emails) that an
+/// indented block must show some code syntax to count.
+fn looks_like_code(text: &str) -> bool {
+    detect_language(text).is_some()
+        || text
+            .lines()
+            .any(|line| line.trim_end().ends_with([';', '{', '}']) || line.contains(" = ") || line.contains("()"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn code(text: &str, language: Option<&str>, fenced: bool) -> Segment {
+        Segment::Code {
+            text: text.to_string(),
+            language: language.map(str::to_string),
+            fenced,
+        }
+    }
+
+    fn prose(text: &str) -> Segment {
+        Segment::Prose { text: text.to_string() }
+    }
+
+    #[test]
+    fn test_fenced_blocks() {
+        let input = "Why does this fail?\n```rs\nfn main() {\n    let  x =\t1;\n}\n```\nThanks!";
+        assert_eq!(
+            Segmenter::segment(input),
+            vec![
+                prose("Why does this fail?"),
+                code("fn main() {\n    let  x =\t1;\n}", Some("rust"), true),
+                prose("Thanks!"),
+            ]
+        );
+
+        // Untagged: guessed. Unclosed: runs to the end.
+        let input = "~~~\ndef greet(name):\n    print(name)\n~~~\n````\nSELECT * FROM users WHERE id = 1;\n```";
+        assert_eq!(
+            Segmenter::segment(input),
+            vec![
+                code("def greet(name):\n    print(name)", Some("python"), true),
+                code("SELECT * FROM users WHERE id = 1;\n```", Some("sql"), true),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_indented_blocks_must_look_like_code() {
+        let input = "My loop never ends:\n\n    while (true) {\n        counter += 1;\n    }\n\nWhat's wrong?";
+        let segments = Segmenter::segment(input);
+        assert_eq!(segments.len(), 3);
+        assert_eq!(segments[1], code("while (true) {\n    counter += 1;\n}", None, false));
+
+        let quoted = "He wrote:\n\n    I will be late tomorrow\n    because of the train\n\nWhat does that mean?";
+        assert!(Segmenter::segment(quoted).iter().all(|s| !s.is_code()));
+
+        // Without a blank line before it, indentation is just a wrapped line.
+        let wrapped = "First line\n    x = compute();";
+        assert!(Segmenter::segment(wrapped).iter().all(|s| !s.is_code()));
+    }
+
+    #[test]
+    fn test_detect_language() {
+        assert_eq!(detect_language("let mut v = Vec::new();\nv.push(1);").as_deref(), Some("rust"));
+        assert_eq!(detect_language("const add = (a, b) => a + b;").as_deref(), Some("javascript"));
+        assert_eq!(detect_language("const add = (a: number, b: number) => a + b;").as_deref(), Some("typescript"));
+        assert_eq!(detect_language("package main\nfunc main() { x := 1 }").as_deref(), Some("go"));
+        assert_eq!(detect_language("#include <iostream>\nstd::cout << 1;").as_deref(), Some("c++"));
+        assert_eq!(detect_language("x = 1"), None);
+    }
+
+    #[test]
+    fn test_mask_and_prose() {
+        let input = "Look:\n```\nlet x = 1;\n```\n\n    fn f() {}\n\ndone";
+        assert_eq!(
+            Segmenter::code_mask(input),
+            vec![false, true, true, true, false, true, false, false]
+        );
+        assert_eq!(Segmenter::prose(input), "Look:\ndone");
+    }
+}
@@ -1,49 +1,118 @@
+use crate::llama::LlmBackend;
+use super::segmenter::Segmenter;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum TokenizerError {
     #[error("No tokens found in input")]
     NoTokens,
-    #[error("Token count exceeds limit: {0}")]
-    TooManyTokens(usize),
+    #[error("Token count {count} exceeds limit of {limit}")]
+    TooManyTokens { count: usize, limit: usize },
+    #[error("Model tokenizer failed: {0}")]
+    Backend(String),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TokenInfo {
     pub tokens: Vec<String>,
     pub word_count: usize,
     pub sentence_count: usize,
+    /// Length in the active model's tokens; what the context window sees.
+    pub model_tokens: usize,
 }
 
 pub struct Tokenizer;
 
 impl Tokenizer {
-    const MAX_TOKENS: usize = 2000;
-    
-    pub fn tokenize(input: &str) -> Result<TokenInfo, TokenizerError> {
-        let tokens: Vec<String> = input
+    /// Words are still split on whitespace for the formatter's statistics,
+    /// but the size limit is checked in model tokens: user input may take at
+    /// most half the context window, leaving the rest for the system prompt,
+    /// retrieval and the reply.
+    ///
+    /// Word statistics cover prose only; pasted code is counted towards the
+    /// model-token limit but never lowercased or split into words.
+    pub fn tokenize(input: &str, llm: &dyn LlmBackend) -> Result<TokenInfo, TokenizerError> {
+        if input.trim().is_empty() {
+            return Err(TokenizerError::NoTokens);
+        }
+        let prose = Segmenter::prose(input);
+        let tokens: Vec<String> = prose
             .split_whitespace()
             .map(|token| token.to_lowercase())
             .collect();
-        
-        if tokens.is_empty() {
-            return Err(TokenizerError::NoTokens);
-        }
-        
-        if tokens.len() > Self::MAX_TOKENS {
-            return Err(TokenizerError::TooManyTokens(tokens.len()));
+
+        let model_tokens = llm
+            .count_tokens(input)
+            .map_err(|e| TokenizerError::Backend(e.to_string()))?;
+        let limit = Self::input_limit(llm);
+        if model_tokens > limit {
+            return Err(TokenizerError::TooManyTokens { count: model_tokens, limit });
         }
-        
-        let sentence_count = input
+
+        let sentence_count = prose
             .chars()
             .filter(|&c| c == '.' || c == '!' || c == '?')
             .count()
             .max(1);
-        
+
         Ok(TokenInfo {
             word_count: tokens.len(),
             sentence_count,
             tokens,
+            model_tokens,
         })
     }
-}
\ No newline at end of file
+
+    pub fn input_limit(llm: &dyn LlmBackend) -> usize {
+        llm.context_length() / 2
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llama::MockBackend;
+
+    #[test]
+    fn test_counts_model_tokens() {
+        let llm = MockBackend::echo();
+        let info = Tokenizer::tokenize("Hello there. How are you?", &llm).unwrap();
+        assert_eq!(info.word_count, 5);
+        assert_eq!(info.sentence_count, 2);
+        assert_eq!(info.model_tokens, 5);
+        assert_eq!(info.tokens[0], "hello");
+    }
+
+    #[test]
+    fn test_limit_follows_context_window() {
+        let llm = MockBackend::echo();
+        let limit = Tokenizer::input_limit(&llm);
+        assert!(Tokenizer::tokenize(&"word ".repeat(limit), &llm).is_ok());
+        match Tokenizer::tokenize(&"word ".repeat(limit + 1), &llm) {
+            Err(TokenizerError::TooManyTokens { count, limit: reported }) => {
+                assert_eq!(count, limit + 1);
+                assert_eq!(reported, limit);
+            }
+            other => panic!("Expected TooManyTokens, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_code_is_not_counted_as_words() {
+        let llm = MockBackend::echo();
+        let info = Tokenizer::tokenize("Why?\n```rust\nfn main() { Foo::Bar. }\n```", &llm).unwrap();
+        assert_eq!(info.word_count, 1);
+        assert_eq!(info.sentence_count, 1);
+        assert!(info.model_tokens > info.word_count);
+
+        let code_only = Tokenizer::tokenize("```\nlet x = 1;\n```", &llm).unwrap();
+        assert_eq!(code_only.word_count, 0);
+    }
+
+    #[test]
+    fn test_rejects_blank_input() {
+        let llm = MockBackend::echo();
+        assert!(matches!(Tokenizer::tokenize(" \n\t", &llm), Err(TokenizerError::NoTokens)));
+    }
+}
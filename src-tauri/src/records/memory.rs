@@ -0,0 +1,70 @@
+//! Long-term memory: the messages the cache flusher embedded, one row each.
+//!
+//! Kept in a database of its own, so its single writer never contends with
+//! the input records, and its table can change without a `RECORD_VERSION`
+//! bump. Vectors use the same encoding as `record_vectors`.
+
+use super::{encode, RecordStoreError};
+use rusqlite::{params, Connection};
+use std::path::Path;
+
+const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS memories (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        timestamp INTEGER NOT NULL,
+        dimension INTEGER NOT NULL,
+        vector BLOB NOT NULL,
+        payload BLOB NOT NULL
+    );";
+
+/// One embedded message; `payload` is the message as JSON.
+#[derive(Debug, Clone)]
+pub struct MemoryRecord {
+    pub vector: Vec<f32>,
+    /// Seconds since the Unix epoch.
+    pub timestamp: u64,
+    pub payload: Vec<u8>,
+}
+
+pub struct MemoryStore {
+    conn: Connection,
+}
+
+impl MemoryStore {
+    /// Opens or creates the database at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, RecordStoreError> {
+        if let Some(dir) = path.as_ref().parent() {
+            // Surfaces as the SQLite error below if it fails.
+            let _ = std::fs::create_dir_all(dir);
+        }
+        Self::init(Connection::open(path)?)
+    }
+
+    /// Nothing touches the disk.
+    pub fn in_memory() -> Result<Self, RecordStoreError> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> Result<Self, RecordStoreError> {
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self { conn })
+    }
+
+    /// Durable once this returns; every append is its own transaction.
+    pub fn append(&mut self, record: &MemoryRecord) -> Result<(), RecordStoreError> {
+        self.conn.execute(
+            "INSERT INTO memories (timestamp, dimension, vector, payload) VALUES (?1, ?2, ?3, ?4)",
+            params![
+                record.timestamp as i64,
+                record.vector.len() as i64,
+                encode(&record.vector),
+                record.payload,
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn count(&self) -> Result<usize, RecordStoreError> {
+        let count: i64 = self.conn.query_row("SELECT COUNT(*) FROM memories", [], |row| row.get(0))?;
+        Ok(count as usize)
+    }
+}
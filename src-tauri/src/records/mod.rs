@@ -0,0 +1,427 @@
+//! Local store for the `SqliteVecRecord` built for every input.
+//!
+//! Records live in an embedded SQLite database, one row per input, with the
+//! vector in a side table once the input has been embedded. Similarity
+//! queries narrow the candidates with the same filters the formatter hands
+//! out (`FormattedInput::get_search_filters`) in SQL, then rank what is left
+//! by cosine similarity. A learner's history is a few thousand rows at most,
+//! so a linear scan over the filtered set is plenty.
+//!
+//! `memory` holds what the cache flusher embeds from finished exchanges.
+
+pub mod memory;
+pub mod schema;
+
+pub use memory::{MemoryRecord, MemoryStore};
+use crate::preprocessing::formatter::{SqliteVecRecord, RECORD_VERSION};
+use crate::preprocessing::FormattedInput;
+use rusqlite::types::Value as SqlValue;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, Row};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+use thiserror::Error;
+
+/// Columns a search may filter on.
+pub const FILTERABLE: &[&str] = &[
+    "domain",
+    "domain_category",
+    "complexity_tier",
+    "proficiency_level",
+    "mode",
+    "proficiency",
+    "personality",
+    "language",
+    "detected_language",
+    "action",
+];
+
+#[derive(Error, Debug)]
+pub enum RecordStoreError {
+    #[error("SQLite error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+    #[error("Database is at record version {0}, newer than this build understands")]
+    UnsupportedVersion(u32),
+    #[error("Cannot filter on {0:?}")]
+    UnknownFilter(String),
+    #[error("Unsupported value for filter {0:?}")]
+    InvalidFilterValue(String),
+}
+
+/// A stored record and how close it is to the query, from -1.0 to 1.0.
+#[derive(Debug, Clone)]
+pub struct ScoredRecord {
+    pub score: f32,
+    pub record: SqliteVecRecord,
+}
+
+pub struct RecordStore {
+    conn: Mutex<Connection>,
+}
+
+impl RecordStore {
+    /// Opens or creates the database at `path` and migrates it.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, RecordStoreError> {
+        if let Some(dir) = path.as_ref().parent() {
+            // Surfaces as the SQLite error below if it fails.
+            let _ = std::fs::create_dir_all(dir);
+        }
+        Self::init(Connection::open(path)?)
+    }
+
+    /// Nothing touches the disk.
+    pub fn in_memory() -> Result<Self, RecordStoreError> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(mut conn: Connection) -> Result<Self, RecordStoreError> {
+        conn.pragma_update(None, "foreign_keys", true)?;
+        schema::migrate(&mut conn)?;
+        Ok(Self { conn: Mutex::new(conn) })
+    }
+
+    /// Inserts or replaces `record`, and its vector when it has one.
+    pub fn insert(&self, record: &SqliteVecRecord) -> Result<(), RecordStoreError> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT OR REPLACE INTO records (
+                id, record_version, raw_input, cleaned_input, action, domain, topic,
+                mode, proficiency, personality, language, word_count, sentence_count,
+                token_preview, complexity_score, estimated_processing_time,
+                suggested_response_length, domain_category, complexity_tier,
+                proficiency_level, detected_language, language_confidence,
+                language_mismatch, created_at, updated_at
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15,
+                      ?16, ?17, ?18, ?19, ?20, ?21, ?22, ?23, ?24, ?25)",
+            params![
+                record.id,
+                RECORD_VERSION,
+                record.raw_input,
+                record.cleaned_input,
+                record.action,
+                record.domain,
+                record.topic,
+                record.mode,
+                record.proficiency,
+                record.personality,
+                record.language,
+                record.word_count,
+                record.sentence_count,
+                record.token_preview,
+                record.complexity_score,
+                record.estimated_processing_time,
+                record.suggested_response_length,
+                record.domain_category,
+                record.complexity_tier,
+                record.proficiency_level,
+                record.detected_language,
+                record.language_confidence,
+                record.language_mismatch,
+                record.created_at,
+                record.updated_at,
+            ],
+        )?;
+        if !record.vector.is_empty() {
+            write_vector(&tx, &record.id, &record.vector)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Attaches the embedding of an already stored input.
+    pub fn set_vector(&self, id: &str, vector: &[f32]) -> Result<(), RecordStoreError> {
+        let conn = self.conn.lock().unwrap();
+        write_vector(&conn, id, vector)?;
+        conn.execute("UPDATE records SET updated_at = ?2 WHERE id = ?1", params![id, now()])?;
+        Ok(())
+    }
+
+    pub fn get(&self, id: &str) -> Result<Option<SqliteVecRecord>, RecordStoreError> {
+        let conn = self.conn.lock().unwrap();
+        let record = conn
+            .query_row(&format!("{} WHERE r.id = ?1", SELECT), [id], read_record)
+            .optional()?;
+        Ok(record)
+    }
+
+    pub fn delete(&self, id: &str) -> Result<bool, RecordStoreError> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn.execute("DELETE FROM records WHERE id = ?1", [id])? > 0)
+    }
+
+    pub fn count(&self) -> Result<usize, RecordStoreError> {
+        let conn = self.conn.lock().unwrap();
+        let count: i64 = conn.query_row("SELECT COUNT(*) FROM records", [], |row| row.get(0))?;
+        Ok(count as usize)
+    }
+
+    /// The `k` embedded records closest to `vector` among those matching
+    /// every filter, best first. A string filter matches exactly, an array
+    /// matches any of its values; empty strings are ignored, since they mean
+    /// the formatter had nothing to say about that field.
+    pub fn search(
+        &self,
+        vector: &[f32],
+        filters: &HashMap<String, Value>,
+        k: usize,
+    ) -> Result<Vec<ScoredRecord>, RecordStoreError> {
+        if vector.is_empty() || k == 0 {
+            return Ok(Vec::new());
+        }
+        let (clause, values) = where_clause(filters)?;
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "{} WHERE v.dimension = ?1{}",
+            SELECT, clause
+        ))?;
+        let params = std::iter::once(SqlValue::Integer(vector.len() as i64)).chain(values);
+        let mut scored = stmt
+            .query_map(params_from_iter(params), read_record)?
+            .map(|record| {
+                let record = record?;
+                Ok(ScoredRecord { score: cosine(vector, &record.vector), record })
+            })
+            .collect::<Result<Vec<_>, RecordStoreError>>()?;
+        scored.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+        scored.truncate(k);
+        Ok(scored)
+    }
+
+    /// Past inputs like `formatted`, filtered by its own search filters.
+    pub fn similar(&self, formatted: &FormattedInput, k: usize) -> Result<Vec<ScoredRecord>, RecordStoreError> {
+        let mut scored = self.search(formatted.get_vector(), &formatted.get_search_filters(), k + 1)?;
+        scored.retain(|s| s.record.id != formatted.get_id());
+        scored.truncate(k);
+        Ok(scored)
+    }
+}
+
+/// Every record column, with the vector (empty when not yet embedded).
+const SELECT: &str = "SELECT r.id, r.raw_input, r.cleaned_input, r.action, r.domain, r.topic,
+        r.mode, r.proficiency, r.personality, r.language, r.word_count, r.sentence_count,
+        r.token_preview, r.complexity_score, r.estimated_processing_time,
+        r.suggested_response_length, r.domain_category, r.complexity_tier,
+        r.proficiency_level, r.detected_language, r.language_confidence,
+        r.language_mismatch, r.created_at, r.updated_at, v.vector
+    FROM records r LEFT JOIN record_vectors v ON v.id = r.id";
+
+fn read_record(row: &Row<'_>) -> rusqlite::Result<SqliteVecRecord> {
+    let vector: Option<Vec<u8>> = row.get(24)?;
+    Ok(SqliteVecRecord {
+        id: row.get(0)?,
+        vector: vector.map(|bytes| decode(&bytes)).unwrap_or_default(),
+        raw_input: row.get(1)?,
+        cleaned_input: row.get(2)?,
+        action: row.get(3)?,
+        domain: row.get(4)?,
+        topic: row.get(5)?,
+        mode: row.get(6)?,
+        proficiency: row.get(7)?,
+        personality: row.get(8)?,
+        language: row.get(9)?,
+        word_count: row.get(10)?,
+        sentence_count: row.get(11)?,
+        token_preview: row.get(12)?,
+        complexity_score: row.get(13)?,
+        estimated_processing_time: row.get(14)?,
+        suggested_response_length: row.get(15)?,
+        domain_category: row.get(16)?,
+        complexity_tier: row.get(17)?,
+        proficiency_level: row.get(18)?,
+        detected_language: row.get(19)?,
+        language_confidence: row.get(20)?,
+        language_mismatch: row.get(21)?,
+        created_at: row.get(22)?,
+        updated_at: row.get(23)?,
+    })
+}
+
+fn write_vector(conn: &Connection, id: &str, vector: &[f32]) -> Result<(), RecordStoreError> {
+    conn.execute(
+        "INSERT OR REPLACE INTO record_vectors (id, dimension, vector) VALUES (?1, ?2, ?3)",
+        params![id, vector.len() as i64, encode(vector)],
+    )?;
+    Ok(())
+}
+
+/// ` AND ...` for every filter, with its bound values in order.
+fn where_clause(filters: &HashMap<String, Value>) -> Result<(String, Vec<SqlValue>), RecordStoreError> {
+    // Sorted, so the same filters always prepare the same statement.
+    let mut keys: Vec<&String> = filters.keys().collect();
+    keys.sort();
+
+    let mut clause = String::new();
+    let mut values = Vec::new();
+    for key in keys {
+        let column = FILTERABLE
+            .iter()
+            .find(|column| **column == key.as_str())
+            .ok_or_else(|| RecordStoreError::UnknownFilter(key.clone()))?;
+        let wanted: Vec<SqlValue> = match &filters[key] {
+            Value::Array(items) => items.iter().map(|item| sql_value(key, item)).collect::<Result<_, _>>()?,
+            Value::String(s) if s.is_empty() => continue,
+            value => vec![sql_value(key, value)?],
+        };
+        if wanted.is_empty() {
+            continue;
+        }
+        let placeholders = vec!["?"; wanted.len()].join(", ");
+        clause.push_str(&format!(" AND r.{} IN ({})", column, placeholders));
+        values.extend(wanted);
+    }
+    Ok((clause, values))
+}
+
+fn sql_value(key: &str, value: &Value) -> Result<SqlValue, RecordStoreError> {
+    match value {
+        Value::String(s) => Ok(SqlValue::Text(s.clone())),
+        Value::Bool(b) => Ok(SqlValue::Integer(*b as i64)),
+        Value::Number(n) => n
+            .as_i64()
+            .map(SqlValue::Integer)
+            .or_else(|| n.as_f64().map(SqlValue::Real))
+            .ok_or_else(|| RecordStoreError::InvalidFilterValue(key.to_string())),
+        _ => Err(RecordStoreError::InvalidFilterValue(key.to_string())),
+    }
+}
+
+/// Little-endian `f32`s, the same layout as the embedding cache's files.
+fn encode(vector: &[f32]) -> Vec<u8> {
+    vector.iter().flat_map(|x| x.to_le_bytes()).collect()
+}
+
+fn decode(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks_exact(4)
+        .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect()
+}
+
+fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let denom = norm(a) * norm(b);
+    if denom <= f32::EPSILON {
+        return 0.0;
+    }
+    dot / denom
+}
+
+fn now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn record(id: &str, domain: &str, tier: &str, vector: Vec<f32>) -> SqliteVecRecord {
+        SqliteVecRecord {
+            id: id.to_string(),
+            vector,
+            raw_input: format!("question {}", id),
+            cleaned_input: format!("question {}", id),
+            action: "explain".into(),
+            domain: domain.into(),
+            topic: "ownership".into(),
+            mode: "Tutor".into(),
+            proficiency: "Beginner".into(),
+            personality: "Aurora".into(),
+            language: "en".into(),
+            word_count: 2,
+            sentence_count: 1,
+            token_preview: "question".into(),
+            complexity_score: 1.5,
+            estimated_processing_time: 3,
+            suggested_response_length: "Standard".into(),
+            domain_category: "programming_language".into(),
+            complexity_tier: tier.into(),
+            proficiency_level: "novice".into(),
+            detected_language: "en".into(),
+            language_confidence: 0.9,
+            language_mismatch: false,
+            created_at: 1,
+            updated_at: 1,
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let store = RecordStore::in_memory().unwrap();
+        let original = record("a", "rust", "low", vec![0.5, -1.0, 2.0]);
+        store.insert(&original).unwrap();
+
+        let loaded = store.get("a").unwrap().unwrap();
+        assert_eq!(loaded.vector, original.vector);
+        assert_eq!(loaded.domain, "rust");
+        assert!(!loaded.language_mismatch);
+        assert_eq!(store.count().unwrap(), 1);
+
+        assert!(store.delete("a").unwrap());
+        assert!(store.get("a").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_search_filters_then_ranks() {
+        let store = RecordStore::in_memory().unwrap();
+        store.insert(&record("near", "rust", "low", vec![1.0, 0.1])).unwrap();
+        store.insert(&record("far", "rust", "low", vec![0.0, 1.0])).unwrap();
+        store.insert(&record("other", "python", "low", vec![1.0, 0.0])).unwrap();
+        store.insert(&record("pending", "rust", "low", Vec::new())).unwrap();
+
+        let filters = HashMap::from([
+            ("domain".to_string(), json!("rust")),
+            ("complexity_tier".to_string(), json!(["low", "medium"])),
+            ("proficiency_level".to_string(), json!("")),
+        ]);
+        let hits = store.search(&[1.0, 0.0], &filters, 5).unwrap();
+        let ids: Vec<_> = hits.iter().map(|h| h.record.id.as_str()).collect();
+        assert_eq!(ids, ["near", "far"]);
+        assert!(hits[0].score > hits[1].score);
+
+        // Embedded later, the pending record becomes searchable.
+        store.set_vector("pending", &[1.0, 0.0]).unwrap();
+        assert_eq!(store.search(&[1.0, 0.0], &filters, 1).unwrap()[0].record.id, "pending");
+
+        let bad = HashMap::from([("raw_input".to_string(), json!("x"))]);
+        assert!(matches!(store.search(&[1.0, 0.0], &bad, 1), Err(RecordStoreError::UnknownFilter(_))));
+    }
+
+    #[test]
+    fn test_memories_persist() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("memory.sqlite3");
+        {
+            let mut store = MemoryStore::open(&path).unwrap();
+            let record = MemoryRecord { vector: vec![0.5, 1.0], timestamp: 7, payload: b"{}".to_vec() };
+            store.append(&record).unwrap();
+            store.append(&record).unwrap();
+        }
+        assert_eq!(MemoryStore::open(&path).unwrap().count().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_migrations_are_versioned() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("records.sqlite3");
+        {
+            let store = RecordStore::open(&path).unwrap();
+            store.insert(&record("a", "rust", "low", vec![1.0])).unwrap();
+        }
+        // Reopening runs nothing twice and keeps the data.
+        let store = RecordStore::open(&path).unwrap();
+        assert_eq!(store.count().unwrap(), 1);
+        drop(store);
+
+        let conn = Connection::open(&path).unwrap();
+        assert_eq!(schema::user_version(&conn).unwrap(), RECORD_VERSION);
+        conn.pragma_update(None, "user_version", RECORD_VERSION + 1).unwrap();
+        drop(conn);
+        assert!(matches!(RecordStore::open(&path), Err(RecordStoreError::UnsupportedVersion(_))));
+    }
+}
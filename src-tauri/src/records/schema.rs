@@ -0,0 +1,70 @@
+//! Table layout and the migrations that get there.
+//!
+//! The database's `user_version` is the `RECORD_VERSION` its tables match.
+//! Migration `n` upgrades a version `n - 1` database to `n`; each row also
+//! keeps the version it was written at, so code reading old rows knows
+//! which columns were filled in for real and which got a default.
+
+use super::RecordStoreError;
+use crate::preprocessing::formatter::RECORD_VERSION;
+use rusqlite::Connection;
+
+/// Indexed by `RECORD_VERSION - 1`.
+const MIGRATIONS: &[&str] = &[
+    // 1: `SqliteVecRecord` as first stored.
+    "CREATE TABLE records (
+        id TEXT PRIMARY KEY,
+        record_version INTEGER NOT NULL,
+        raw_input TEXT NOT NULL,
+        cleaned_input TEXT NOT NULL,
+        action TEXT NOT NULL,
+        domain TEXT NOT NULL,
+        topic TEXT NOT NULL,
+        mode TEXT NOT NULL,
+        proficiency TEXT NOT NULL,
+        personality TEXT NOT NULL,
+        language TEXT NOT NULL,
+        word_count INTEGER NOT NULL,
+        sentence_count INTEGER NOT NULL,
+        token_preview TEXT NOT NULL,
+        complexity_score REAL NOT NULL,
+        estimated_processing_time INTEGER NOT NULL,
+        suggested_response_length TEXT NOT NULL,
+        domain_category TEXT NOT NULL,
+        complexity_tier TEXT NOT NULL,
+        proficiency_level TEXT NOT NULL,
+        detected_language TEXT NOT NULL,
+        language_confidence REAL NOT NULL,
+        language_mismatch INTEGER NOT NULL,
+        created_at INTEGER NOT NULL,
+        updated_at INTEGER NOT NULL
+    );
+    CREATE INDEX records_filters
+        ON records (domain, domain_category, complexity_tier, proficiency_level);
+    CREATE TABLE record_vectors (
+        id TEXT PRIMARY KEY REFERENCES records (id) ON DELETE CASCADE,
+        dimension INTEGER NOT NULL,
+        vector BLOB NOT NULL
+    );",
+];
+
+/// Brings `conn` up to `RECORD_VERSION`, one migration per transaction.
+pub fn migrate(conn: &mut Connection) -> Result<u32, RecordStoreError> {
+    debug_assert_eq!(MIGRATIONS.len(), RECORD_VERSION as usize);
+    let mut version = user_version(conn)?;
+    if version > RECORD_VERSION {
+        return Err(RecordStoreError::UnsupportedVersion(version));
+    }
+    while version < RECORD_VERSION {
+        let tx = conn.transaction()?;
+        tx.execute_batch(MIGRATIONS[version as usize])?;
+        tx.pragma_update(None, "user_version", version + 1)?;
+        tx.commit()?;
+        version += 1;
+    }
+    Ok(version)
+}
+
+pub fn user_version(conn: &Connection) -> Result<u32, RecordStoreError> {
+    Ok(conn.pragma_query_value(None, "user_version", |row| row.get(0))?)
+}
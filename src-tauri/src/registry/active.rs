@@ -0,0 +1,70 @@
+//! The engines behind the active selection.
+//!
+//! One instance is managed by the app; `send_output` takes its chat engine
+//! from here, the cache flusher and retrieval their embedder, and
+//! `select_model` swaps new engines in. Engines are handed out as `Arc`s, so
+//! a swap never pulls one out from under a generation or a flush that is
+//! still running: the old engine is dropped once they finish. Until
+//! something is selected, each slot loads the registry's preferred model on
+//! first use.
+
+use crate::embedding::{self, cache::DEFAULT_CAPACITY, CachedEmbedder, EmbeddingCache, EmbeddingEngine};
+use crate::llama::{self, LLMEngine};
+use std::sync::{Arc, Mutex};
+
+pub struct ActiveModels {
+    chat: Mutex<Option<Arc<LLMEngine>>>,
+    embedding: Mutex<Option<Arc<EmbeddingEngine>>>,
+    /// Shared by every embedder handed out, whatever engine it wraps.
+    cache: Arc<EmbeddingCache>,
+}
+
+impl Default for ActiveModels {
+    fn default() -> Self {
+        Self::new(Arc::new(EmbeddingCache::in_memory(DEFAULT_CAPACITY)))
+    }
+}
+
+impl ActiveModels {
+    /// Embedders go through `cache`; it rebinds itself when the model changes.
+    pub fn new(cache: Arc<EmbeddingCache>) -> Self {
+        Self {
+            chat: Mutex::new(None),
+            embedding: Mutex::new(None),
+            cache,
+        }
+    }
+
+    /// The active chat engine, loading `LLMEngine::from_models_dir` if none is.
+    pub fn chat(&self) -> llama::Result<Arc<LLMEngine>> {
+        let mut chat = self.chat.lock().unwrap();
+        if chat.is_none() {
+            *chat = Some(Arc::new(LLMEngine::from_models_dir()?));
+        }
+        Ok(Arc::clone(chat.as_ref().unwrap()))
+    }
+
+    /// The active embedding engine, loading `EmbeddingEngine::from_models_dir`
+    /// if none is.
+    pub fn embedding(&self) -> embedding::Result<Arc<EmbeddingEngine>> {
+        let mut engine = self.embedding.lock().unwrap();
+        if engine.is_none() {
+            *engine = Some(Arc::new(EmbeddingEngine::from_models_dir()?));
+        }
+        Ok(Arc::clone(engine.as_ref().unwrap()))
+    }
+
+    /// The active embedding engine behind the shared cache.
+    pub fn embedder(&self) -> embedding::Result<CachedEmbedder> {
+        let engine = EmbeddingEngine::clone(&*self.embedding()?);
+        Ok(CachedEmbedder::new(engine, Arc::clone(&self.cache)))
+    }
+
+    pub fn set_chat(&self, engine: LLMEngine) {
+        *self.chat.lock().unwrap() = Some(Arc::new(engine));
+    }
+
+    pub fn set_embedding(&self, engine: EmbeddingEngine) {
+        *self.embedding.lock().unwrap() = Some(Arc::new(engine));
+    }
+}
@@ -0,0 +1,313 @@
+//! Minimal GGUF header reader.
+//!
+//! Only the metadata key/value section is decoded; tensor data is never
+//! touched, so inspecting a multi-GB model costs a few KB of I/O. Arrays
+//! (tokenizer vocabularies and merges, mostly) are skipped and only their
+//! length is kept.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek};
+use std::path::Path;
+use thiserror::Error;
+
+const MAGIC: &[u8; 4] = b"GGUF";
+// Anything longer is a corrupt length field, not a real string.
+const MAX_STRING_BYTES: u64 = 16 * 1024 * 1024;
+
+#[derive(Error, Debug)]
+pub enum GgufError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Not a GGUF file")]
+    BadMagic,
+    #[error("Unsupported GGUF version {0}")]
+    UnsupportedVersion(u32),
+    #[error("Corrupt header: {0}")]
+    Corrupt(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GgufValue {
+    Uint(u64),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Str(String),
+    /// Element type and count; the elements themselves are skipped.
+    Array { item_type: u32, len: u64 },
+}
+
+impl GgufValue {
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            GgufValue::Uint(v) => Some(v),
+            GgufValue::Int(v) if v >= 0 => Some(v as u64),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            GgufValue::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GgufHeader {
+    pub version: u32,
+    pub tensor_count: u64,
+    pub metadata: HashMap<String, GgufValue>,
+}
+
+impl GgufHeader {
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self, GgufError> {
+        Self::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    pub fn from_reader<R: Read + Seek>(mut r: R) -> Result<Self, GgufError> {
+        let mut magic = [0u8; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(GgufError::BadMagic);
+        }
+        // v1 used 32-bit counts and is long gone from the wild.
+        let version = read_u32(&mut r)?;
+        if !(2..=3).contains(&version) {
+            return Err(GgufError::UnsupportedVersion(version));
+        }
+        let tensor_count = read_u64(&mut r)?;
+        let kv_count = read_u64(&mut r)?;
+
+        let mut metadata = HashMap::new();
+        for _ in 0..kv_count {
+            let key = read_string(&mut r)?;
+            let value_type = read_u32(&mut r)?;
+            let value = read_value(&mut r, value_type)?;
+            metadata.insert(key, value);
+        }
+        Ok(Self { version, tensor_count, metadata })
+    }
+
+    pub fn get(&self, key: &str) -> Option<&GgufValue> {
+        self.metadata.get(key)
+    }
+
+    pub fn architecture(&self) -> Option<&str> {
+        self.get("general.architecture").and_then(GgufValue::as_str)
+    }
+
+    /// Looks up an architecture-scoped key such as `qwen2.context_length`.
+    pub fn arch_u64(&self, suffix: &str) -> Option<u64> {
+        let arch = self.architecture()?;
+        self.get(&format!("{}.{}", arch, suffix)).and_then(GgufValue::as_u64)
+    }
+
+    /// Weight format from `general.file_type` (llama.cpp's `llama_ftype`).
+    pub fn quantization(&self) -> Option<String> {
+        let file_type = self.get("general.file_type")?.as_u64()?;
+        let name = match file_type {
+            0 => "F32",
+            1 => "F16",
+            2 => "Q4_0",
+            3 => "Q4_1",
+            7 => "Q8_0",
+            8 => "Q5_0",
+            9 => "Q5_1",
+            10 => "Q2_K",
+            11 => "Q3_K_S",
+            12 => "Q3_K_M",
+            13 => "Q3_K_L",
+            14 => "Q4_K_S",
+            15 => "Q4_K_M",
+            16 => "Q5_K_S",
+            17 => "Q5_K_M",
+            18 => "Q6_K",
+            25 => "IQ4_NL",
+            30 => "IQ4_XS",
+            32 => "BF16",
+            other => return Some(format!("ftype {}", other)),
+        };
+        Some(name.to_string())
+    }
+}
+
+fn read_value<R: Read + Seek>(r: &mut R, value_type: u32) -> Result<GgufValue, GgufError> {
+    Ok(match value_type {
+        0 => GgufValue::Uint(read_n::<1, _>(r)?[0] as u64),
+        1 => GgufValue::Int(read_n::<1, _>(r)?[0] as i8 as i64),
+        2 => GgufValue::Uint(u16::from_le_bytes(read_n(r)?) as u64),
+        3 => GgufValue::Int(i16::from_le_bytes(read_n(r)?) as i64),
+        4 => GgufValue::Uint(read_u32(r)? as u64),
+        5 => GgufValue::Int(i32::from_le_bytes(read_n(r)?) as i64),
+        6 => GgufValue::Float(f32::from_le_bytes(read_n(r)?) as f64),
+        7 => GgufValue::Bool(read_n::<1, _>(r)?[0] != 0),
+        8 => GgufValue::Str(read_string(r)?),
+        9 => {
+            let item_type = read_u32(r)?;
+            let len = read_u64(r)?;
+            skip_array(r, item_type, len)?;
+            GgufValue::Array { item_type, len }
+        }
+        10 => GgufValue::Uint(read_u64(r)?),
+        11 => GgufValue::Int(i64::from_le_bytes(read_n(r)?)),
+        12 => GgufValue::Float(f64::from_le_bytes(read_n(r)?)),
+        other => return Err(GgufError::Corrupt(format!("unknown value type {}", other))),
+    })
+}
+
+fn skip_array<R: Read + Seek>(r: &mut R, item_type: u32, len: u64) -> Result<(), GgufError> {
+    let width: i64 = match item_type {
+        0 | 1 | 7 => 1,
+        2 | 3 => 2,
+        4..=6 => 4,
+        10..=12 => 8,
+        8 => {
+            for _ in 0..len {
+                let n = read_u64(r)?;
+                if n > MAX_STRING_BYTES {
+                    return Err(GgufError::Corrupt(format!("string of {} bytes", n)));
+                }
+                r.seek_relative(n as i64)?;
+            }
+            return Ok(());
+        }
+        9 => {
+            for _ in 0..len {
+                let inner_type = read_u32(r)?;
+                let inner_len = read_u64(r)?;
+                skip_array(r, inner_type, inner_len)?;
+            }
+            return Ok(());
+        }
+        other => return Err(GgufError::Corrupt(format!("unknown array type {}", other))),
+    };
+    let bytes = i64::try_from(len)
+        .ok()
+        .and_then(|len| len.checked_mul(width))
+        .ok_or_else(|| GgufError::Corrupt(format!("array of {} elements", len)))?;
+    r.seek_relative(bytes)?;
+    Ok(())
+}
+
+fn read_n<const N: usize, R: Read>(r: &mut R) -> Result<[u8; N], GgufError> {
+    let mut buf = [0u8; N];
+    r.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn read_u32<R: Read>(r: &mut R) -> Result<u32, GgufError> {
+    Ok(u32::from_le_bytes(read_n(r)?))
+}
+
+fn read_u64<R: Read>(r: &mut R) -> Result<u64, GgufError> {
+    Ok(u64::from_le_bytes(read_n(r)?))
+}
+
+fn read_string<R: Read>(r: &mut R) -> Result<String, GgufError> {
+    let len = read_u64(r)?;
+    if len > MAX_STRING_BYTES {
+        return Err(GgufError::Corrupt(format!("string of {} bytes", len)));
+    }
+    let mut buf = vec![0u8; len as usize];
+    r.read_exact(&mut buf)?;
+    Ok(String::from_utf8_lossy(&buf).into_owned())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Builds a tiny GGUF v3 header in memory.
+    pub(crate) struct Writer(pub Vec<u8>);
+
+    impl Writer {
+        pub fn new(kv_count: u64) -> Self {
+            let mut buf = MAGIC.to_vec();
+            buf.extend_from_slice(&3u32.to_le_bytes());
+            buf.extend_from_slice(&0u64.to_le_bytes());
+            buf.extend_from_slice(&kv_count.to_le_bytes());
+            Writer(buf)
+        }
+        fn key(&mut self, key: &str, value_type: u32) {
+            self.raw_str(key);
+            self.0.extend_from_slice(&value_type.to_le_bytes());
+        }
+        fn raw_str(&mut self, s: &str) {
+            self.0.extend_from_slice(&(s.len() as u64).to_le_bytes());
+            self.0.extend_from_slice(s.as_bytes());
+        }
+        pub fn string(mut self, key: &str, value: &str) -> Self {
+            self.key(key, 8);
+            self.raw_str(value);
+            self
+        }
+        pub fn u32(mut self, key: &str, value: u32) -> Self {
+            self.key(key, 4);
+            self.0.extend_from_slice(&value.to_le_bytes());
+            self
+        }
+        pub fn strings(mut self, key: &str, items: &[&str]) -> Self {
+            self.key(key, 9);
+            self.0.extend_from_slice(&8u32.to_le_bytes());
+            self.0.extend_from_slice(&(items.len() as u64).to_le_bytes());
+            for item in items {
+                self.raw_str(item);
+            }
+            self
+        }
+        pub fn floats(mut self, key: &str, items: &[f32]) -> Self {
+            self.key(key, 9);
+            self.0.extend_from_slice(&6u32.to_le_bytes());
+            self.0.extend_from_slice(&(items.len() as u64).to_le_bytes());
+            for item in items {
+                self.0.extend_from_slice(&item.to_le_bytes());
+            }
+            self
+        }
+    }
+
+    #[test]
+    fn test_reads_metadata_and_skips_arrays() {
+        let bytes = Writer::new(5)
+            .string("general.architecture", "qwen2")
+            .strings("tokenizer.ggml.tokens", &["<s>", "hello", "world"])
+            .floats("tokenizer.ggml.scores", &[0.0, -1.0, -2.0])
+            .u32("qwen2.context_length", 32768)
+            .u32("general.file_type", 17)
+            .0;
+        let header = GgufHeader::from_reader(Cursor::new(bytes)).unwrap();
+        assert_eq!(header.version, 3);
+        assert_eq!(header.architecture(), Some("qwen2"));
+        assert_eq!(header.arch_u64("context_length"), Some(32768));
+        assert_eq!(header.quantization().as_deref(), Some("Q5_K_M"));
+        assert_eq!(
+            header.get("tokenizer.ggml.tokens"),
+            Some(&GgufValue::Array { item_type: 8, len: 3 })
+        );
+    }
+
+    #[test]
+    fn test_rejects_non_gguf_and_truncated_input() {
+        assert!(matches!(
+            GgufHeader::from_reader(Cursor::new(b"PK\x03\x04rest".to_vec())),
+            Err(GgufError::BadMagic)
+        ));
+        let mut bytes = Writer::new(1).string("general.name", "cut short").0;
+        bytes.truncate(bytes.len() - 3);
+        assert!(matches!(GgufHeader::from_reader(Cursor::new(bytes)), Err(GgufError::Io(_))));
+    }
+
+    #[test]
+    fn test_rejects_absurd_lengths() {
+        let mut bytes = Writer::new(1).0;
+        bytes.extend_from_slice(&u64::MAX.to_le_bytes());
+        assert!(matches!(
+            GgufHeader::from_reader(Cursor::new(bytes)),
+            Err(GgufError::Corrupt(_))
+        ));
+    }
+}
@@ -0,0 +1,260 @@
+//! Discovery and selection of local GGUF models.
+//!
+//! The registry walks a models directory, reads each file's GGUF header and
+//! remembers which chat and embedding model is currently active. The engines
+//! themselves live in `ActiveModels`, so a swap is just "look up the path,
+//! build a new engine, replace the old one" and every reader sees it.
+
+pub mod active;
+pub mod gguf;
+
+pub use active::ActiveModels;
+
+use gguf::{GgufError, GgufHeader, GgufValue};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Environment variable that overrides `DEFAULT_MODELS_DIR`.
+pub const MODELS_DIR_ENV: &str = "ATHENA_MODELS_DIR";
+pub const DEFAULT_MODELS_DIR: &str = "llama/models";
+
+// Architectures that only ever ship as encoders.
+const EMBEDDING_ARCHS: &[&str] = &["bert", "nomic-bert", "jina-bert-v2", "t5encoder"];
+
+#[derive(Error, Debug)]
+pub enum RegistryError {
+    #[error("Models directory not readable: {path} ({source})")]
+    DirUnreadable { path: String, source: std::io::Error },
+    #[error("Unknown model: {0}")]
+    UnknownModel(String),
+    #[error("No {0:?} model available")]
+    NoModel(ModelKind),
+}
+
+pub type Result<T> = std::result::Result<T, RegistryError>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModelKind {
+    Chat,
+    Embedding,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelInfo {
+    /// File stem, unique within the registry.
+    pub id: String,
+    pub path: PathBuf,
+    pub kind: ModelKind,
+    pub name: Option<String>,
+    pub architecture: String,
+    pub context_length: Option<u64>,
+    pub embedding_dim: Option<u64>,
+    pub quantization: Option<String>,
+    pub file_size: u64,
+}
+
+impl ModelInfo {
+    pub fn inspect<P: AsRef<Path>>(path: P) -> std::result::Result<Self, GgufError> {
+        let path = path.as_ref();
+        let header = GgufHeader::read(path)?;
+        let architecture = header.architecture().unwrap_or("unknown").to_string();
+        let kind = if EMBEDDING_ARCHS.contains(&architecture.as_str())
+            || header.get(&format!("{}.pooling_type", architecture)).is_some()
+        {
+            ModelKind::Embedding
+        } else {
+            ModelKind::Chat
+        };
+        Ok(Self {
+            id: path
+                .file_stem()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            path: path.to_path_buf(),
+            kind,
+            name: header.get("general.name").and_then(GgufValue::as_str).map(str::to_string),
+            context_length: header.arch_u64("context_length"),
+            embedding_dim: header.arch_u64("embedding_length"),
+            quantization: header.quantization(),
+            architecture,
+            file_size: std::fs::metadata(path)?.len(),
+        })
+    }
+}
+
+/// What `list_models` hands the frontend.
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelListing {
+    pub models: Vec<ModelInfo>,
+    pub active_chat: Option<String>,
+    pub active_embedding: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct ModelRegistry {
+    dir: PathBuf,
+    models: Vec<ModelInfo>,
+    active_chat: Option<String>,
+    active_embedding: Option<String>,
+}
+
+impl ModelRegistry {
+    /// `$ATHENA_MODELS_DIR`, falling back to `DEFAULT_MODELS_DIR`.
+    pub fn default_dir() -> PathBuf {
+        std::env::var_os(MODELS_DIR_ENV)
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_MODELS_DIR))
+    }
+
+    /// An empty registry over `dir`; call `rescan` to populate it.
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+            ..Default::default()
+        }
+    }
+
+    pub fn scan<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let mut registry = Self::new(dir);
+        registry.rescan()?;
+        Ok(registry)
+    }
+
+    /// Re-reads the directory. Active selections survive if the file does.
+    pub fn rescan(&mut self) -> Result<()> {
+        let mut models = Vec::new();
+        collect(&self.dir, &mut models).map_err(|source| RegistryError::DirUnreadable {
+            path: self.dir.display().to_string(),
+            source,
+        })?;
+        models.sort_by(|a, b| a.id.cmp(&b.id));
+        models.dedup_by(|a, b| a.id == b.id);
+        self.models = models;
+        if self.active_chat.as_deref().is_some_and(|id| self.get(id).is_none()) {
+            self.active_chat = None;
+        }
+        if self.active_embedding.as_deref().is_some_and(|id| self.get(id).is_none()) {
+            self.active_embedding = None;
+        }
+        Ok(())
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn models(&self) -> &[ModelInfo] {
+        &self.models
+    }
+
+    pub fn get(&self, id: &str) -> Option<&ModelInfo> {
+        self.models.iter().find(|m| m.id == id)
+    }
+
+    /// The active model of `kind`, or the first one found if none was picked.
+    pub fn preferred(&self, kind: ModelKind) -> Result<&ModelInfo> {
+        let active = match kind {
+            ModelKind::Chat => self.active_chat.as_deref(),
+            ModelKind::Embedding => self.active_embedding.as_deref(),
+        };
+        active
+            .and_then(|id| self.get(id))
+            .or_else(|| self.models.iter().find(|m| m.kind == kind))
+            .ok_or(RegistryError::NoModel(kind))
+    }
+
+    /// Records `id` as the active model of its kind and returns it.
+    pub fn select(&mut self, id: &str) -> Result<ModelInfo> {
+        let model = self
+            .get(id)
+            .cloned()
+            .ok_or_else(|| RegistryError::UnknownModel(id.to_string()))?;
+        match model.kind {
+            ModelKind::Chat => self.active_chat = Some(model.id.clone()),
+            ModelKind::Embedding => self.active_embedding = Some(model.id.clone()),
+        }
+        Ok(model)
+    }
+
+    pub fn listing(&self) -> ModelListing {
+        ModelListing {
+            models: self.models.clone(),
+            active_chat: self.active_chat.clone(),
+            active_embedding: self.active_embedding.clone(),
+        }
+    }
+}
+
+// Recursive walk; files that aren't valid GGUF are skipped, not fatal.
+fn collect(dir: &Path, out: &mut Vec<ModelInfo>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect(&path, out)?;
+        } else if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("gguf")) {
+            if let Ok(info) = ModelInfo::inspect(&path) {
+                out.push(info);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::gguf::tests::Writer;
+    use super::*;
+
+    fn write_model(dir: &Path, file: &str, arch: &str, extra: Option<(&str, u32)>) {
+        let mut writer = Writer::new(if extra.is_some() { 3 } else { 2 })
+            .string("general.architecture", arch)
+            .u32(&format!("{}.embedding_length", arch), 384);
+        if let Some((key, value)) = extra {
+            writer = writer.u32(key, value);
+        }
+        std::fs::write(dir.join(file), writer.0).unwrap();
+    }
+
+    #[test]
+    fn test_scan_classifies_models() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("nested")).unwrap();
+        write_model(dir.path(), "qwen.gguf", "qwen2", Some(("qwen2.context_length", 32768)));
+        write_model(&dir.path().join("nested"), "bge.gguf", "bert", None);
+        std::fs::write(dir.path().join("broken.gguf"), b"not a model").unwrap();
+        std::fs::write(dir.path().join("notes.txt"), b"ignored").unwrap();
+
+        let registry = ModelRegistry::scan(dir.path()).unwrap();
+        let ids: Vec<_> = registry.models().iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, ["bge", "qwen"]);
+
+        let qwen = registry.get("qwen").unwrap();
+        assert_eq!(qwen.kind, ModelKind::Chat);
+        assert_eq!(qwen.context_length, Some(32768));
+        assert_eq!(registry.get("bge").unwrap().kind, ModelKind::Embedding);
+        assert_eq!(registry.get("bge").unwrap().embedding_dim, Some(384));
+    }
+
+    #[test]
+    fn test_select_and_rescan() {
+        let dir = tempfile::tempdir().unwrap();
+        write_model(dir.path(), "a.gguf", "llama", None);
+        write_model(dir.path(), "b.gguf", "llama", None);
+
+        let mut registry = ModelRegistry::scan(dir.path()).unwrap();
+        assert_eq!(registry.preferred(ModelKind::Chat).unwrap().id, "a");
+        assert!(registry.preferred(ModelKind::Embedding).is_err());
+
+        registry.select("b").unwrap();
+        assert_eq!(registry.preferred(ModelKind::Chat).unwrap().id, "b");
+        assert_eq!(registry.listing().active_chat.as_deref(), Some("b"));
+        assert!(matches!(registry.select("c"), Err(RegistryError::UnknownModel(_))));
+
+        std::fs::remove_file(dir.path().join("b.gguf")).unwrap();
+        registry.rescan().unwrap();
+        assert_eq!(registry.listing().active_chat, None);
+        assert_eq!(registry.preferred(ModelKind::Chat).unwrap().id, "a");
+    }
+}
@@ -10,6 +10,7 @@ const ChatContainer = ({
   isTyping = false,
   onBack,
   onNewChat,
+  onStop,
   onThemeChange,
   onLanguageChange
 }) => {
@@ -26,6 +27,7 @@ const ChatContainer = ({
   const PERSONALITY_NAMES = { 0: 'Erika', 1: 'Ekaterina', 2: 'Aurora', 3: 'Viktor' };
   const PERSONALITY_AVATARS = { 0: 'E', 1: 'K', 2: 'A', 3: 'V' };
   const name = PERSONALITY_NAMES[personality] || 'Assistant';
+  const generating = isTyping || messages.some((m) => m.streaming);
   const avatar = PERSONALITY_AVATARS[personality] || 'A';
 
   /* ----------  QUICK ACTIONS  ---------- */
@@ -296,8 +298,9 @@ const ChatContainer = ({
         )}
 
         {messages.map((m, i) => (
-          <div key={i} style={styles.bubble(m.role === 'user')}>
+          <div key={m.streamId || i} style={styles.bubble(m.role === 'user')}>
             {m.content}
+            {m.streaming && <span style={{ marginLeft: 2, opacity: .7, animation: 'pulse 1s infinite' }}>▍</span>}
             <div style={styles.time(m.role === 'user')}>{fmtTime(m.timestamp || Date.now())}</div>
           </div>
         ))}
@@ -335,13 +338,19 @@ const ChatContainer = ({
               e.target.style.height = Math.min(e.target.scrollHeight, 120) + 'px';
             }}
           />
-          <button
-            style={styles.sendBtn(inputValue.trim())}
-            onClick={send}
-            disabled={!inputValue.trim()}
-          >
-            ↑
-          </button>
+          {generating && onStop ? (
+            <button style={styles.sendBtn(true)} onClick={onStop} title={translator.t('stopGenerating')}>
+              ■
+            </button>
+          ) : (
+            <button
+              style={styles.sendBtn(inputValue.trim())}
+              onClick={send}
+              disabled={!inputValue.trim()}
+            >
+              ↑
+            </button>
+          )}
         </div>
       </footer>
     </div>
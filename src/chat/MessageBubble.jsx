@@ -25,6 +25,11 @@ const MessageBubble = ({ message, theme }) => {
         : '#fff',
       boxShadow: isUser ? '' : '0 2px 8px rgba(0,0,0,.1)'
     },
+    caret: {
+      marginLeft: 2,
+      opacity: 0.7,
+      animation: 'pulse 1s infinite'
+    },
     meta: {
       fontSize: 11,
       opacity: 0.6,
@@ -35,7 +40,10 @@ const MessageBubble = ({ message, theme }) => {
 
   return (
     <div style={styles.wrapper}>
-      <div style={styles.bubble}>{message.content}</div>
+      <div style={styles.bubble}>
+        {message.content}
+        {message.streaming && <span style={styles.caret}>▍</span>}
+      </div>
       <small style={styles.meta}>
         {new Date(message.timestamp || Date.now()).toLocaleTimeString([], {
           hour: '2-digit',
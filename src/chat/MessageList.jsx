@@ -13,7 +13,7 @@ const MessageList = ({ messages, theme }) => (
     }}
   >
     {messages.map((m, i) => (
-      <MessageBubble key={i} message={m} theme={theme} />
+      <MessageBubble key={m.streamId || i} message={m} theme={theme} />
     ))}
   </div>
 );
@@ -1,5 +1,6 @@
 import { useState, useEffect } from 'react';
 import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
 import { getCurrentWindow } from '@tauri-apps/api/window';
 import { locale } from '@tauri-apps/api/os';
 
@@ -52,8 +53,43 @@ const hook = {
     'receive_personality': async (personality) => {
         return await invoke('receive_personality', {personality});
     },
-    'send_output': async (output) => {
-        return await invoke('send_output', {output});
+    'send_output': async ({ input, mode, proficiency, personality, language }) => {
+        return await invoke('send_output', { input, mode, proficiency, personality, language });
+    },
+    'cancel_generation': async () => {
+        return await invoke('cancel_generation');
+    },
+    'reset_conversation': async () => {
+        return await invoke('reset_conversation');
+    },
+    // { models: [{ id, kind, architecture, context_length, quantization, ... }], active_chat, active_embedding }
+    'list_models': async () => {
+        return await invoke('list_models');
+    },
+    'select_model': async (id) => {
+        return await invoke('select_model', { id });
+    },
+    // { hits, disk_hits, misses, entries, disk_bytes }
+    'embedding_cache_stats': async () => {
+        return await invoke('embedding_cache_stats');
+    },
+    // { persisted, errors: [message] }; errors are cleared once read
+    'memory_status': async () => {
+        return await invoke('memory_status');
+    },
+    // { auto_adjust, domains: [{ domain, estimate: { level, observations, updated_at }, proficiency, suggested }] }
+    'learner_profile': async (proficiency) => {
+        return await invoke('learner_profile', { proficiency });
+    },
+    'set_proficiency_auto_adjust': async (enabled) => {
+        return await invoke('set_proficiency_auto_adjust', { enabled });
+    },
+    'reset_learner_profile': async () => {
+        return await invoke('reset_learner_profile');
+    },
+    // Partial assistant text: { stream_id, delta, done }. Resolves to an unlisten fn.
+    'on_token': async (handler) => {
+        return await listen('llm://token', (event) => handler(event.payload));
     }
 }
 
@@ -1,11 +1,14 @@
 import React, { useState, useEffect, useRef } from 'react';
 import ChatContainer from '../chat/ChatContainer';
 import translator from '../translation/main';
+import hook from '../hook';
 
 const Chat = ({
   theme = 0,
   personality = 0,
   personalityName = 'Assistant',
+  mode = 0,
+  proficiency = 0,
   onBack,
   onNewChat,
   onThemeChange,
@@ -15,6 +18,27 @@ const Chat = ({
   const [isTyping, setIsTyping] = useState(false);
   const abortRef = useRef(null);
 
+  /* Fold streamed deltas into the assistant bubble they belong to. */
+  useEffect(() => {
+    let unlisten;
+    hook.on_token(({ stream_id, delta, done }) => {
+      if (abortRef.current?.signal.aborted) return;
+      setIsTyping(false);
+      setMessages(prev => {
+        const idx = prev.findIndex(m => m.streamId === stream_id);
+        if (idx === -1) {
+          return [...prev, { role: 'assistant', content: delta, streamId: stream_id, streaming: !done, timestamp: Date.now() }];
+        }
+        const next = [...prev];
+        const msg = next[idx];
+        // The final event carries the post-processed reply, which replaces the raw deltas.
+        next[idx] = { ...msg, content: done ? delta : msg.content + delta, streaming: !done };
+        return next;
+      });
+    }).then(fn => { unlisten = fn; });
+    return () => unlisten?.();
+  }, []);
+
   const sendMessage = async (text) => {
     if (!text.trim()) return;
 
@@ -26,22 +50,15 @@ const Chat = ({
     abortRef.current = controller;
 
     try {
-      const res = await fetch('/api/chat', {
-        method: 'POST',
-        headers: { 'Content-Type': 'application/json' },
-        body: JSON.stringify({
-          text,
-          personality: personalityName,
-          language: translator.current()
-        }),
-        signal: controller.signal
+      await hook.send_output({
+        input: text,
+        mode,
+        proficiency,
+        personality,
+        language: translator.current()
       });
-
-      if (!res.ok) throw new Error('Network error');
-      const data = await res.json();
-      setMessages(prev => [...prev, { role: 'assistant', content: data.reply, timestamp: Date.now() }]);
     } catch (err) {
-      if (err.name !== 'AbortError') {
+      if (!controller.signal.aborted) {
         setMessages(prev => [...prev, { role: 'assistant', content: translator.t('errorMessage'), timestamp: Date.now() }]);
       }
     } finally {
@@ -50,7 +67,16 @@ const Chat = ({
     }
   };
 
-  useEffect(() => () => abortRef.current?.abort(), []);
+  /* Abort locally and tell the backend to stop decoding. */
+  const stopGeneration = () => {
+    if (!abortRef.current) return;
+    abortRef.current.abort();
+    hook.cancel_generation().catch(() => {});
+    // Late deltas are dropped once aborted, so settle the in-flight bubble here.
+    setMessages(prev => prev.map(m => (m.streaming ? { ...m, streaming: false } : m)));
+  };
+
+  useEffect(() => () => stopGeneration(), []);
 
   return (
     <ChatContainer
@@ -61,8 +87,10 @@ const Chat = ({
       isTyping={isTyping}
       onSendMessage={sendMessage}
       onBack={onBack}
+      onStop={stopGeneration}
       onNewChat={() => {
-        abortRef.current?.abort();
+        stopGeneration();
+        hook.reset_conversation().catch(() => {});
         setMessages([]);
         setIsTyping(false);
         onNewChat?.();
@@ -73,4 +101,4 @@ const Chat = ({
   );
 };
 
-export default Chat; 
\ No newline at end of file
+export default Chat;
@@ -13,6 +13,12 @@ export default {
   assistantModeDescription: "Schnelle Antworten, minimale Begleitung—davon ausgehend, dass du Bescheid weißt.",
   tutor: "Tutor",
   tutorModeDescription: "Schritt-für-Schritt-Erklärungen, sanfte Anstöße und kein Urteil.",
+  translatorMode: "Übersetzer",
+  translatorModeDescription: "Natürliche Übersetzungen mit Alternativen und Hinweisen zum Register.",
+  codeReviewMode: "Code-Reviewer",
+  codeReviewModeDescription: "Füge einen Ausschnitt ein und erhalte Befunde nach Schweregrad.",
+  quizMode: "Quiz",
+  quizModeDescription: "Fragen zu deinem Thema, bewertet, sobald du antwortest.",
   experienceLevel: "Erfahrungsgrad",
   proficiencyDescription: "Sei ehrlich; niemand wird es erfahren.",
   beginner: "Anfänger",
@@ -62,6 +68,7 @@ export default {
   lightMode: "Heller Modus",
   changeLanguage: "Sprache ändern",
   errorMessage: "Etwas ist schiefgelaufen.",
+  stopGenerating: "Generierung stoppen",
   helpMeBrainstorm: "Lass uns brainstormen",
   explainSomething: "Erkläre mir etwas",
   reviewMyWork: "Überprüfe meine Arbeit",
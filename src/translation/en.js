@@ -14,6 +14,12 @@ export default {
   assistantModeDescription: "Quick answers, minimal guidance—assume you know the ropes.",
   tutor: "Tutor",
   tutorModeDescription: "Step-by-step explanations, gentle nudges, and zero judgment.",
+  translatorMode: "Translator",
+  translatorModeDescription: "Natural translations with alternatives and notes on register.",
+  codeReviewMode: "Code Reviewer",
+  codeReviewModeDescription: "Paste a snippet and get findings ranked by severity.",
+  quizMode: "Quiz",
+  quizModeDescription: "Questions on your topic, graded when you answer.",
   experienceLevel: "Experience Level",
   proficiencyDescription: "Be honest; we won’t tell anyone.",
   beginner: "Beginner",
@@ -67,6 +73,7 @@ export default {
   lightMode: "Light mode",
   changeLanguage: "Change language",
   errorMessage: "Sorry, something went wrong.",
+  stopGenerating: "Stop generating",
   helpMeBrainstorm: "Help me brainstorm",
   explainSomething: "Explain something",
   reviewMyWork: "Review my work",
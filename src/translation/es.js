@@ -13,6 +13,12 @@ export default {
   assistantModeDescription: "Respuestas rápidas, orientación mínima—asume que sabes lo que haces.",
   tutor: "Tutor",
   tutorModeDescription: "Explicaciones paso a paso, empujones suaves y cero juicio.",
+  translatorMode: "Traductor",
+  translatorModeDescription: "Traducciones naturales con alternativas y notas sobre el registro.",
+  codeReviewMode: "Revisor de código",
+  codeReviewModeDescription: "Pega un fragmento y recibe observaciones ordenadas por gravedad.",
+  quizMode: "Cuestionario",
+  quizModeDescription: "Preguntas sobre tu tema, corregidas cuando respondes.",
   experienceLevel: "Nivel de Experiencia",
   proficiencyDescription: "Sé honesto; nadie se enterará.",
   beginner: "Principiante",
@@ -62,6 +68,7 @@ export default {
   lightMode: "Modo claro",
   changeLanguage: "Cambiar idioma",
   errorMessage: "Lo siento, algo salió mal.",
+  stopGenerating: "Detener generación",
   helpMeBrainstorm: "Ayúdame a idear",
   explainSomething: "Explícame algo",
   reviewMyWork: "Revisa mi trabajo",
@@ -13,6 +13,12 @@ export default {
   assistantModeDescription: "Réponses rapides, guidage minimal—on suppose que vous savez ce que vous faites.",
   tutor: "Tuteur",
   tutorModeDescription: "Explications pas à pas, petites poussées et zéro jugement.",
+  translatorMode: "Traducteur",
+  translatorModeDescription: "Des traductions naturelles, avec variantes et remarques sur le registre.",
+  codeReviewMode: "Relecteur de code",
+  codeReviewModeDescription: "Collez un extrait et obtenez des remarques classées par gravité.",
+  quizMode: "Quiz",
+  quizModeDescription: "Des questions sur votre sujet, corrigées dès que vous répondez.",
   experienceLevel: "Niveau d’expérience",
   proficiencyDescription: "Soyez honnête ; personne ne le saura.",
   beginner: "Débutant",
@@ -62,6 +68,7 @@ export default {
   lightMode: "Mode clair",
   changeLanguage: "Changer de langue",
   errorMessage: "Désolé, une erreur est survenue.",
+  stopGenerating: "Arrêter la génération",
   helpMeBrainstorm: "Aide-moi à brainstormer",
   explainSomething: "Explique-moi quelque chose",
   reviewMyWork: "Revois mon travail",
@@ -13,6 +13,12 @@ export default {
   assistantModeDescription: "Risposte rapide, minima guida—si assume che tu sappia quel che fai.",
   tutor: "Tutor",
   tutorModeDescription: "Spiegazioni passo-passo, spinte gentili e zero giudizi.",
+  translatorMode: "Traduttore",
+  translatorModeDescription: "Traduzioni naturali con alternative e note sul registro.",
+  codeReviewMode: "Revisore di codice",
+  codeReviewModeDescription: "Incolla un frammento e ricevi osservazioni ordinate per gravità.",
+  quizMode: "Quiz",
+  quizModeDescription: "Domande sul tuo argomento, valutate quando rispondi.",
   experienceLevel: "Livello di esperienza",
   proficiencyDescription: "Sii onesto; nessuno lo saprà.",
   beginner: "Principiante",
@@ -62,6 +68,7 @@ export default {
   lightMode: "Modalità chiara",
   changeLanguage: "Cambia lingua",
   errorMessage: "Ops, qualcosa è andato storto.",
+  stopGenerating: "Interrompi generazione",
   helpMeBrainstorm: "Aiutami a brainstormare",
   explainSomething: "Spiegami qualcosa",
   reviewMyWork: "Rivedi il mio lavoro",
@@ -13,6 +13,12 @@ export default {
   assistantModeDescription: "Respostas rápidas, orientação mínima—assumindo que você já sabe o básico.",
   tutor: "Tutor",
   tutorModeDescription: "Explicações passo a passo, incentivos suaves e zero julgamento.",
+  translatorMode: "Tradutor",
+  translatorModeDescription: "Traduções naturais com alternativas e notas sobre o registro.",
+  codeReviewMode: "Revisor de código",
+  codeReviewModeDescription: "Cole um trecho e receba apontamentos ordenados por gravidade.",
+  quizMode: "Quiz",
+  quizModeDescription: "Perguntas sobre o seu tema, corrigidas quando você responde.",
   experienceLevel: "Nível de Experiência",
   proficiencyDescription: "Seja honesto; ninguém vai saber.",
   beginner: "Iniciante",
@@ -62,6 +68,7 @@ export default {
   lightMode: "Modo claro",
   changeLanguage: "Mudar idioma",
   errorMessage: "Ops, algo deu errado.",
+  stopGenerating: "Parar geração",
   helpMeBrainstorm: "Ajuda-me a brainstormar",
   explainSomething: "Explica-me algo",
   reviewMyWork: "Revisa meu trabalho",
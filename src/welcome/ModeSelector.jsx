@@ -4,6 +4,9 @@ const ModeSelector = ({ value, setValue, translator }) => {
   const modes = [
     { label: "assistant", desc: "assistantModeDescription", val: 0 },
     { label: "tutor", desc: "tutorModeDescription", val: 1 },
+    { label: "translatorMode", desc: "translatorModeDescription", val: 2 },
+    { label: "codeReviewMode", desc: "codeReviewModeDescription", val: 3 },
+    { label: "quizMode", desc: "quizModeDescription", val: 4 },
   ];
 
   return (